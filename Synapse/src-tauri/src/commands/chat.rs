@@ -0,0 +1,747 @@
+//! Chat commands
+//!
+//! This module handles all chat-related commands including:
+//! - Chat session management
+//! - Sending messages through the active provider
+//! - Streaming chat completions to the main window
+
+use std::sync::Arc;
+
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use tauri::{Manager, State, Window};
+use log::{error, warn};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::services::ai::{
+    AIProvider, ChatCompletionDelta, ChatCompletionParams, ContentPart, Message, ProviderRegistry,
+    Role,
+};
+use crate::services::chat::{
+    attachments, context, Branch, ChatManager, ChatSession, ChatSessionSummary, ContextStrategy, ExportFormat,
+    ImportFormat, ImportReport, SearchQuery, SearchResult, SessionSettings, DEFAULT_SESSION_TITLE,
+};
+use crate::settings::{Settings, SettingsManager};
+use super::{CommandResult, CommandError};
+
+/// Name of the event carrying `ChatEvent`s from the chat manager
+pub const CHAT_EVENT: &str = "chat_event";
+
+/// Creates a configured provider and the parameters configured for it
+///
+/// # Errors
+/// Returns an error if the API key cannot be read, the provider cannot be
+/// created, or no model is configured or available
+async fn create_provider(
+    settings_manager: &SettingsManager,
+    settings: &Settings,
+    provider_id: &str
+) -> CommandResult<(Arc<dyn AIProvider>, ChatCompletionParams)> {
+    let api_key = settings_manager.find_api_key(provider_id).await?;
+    ProviderRegistry::global()
+        .create_configured(provider_id, api_key, &settings.ai_providers)
+        .await
+        .map_err(CommandError::from)
+}
+
+/// Gives the chat manager the providers and title model the settings configure
+///
+/// Providers created earlier are dropped and the ones chats used so far,
+/// along with the active provider, are created again, so new settings and
+/// API keys take effect. Providers that cannot be created are only logged;
+/// chats using them report the error when they are used.
+pub(crate) async fn apply_provider_settings(
+    chat_manager: &ChatManager,
+    settings_manager: &SettingsManager,
+    settings: &Settings
+) {
+    let active = settings.ai_providers.active_provider.clone();
+    let mut provider_ids = chat_manager.clear_providers().await;
+    provider_ids.extend(active.clone());
+    provider_ids.sort();
+    provider_ids.dedup();
+
+    for provider_id in provider_ids {
+        match create_provider(settings_manager, settings, &provider_id).await {
+            Ok((provider, params)) => {
+                chat_manager.add_configured_provider(provider_id, provider, params).await;
+            }
+            Err(e) => warn!("Provider {} is not available: {}", provider_id, e),
+        }
+    }
+    chat_manager.set_default_provider(active).await;
+    chat_manager.set_title_model(settings.ai_providers.title_model.clone()).await;
+}
+
+/// Gives the chat manager the provider a session uses and returns its parameters
+///
+/// The session's own provider and parameters take precedence over the
+/// active provider and its configuration. Providers are only created the
+/// first time they are used; see `apply_provider_settings`.
+///
+/// # Errors
+/// Returns an error if the session does not exist, no provider is selected,
+/// or the provider cannot be created
+async fn prepare_chat(
+    chat_manager: &ChatManager,
+    settings_manager: &SettingsManager,
+    session_id: &str
+) -> CommandResult<ChatCompletionParams> {
+    let session = chat_manager.get_session(session_id)
+        .await?
+        .ok_or_else(|| CommandError::NotFound("Chat session not found".to_string()))?;
+    let provider_id = match session.settings.provider_id.clone() {
+        Some(provider_id) => provider_id,
+        None => settings_manager.get_settings()
+            .await?
+            .ai_providers
+            .active_provider
+            .ok_or_else(|| CommandError::InvalidInput("No AI provider selected".to_string()))?,
+    };
+
+    let params = match chat_manager.configured_params(&provider_id).await {
+        Some(params) => params,
+        None => {
+            let settings = settings_manager.get_settings().await?;
+            let (provider, params) = create_provider(settings_manager, &settings, &provider_id).await?;
+            chat_manager.add_configured_provider(provider_id, provider, params.clone()).await;
+            params
+        }
+    };
+    Ok(session.settings.apply(params))
+}
+
+/// Forwards chat manager events to a window as `chat_event` events
+pub fn forward_chat_events(chat_manager: &ChatManager, window: Window) {
+    let mut events = chat_manager.subscribe();
+    tauri::async_runtime::spawn(async move {
+        loop {
+            match events.recv().await {
+                Ok(event) => {
+                    if let Err(e) = window.emit(CHAT_EVENT, &event) {
+                        error!("Failed to emit chat event: {}", e);
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    error!("Dropped {} chat events", skipped);
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+/// Creates a new chat session
+/// 
+/// # Arguments
+/// * `title` - The session title, defaulting to "New chat"
+#[tauri::command]
+pub async fn create_session(
+    title: Option<String>,
+    chat_manager: State<'_, ChatManager>
+) -> CommandResult<ChatSession> {
+    let title = title
+        .filter(|title| !title.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_SESSION_TITLE.to_string());
+
+    chat_manager
+        .create_session(title)
+        .await
+        .map_err(CommandError::from)
+}
+
+/// Renames a chat session
+/// 
+/// # Errors
+/// Returns an error if the title is empty or the session does not exist
+#[tauri::command]
+pub async fn rename_session(
+    session_id: String,
+    title: String,
+    chat_manager: State<'_, ChatManager>
+) -> CommandResult<()> {
+    let title = title.trim();
+    if title.is_empty() {
+        return Err(CommandError::InvalidInput("Title must not be empty".to_string()));
+    }
+
+    chat_manager
+        .rename_session(&session_id, title.to_string())
+        .await
+        .map_err(CommandError::from)
+}
+
+/// Lists all chat sessions without their messages
+#[tauri::command]
+pub async fn list_sessions(
+    chat_manager: State<'_, ChatManager>
+) -> CommandResult<Vec<ChatSessionSummary>> {
+    chat_manager
+        .list_sessions()
+        .await
+        .map_err(CommandError::from)
+}
+
+/// Retrieves a chat session with its messages
+/// 
+/// # Errors
+/// Returns an error if the session does not exist
+#[tauri::command]
+pub async fn get_session(
+    session_id: String,
+    chat_manager: State<'_, ChatManager>
+) -> CommandResult<ChatSession> {
+    chat_manager
+        .get_session(&session_id)
+        .await?
+        .ok_or_else(|| CommandError::NotFound(format!("Chat session not found: {}", session_id)))
+}
+
+/// Deletes a chat session
+/// 
+/// # Errors
+/// Returns an error if the session does not exist
+#[tauri::command]
+pub async fn delete_session(
+    session_id: String,
+    chat_manager: State<'_, ChatManager>
+) -> CommandResult<()> {
+    chat_manager
+        .delete_session(&session_id)
+        .await
+        .map_err(CommandError::from)
+}
+
+/// Exports a chat session as Markdown, JSON or HTML
+/// 
+/// # Arguments
+/// * `session_id` - The chat session to export
+/// * `format` - One of `markdown`, `json` or `html`
+/// * `path` - File to write the export to; if omitted the export is returned
+/// 
+/// # Returns
+/// The rendered export, or `None` if it was written to `path`
+/// 
+/// # Errors
+/// Returns an error if the session does not exist or the file cannot be written
+#[tauri::command]
+pub async fn export_session(
+    session_id: String,
+    format: ExportFormat,
+    path: Option<String>,
+    chat_manager: State<'_, ChatManager>
+) -> CommandResult<Option<String>> {
+    let content = chat_manager.export_session(&session_id, format).await?;
+
+    match path {
+        Some(path) => {
+            tokio::fs::write(&path, content)
+                .await
+                .map_err(|e| CommandError::Internal(format!("Failed to write {}: {}", path, e)))?;
+            Ok(None)
+        }
+        None => Ok(Some(content)),
+    }
+}
+
+/// Imports conversations from a Synapse, ChatGPT or Claude export file
+/// 
+/// # Arguments
+/// * `path` - The export file, e.g. `conversations.json`
+/// * `format` - One of `synapse`, `chatgpt` or `claude`; detected if omitted
+/// 
+/// # Returns
+/// How many sessions were imported, which were duplicates and what was skipped
+/// 
+/// # Errors
+/// Returns an error if the file cannot be read or its format is not recognized
+#[tauri::command]
+pub async fn import_sessions(
+    path: String,
+    format: Option<ImportFormat>,
+    chat_manager: State<'_, ChatManager>
+) -> CommandResult<ImportReport> {
+    let content = tokio::fs::read_to_string(&path)
+        .await
+        .map_err(|e| CommandError::InvalidInput(format!("Failed to read {}: {}", path, e)))?;
+
+    chat_manager
+        .import_sessions(&content, format)
+        .await
+        .map_err(CommandError::from)
+}
+
+/// Searches all chat sessions for messages and titles matching a query
+/// 
+/// # Arguments
+/// * `query` - The words to search for and optional date, role and
+///   provider/model filters
+/// 
+/// # Errors
+/// Returns an error if the query contains no words
+#[tauri::command]
+pub async fn search_chats(
+    query: SearchQuery,
+    chat_manager: State<'_, ChatManager>
+) -> CommandResult<Vec<SearchResult>> {
+    chat_manager
+        .search(&query)
+        .await
+        .map_err(CommandError::from)
+}
+
+/// Attaches a file to a chat session
+/// 
+/// The file is copied next to the session, so the returned part stays
+/// valid if the original is moved or deleted.
+/// 
+/// # Arguments
+/// * `session_id` - The chat session to attach the file to
+/// * `path` - The file to attach
+/// 
+/// # Returns
+/// The image or file part to pass to `send_message`
+/// 
+/// # Errors
+/// Returns an error if:
+/// - The session does not exist
+/// - The file cannot be read
+/// - The file is of an unsupported type or too large
+#[tauri::command]
+pub async fn add_attachment(
+    session_id: String,
+    path: String,
+    chat_manager: State<'_, ChatManager>
+) -> CommandResult<ContentPart> {
+    let size = tokio::fs::metadata(&path)
+        .await
+        .map_err(|e| CommandError::InvalidInput(format!("Failed to read {}: {}", path, e)))?
+        .len();
+    if size > attachments::MAX_FILE_SIZE as u64 {
+        return Err(CommandError::InvalidInput(format!("{} is too large to attach", path)));
+    }
+    let data = tokio::fs::read(&path)
+        .await
+        .map_err(|e| CommandError::InvalidInput(format!("Failed to read {}: {}", path, e)))?;
+
+    let name = std::path::Path::new(&path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    chat_manager
+        .add_attachment(&session_id, &name, &data)
+        .await
+        .map_err(CommandError::from)
+}
+
+/// Sends a message in a chat session and returns the assistant's reply
+/// 
+/// The reply is generated by the session's provider, or the active one,
+/// from the session's history, and both messages are added to the session.
+/// 
+/// # Arguments
+/// * `session_id` - The chat session to send the message in
+/// * `content` - The text of the user's message
+/// * `attachments` - Images and files to send along, as returned by
+///   `add_attachment` or with inline base64 data
+/// 
+/// # Errors
+/// Returns an error if:
+/// - The message has neither text nor attachments
+/// - No provider is active or it cannot be created
+/// - The session does not exist
+/// - An attachment is invalid
+/// - The provider request fails
+#[tauri::command]
+pub async fn send_message(
+    session_id: String,
+    content: String,
+    attachments: Option<Vec<ContentPart>>,
+    chat_manager: State<'_, ChatManager>,
+    settings_manager: State<'_, SettingsManager>
+) -> CommandResult<Message> {
+    let attachments = attachments.unwrap_or_default();
+    if !attachments.iter().all(|part| matches!(part, ContentPart::Image { .. } | ContentPart::File { .. })) {
+        return Err(CommandError::InvalidInput("Attachments must be images or files".to_string()));
+    }
+    if content.trim().is_empty() && attachments.is_empty() {
+        return Err(CommandError::InvalidInput("Message must not be empty".to_string()));
+    }
+
+    let params = prepare_chat(&chat_manager, &settings_manager, &session_id).await?;
+
+    let mut parts = Vec::new();
+    if !content.trim().is_empty() {
+        parts.push(ContentPart::text(content));
+    }
+    parts.extend(attachments);
+    chat_manager
+        .send_message(&session_id, parts, params)
+        .await
+        .map_err(CommandError::from)
+}
+
+/// Edits a message, keeping the original version on its own branch
+/// 
+/// Editing a user message generates a new reply with the active provider.
+/// 
+/// # Arguments
+/// * `session_id` - The chat session containing the message
+/// * `message_id` - The message to edit
+/// * `content` - The new text of the message
+/// 
+/// # Returns
+/// The new reply, or the edited message if it was not a user message
+/// 
+/// # Errors
+/// Returns an error if:
+/// - The session or message does not exist
+/// - A user message is edited and no provider is active or it cannot be
+///   created
+/// - The provider request fails
+#[tauri::command]
+pub async fn edit_message(
+    session_id: String,
+    message_id: String,
+    content: String,
+    chat_manager: State<'_, ChatManager>,
+    settings_manager: State<'_, SettingsManager>
+) -> CommandResult<Message> {
+    if content.trim().is_empty() {
+        return Err(CommandError::InvalidInput("Message must not be empty".to_string()));
+    }
+
+    // Only edits of user messages are answered, so only they need a provider
+    let session = chat_manager.get_session(&session_id)
+        .await?
+        .ok_or_else(|| CommandError::NotFound("Chat session not found".to_string()))?;
+    let is_user = session.message(&message_id)
+        .ok_or_else(|| CommandError::NotFound("Message not found".to_string()))?
+        .role == Role::User;
+    let params = if is_user {
+        Some(prepare_chat(&chat_manager, &settings_manager, &session_id).await?)
+    } else {
+        None
+    };
+
+    chat_manager
+        .edit_message(&session_id, &message_id, content, params)
+        .await
+        .map_err(CommandError::from)
+}
+
+/// Completion parameters that replace the active provider's configuration
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CompletionOverrides {
+    /// Model to generate with
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Temperature for response generation (0.0 to 1.0)
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    /// Maximum tokens to generate
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+}
+
+impl CompletionOverrides {
+    /// Checks the overrides with the same limits as session settings
+    ///
+    /// # Errors
+    /// Returns an error if the temperature is outside 0 to 1 or the token
+    /// limit is 0
+    fn validate(&self) -> CommandResult<()> {
+        SessionSettings {
+            temperature: self.temperature,
+            max_tokens: self.max_tokens,
+            ..SessionSettings::default()
+        }
+        .validate()
+        .map_err(CommandError::InvalidInput)
+    }
+
+    /// Applies the overrides to a set of parameters
+    fn apply(self, mut params: ChatCompletionParams) -> ChatCompletionParams {
+        if let Some(model) = self.model.filter(|model| !model.is_empty()) {
+            params.model = model;
+        }
+        if let Some(temperature) = self.temperature {
+            params.temperature = temperature;
+        }
+        if let Some(max_tokens) = self.max_tokens {
+            params.max_tokens = i32::try_from(max_tokens).unwrap_or(i32::MAX);
+        }
+        params
+    }
+}
+
+/// Generates a new version of an assistant message, keeping the old one
+/// 
+/// # Arguments
+/// * `session_id` - The chat session containing the message
+/// * `message_id` - The message to regenerate; defaults to the last message
+/// * `overrides` - Parameters to use instead of the provider's configuration
+/// 
+/// # Errors
+/// Returns an error if:
+/// - The session or message does not exist, or it is not an assistant message
+/// - The overrides are out of range
+/// - No provider is active or it cannot be created
+/// - The provider request fails
+#[tauri::command]
+pub async fn regenerate_message(
+    session_id: String,
+    message_id: Option<String>,
+    overrides: Option<CompletionOverrides>,
+    chat_manager: State<'_, ChatManager>,
+    settings_manager: State<'_, SettingsManager>
+) -> CommandResult<Message> {
+    let overrides = overrides.unwrap_or_default();
+    overrides.validate()?;
+    let params = prepare_chat(&chat_manager, &settings_manager, &session_id).await?;
+    let params = overrides.apply(params);
+
+    chat_manager
+        .regenerate(&session_id, message_id.as_deref(), params)
+        .await
+        .map_err(CommandError::from)
+}
+
+/// Continues an assistant message that was cut off by the token limit
+/// 
+/// # Arguments
+/// * `session_id` - The chat session containing the message
+/// * `message_id` - The message to continue; defaults to the last message
+/// * `overrides` - Parameters to use instead of the provider's configuration
+/// 
+/// # Errors
+/// Returns an error if:
+/// - The session or message does not exist, or it was not truncated
+/// - The overrides are out of range
+/// - No provider is active or it cannot be created
+/// - The provider request fails
+#[tauri::command]
+pub async fn continue_message(
+    session_id: String,
+    message_id: Option<String>,
+    overrides: Option<CompletionOverrides>,
+    chat_manager: State<'_, ChatManager>,
+    settings_manager: State<'_, SettingsManager>
+) -> CommandResult<Message> {
+    let overrides = overrides.unwrap_or_default();
+    overrides.validate()?;
+    let params = prepare_chat(&chat_manager, &settings_manager, &session_id).await?;
+    let params = overrides.apply(params);
+
+    chat_manager
+        .continue_message(&session_id, message_id.as_deref(), params)
+        .await
+        .map_err(CommandError::from)
+}
+
+/// Lists the alternative branches at a message
+/// 
+/// # Arguments
+/// * `session_id` - The chat session containing the message
+/// * `message_id` - The message whose siblings to list
+/// 
+/// # Errors
+/// Returns an error if the session or message does not exist
+#[tauri::command]
+pub async fn list_branches(
+    session_id: String,
+    message_id: String,
+    chat_manager: State<'_, ChatManager>
+) -> CommandResult<Vec<Branch>> {
+    chat_manager
+        .list_branches(&session_id, &message_id)
+        .await
+        .map_err(CommandError::from)
+}
+
+/// Switches a session to the branch containing a message
+/// 
+/// # Arguments
+/// * `session_id` - The chat session to switch
+/// * `message_id` - A message on the branch to continue from
+/// 
+/// # Errors
+/// Returns an error if the session or message does not exist
+#[tauri::command]
+pub async fn switch_branch(
+    session_id: String,
+    message_id: String,
+    chat_manager: State<'_, ChatManager>
+) -> CommandResult<ChatSession> {
+    chat_manager
+        .switch_branch(&session_id, &message_id)
+        .await
+        .map_err(CommandError::from)
+}
+
+/// Copies a branch of a session into a new session
+/// 
+/// # Arguments
+/// * `session_id` - The chat session to fork
+/// * `message_id` - The last message to copy; defaults to the active branch's last message
+/// 
+/// # Errors
+/// Returns an error if the session or message does not exist
+#[tauri::command]
+pub async fn fork_session(
+    session_id: String,
+    message_id: Option<String>,
+    chat_manager: State<'_, ChatManager>
+) -> CommandResult<ChatSession> {
+    chat_manager
+        .fork_session(&session_id, message_id.as_deref())
+        .await
+        .map_err(CommandError::from)
+}
+
+/// Sets how a session handles conversations that outgrow the model's context
+/// 
+/// # Arguments
+/// * `session_id` - The chat session to configure
+/// * `strategy` - `truncate` to drop older turns, `summarize` to replace them with a running summary
+/// 
+/// # Errors
+/// Returns an error if the session does not exist
+#[tauri::command]
+pub async fn set_context_strategy(
+    session_id: String,
+    strategy: ContextStrategy,
+    chat_manager: State<'_, ChatManager>
+) -> CommandResult<()> {
+    chat_manager
+        .set_context_strategy(&session_id, strategy)
+        .await
+        .map_err(CommandError::from)
+}
+
+/// Chooses the provider and parameters a session generates with
+/// 
+/// Unset fields fall back to the active provider and its configuration. The
+/// model may be changed at any point in a conversation; each reply records
+/// the model that wrote it.
+/// 
+/// # Arguments
+/// * `session_id` - The chat session to configure
+/// * `settings` - Provider id, model, temperature, max tokens and system prompt
+/// 
+/// # Errors
+/// Returns an error if the session does not exist, the provider is not
+/// configured, or a parameter is out of range
+#[tauri::command]
+pub async fn update_session_settings(
+    session_id: String,
+    settings: SessionSettings,
+    chat_manager: State<'_, ChatManager>,
+    settings_manager: State<'_, SettingsManager>
+) -> CommandResult<ChatSession> {
+    let settings = settings.normalized();
+    if let Some(provider_id) = &settings.provider_id {
+        let app_settings = settings_manager.get_settings().await?;
+        ProviderRegistry::global().resolve(provider_id, &app_settings.ai_providers)?;
+    }
+
+    chat_manager
+        .update_session_settings(&session_id, settings)
+        .await
+        .map_err(CommandError::from)
+}
+
+/// Name of the event carrying streamed completion updates
+pub const CHAT_STREAM_EVENT: &str = "chat_stream";
+
+/// Payload of a `chat_stream` event
+///
+/// Every event carries the session and request it belongs to, so the
+/// frontend can route concurrent streams to the right conversation.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChatStreamEvent {
+    /// The chat session the completion belongs to
+    pub session_id: String,
+    /// Identifier chosen by the frontend for this request
+    pub request_id: String,
+    /// The update itself
+    #[serde(flatten)]
+    pub payload: ChatStreamPayload,
+}
+
+/// Kinds of updates sent while streaming a completion
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ChatStreamPayload {
+    /// An incremental update from the provider
+    Delta { delta: ChatCompletionDelta },
+    /// The stream failed and no further updates will follow
+    Error { message: String },
+    /// The stream completed successfully
+    Done,
+}
+
+/// Streams a chat completion to the main window
+///
+/// Deltas are emitted as `chat_stream` events as they arrive. The command
+/// resolves once the stream has finished. Older messages that do not fit
+/// the model's context are dropped.
+///
+/// # Arguments
+/// * `session_id` - The chat session the completion belongs to
+/// * `request_id` - Identifier used to correlate the emitted events
+/// * `provider` - The name of the AI provider to use
+/// * `messages` - The conversation history to complete
+/// * `params` - The completion parameters
+///
+/// # Errors
+/// Returns an error if:
+/// - The main window cannot be found
+/// - The provider cannot be created
+/// - The provider request fails
+#[tauri::command]
+pub async fn stream_chat_completion(
+    window: Window,
+    session_id: String,
+    request_id: String,
+    provider: String,
+    messages: Vec<Message>,
+    params: ChatCompletionParams,
+    settings_manager: State<'_, SettingsManager>
+) -> CommandResult<()> {
+    let main_window = window.app_handle()
+        .get_window("main")
+        .ok_or_else(|| CommandError::Window("Main window not found".to_string()))?;
+
+    let emit = |payload: ChatStreamPayload| {
+        let event = ChatStreamEvent {
+            session_id: session_id.clone(),
+            request_id: request_id.clone(),
+            payload,
+        };
+        if let Err(e) = main_window.emit(CHAT_STREAM_EVENT, event) {
+            error!("Failed to emit chat stream event: {}", e);
+        }
+    };
+
+    let result = async {
+        let settings = settings_manager.get_settings().await?;
+        let api_key = settings_manager.find_api_key(&provider).await?;
+        let provider = ProviderRegistry::global()
+            .create(&provider, api_key, &settings.ai_providers)
+            .await?;
+        let messages = context::fit(&messages, context::budget(&params)).into_messages(None);
+        let mut stream = provider.create_chat_completion_stream(messages, params).await?;
+
+        while let Some(delta) = stream.next().await {
+            emit(ChatStreamPayload::Delta { delta: delta? });
+        }
+        Ok::<(), CommandError>(())
+    }.await;
+
+    match &result {
+        Ok(()) => emit(ChatStreamPayload::Done),
+        Err(e) => emit(ChatStreamPayload::Error { message: e.to_string() }),
+    }
+    result
+}
@@ -0,0 +1,181 @@
+//! Document index commands
+//!
+//! This module handles commands for the index of the user's documents including:
+//! - Indexing and watching the configured folders
+//! - Forwarding indexing progress to the main window
+//! - Searching the indexed documents
+//! - Reporting the state of the index
+
+use log::{error, warn};
+use tauri::{State, Window};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::services::ai::ProviderRegistry;
+use crate::services::documents::{DocumentWatcher, Embedder, IndexReport, IndexStatus, Passage, Retriever};
+use crate::services::{ChatManager, DocumentIndex};
+use crate::settings::{Settings, SettingsManager};
+use super::{CommandResult, CommandError};
+
+/// Name of the event carrying `IndexEvent`s of indexing runs
+pub const DOCUMENT_INDEX_EVENT: &str = "document_index";
+
+/// Creates the embedder configured for the document index
+///
+/// # Errors
+/// Returns an error if no provider is selected, the provider cannot be
+/// created, or no embedding model is configured or known for it
+pub(crate) async fn create_embedder(
+    settings_manager: &SettingsManager,
+    settings: &Settings
+) -> CommandResult<Embedder> {
+    let provider_id = settings.documents.provider_id
+        .clone()
+        .or_else(|| settings.ai_providers.active_provider.clone())
+        .ok_or_else(|| CommandError::InvalidInput("No AI provider selected".to_string()))?;
+    let registry = ProviderRegistry::global();
+    let (descriptor, _) = registry.resolve(&provider_id, &settings.ai_providers)?;
+    let model = settings.documents.embedding_model
+        .clone()
+        .filter(|model| !model.trim().is_empty())
+        .or(descriptor.default_embedding_model)
+        .ok_or_else(|| CommandError::InvalidInput(format!(
+            "Choose an embedding model for {}", descriptor.display_name
+        )))?;
+
+    let api_key = settings_manager.find_api_key(&provider_id).await?;
+    let provider = registry.create(&provider_id, api_key, &settings.ai_providers).await?;
+    Ok(Embedder::new(provider_id, provider, model))
+}
+
+/// Applies the document settings to the watcher and to chats
+///
+/// One embedder serves both: the watcher indexes the folders with it and
+/// chats look up passages with it. While documents are disabled or no
+/// embedding provider can be created, the folders are not watched and
+/// chats answer without them; the latter is only logged, as the settings
+/// may be completed later.
+pub(crate) async fn apply_document_settings(
+    document_watcher: &DocumentWatcher,
+    chat_manager: &ChatManager,
+    settings_manager: &SettingsManager,
+    settings: &Settings
+) {
+    let documents = &settings.documents;
+    let embedder = if documents.enabled && !documents.folders.is_empty() {
+        match create_embedder(settings_manager, settings).await {
+            Ok(embedder) => Some(embedder),
+            Err(e) => {
+                warn!("Documents are not used: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let retriever = embedder
+        .clone()
+        .map(|embedder| Retriever::new(document_watcher.index().clone(), embedder, documents.top_k));
+    chat_manager.set_retriever(retriever).await;
+    document_watcher.configure(documents.folders.clone(), embedder);
+}
+
+/// Forwards the progress of indexing runs to a window as `document_index` events
+pub fn forward_index_events(document_watcher: &DocumentWatcher, window: Window) {
+    let mut events = document_watcher.subscribe();
+    tauri::async_runtime::spawn(async move {
+        loop {
+            match events.recv().await {
+                Ok(event) => {
+                    if let Err(e) = window.emit(DOCUMENT_INDEX_EVENT, &event) {
+                        error!("Failed to emit document index event: {}", e);
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Dropped {} document index events", skipped);
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+/// Indexes the configured folders
+///
+/// Only files whose modification time or size changed are read, and only
+/// their changed chunks are embedded. Files that were deleted and folders
+/// no longer configured are removed from the index. Progress is reported
+/// through `document_index` events.
+///
+/// # Returns
+/// How many files and chunks were indexed, kept or removed, and which files
+/// were skipped
+///
+/// # Errors
+/// Returns an error if no embedding provider is available or embedding fails
+#[tauri::command]
+pub async fn index_documents(
+    chat_manager: State<'_, ChatManager>,
+    settings_manager: State<'_, SettingsManager>,
+    document_watcher: State<'_, DocumentWatcher>
+) -> CommandResult<IndexReport> {
+    let settings = settings_manager.get_settings().await?;
+    // Picks up API keys stored since the folders were last configured
+    let embedder = create_embedder(&settings_manager, &settings).await?;
+    if settings.documents.enabled {
+        let index = document_watcher.index().clone();
+        let retriever = Retriever::new(index, embedder.clone(), settings.documents.top_k);
+        chat_manager.set_retriever(Some(retriever)).await;
+    }
+    document_watcher.configure(settings.documents.folders.clone(), Some(embedder));
+
+    document_watcher
+        .sync()
+        .await
+        .map_err(CommandError::from)
+}
+
+/// Searches the indexed documents for passages related to a question
+///
+/// # Arguments
+/// * `query` - The question or keywords
+/// * `limit` - Maximum number of passages, defaulting to the configured number
+///
+/// # Errors
+/// Returns an error if the query is empty or cannot be embedded
+#[tauri::command]
+pub async fn search_documents(
+    query: String,
+    limit: Option<usize>,
+    settings_manager: State<'_, SettingsManager>,
+    document_index: State<'_, DocumentIndex>
+) -> CommandResult<Vec<Passage>> {
+    if query.trim().is_empty() {
+        return Err(CommandError::InvalidInput("Query must not be empty".to_string()));
+    }
+    let settings = settings_manager.get_settings().await?;
+    let embedder = create_embedder(&settings_manager, &settings).await?;
+    let limit = limit.unwrap_or(settings.documents.top_k);
+
+    document_index
+        .search(&embedder, &query, limit)
+        .await
+        .map_err(CommandError::from)
+}
+
+/// Reports the state of the document index
+///
+/// # Returns
+/// The indexed folders, whether they are watched, the progress of a run in
+/// progress, the outcome of the last run and the size of the index
+///
+/// # Errors
+/// Returns an error if the index cannot be read
+#[tauri::command]
+pub async fn document_index_status(
+    document_watcher: State<'_, DocumentWatcher>
+) -> CommandResult<IndexStatus> {
+    document_watcher
+        .status()
+        .await
+        .map_err(CommandError::from)
+}
@@ -0,0 +1,40 @@
+//! MCP commands
+//!
+//! This module handles commands for the configured MCP servers including:
+//! - Reporting the status of every server
+//! - Restarting a server
+
+use tauri::State;
+
+use crate::services::mcp::McpServerStatus;
+use crate::services::McpManager;
+use super::{CommandResult, CommandError};
+
+/// Lists the configured MCP servers with their status
+///
+/// # Returns
+/// Each server's state, last error, restart count and the tools,
+/// resources and prompts it offers
+#[tauri::command]
+pub async fn list_mcp_servers(
+    mcp_manager: State<'_, McpManager>
+) -> CommandResult<Vec<McpServerStatus>> {
+    Ok(mcp_manager.statuses())
+}
+
+/// Restarts an MCP server, for example after it failed too often
+///
+/// # Arguments
+/// * `server_id` - Id of the server in the settings
+///
+/// # Errors
+/// Returns an error if no server with this id is configured
+#[tauri::command]
+pub async fn restart_mcp_server(
+    server_id: String,
+    mcp_manager: State<'_, McpManager>
+) -> CommandResult<()> {
+    mcp_manager
+        .restart(&server_id)
+        .map_err(CommandError::from)
+}
@@ -8,6 +8,10 @@ use thiserror::Error;
 
 pub mod window;
 pub mod settings;
+pub mod chat;
+pub mod documents;
+pub mod mcp;
+pub mod tools;
 
 // Re-export all commands with their Tauri command attributes
 pub use window::{
@@ -22,6 +26,45 @@ pub use settings::{
     store_api_key,
     get_api_key,
     delete_api_key,
+    list_providers,
+};
+
+pub use chat::{
+    create_session,
+    list_sessions,
+    get_session,
+    delete_session,
+    rename_session,
+    export_session,
+    import_sessions,
+    search_chats,
+    add_attachment,
+    send_message,
+    edit_message,
+    regenerate_message,
+    continue_message,
+    list_branches,
+    switch_branch,
+    fork_session,
+    set_context_strategy,
+    update_session_settings,
+    stream_chat_completion,
+};
+
+pub use documents::{
+    index_documents,
+    search_documents,
+    document_index_status,
+};
+
+pub use mcp::{
+    list_mcp_servers,
+    restart_mcp_server,
+};
+
+pub use tools::{
+    list_tools,
+    respond_to_tool_approval,
 };
 
 /// Error type for command handlers
@@ -38,6 +81,12 @@ pub enum CommandError {
     
     #[error("Invalid input: {0}")]
     InvalidInput(String),
+
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    #[error("Provider request failed: {0}")]
+    Provider(String),
 }
 
 /// Result type alias for command handlers
@@ -48,6 +97,10 @@ impl From<crate::utils::AppError> for CommandError {
         match error {
             crate::utils::AppError::InvalidInput(msg) => CommandError::InvalidInput(msg),
             crate::utils::AppError::Internal(msg) => CommandError::Internal(msg),
+            crate::utils::AppError::NotFound(msg) => CommandError::NotFound(msg),
+            crate::utils::AppError::Api(msg) | crate::utils::AppError::Network(msg) => {
+                CommandError::Provider(msg)
+            }
             _ => CommandError::Internal(error.to_string()),
         }
     }
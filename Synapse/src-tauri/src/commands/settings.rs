@@ -3,9 +3,13 @@
 //! This module handles all settings-related commands including:
 //! - Settings retrieval and updates
 //! - API key management
+//! - Provider discovery
 //! - Settings validation
 
 use tauri::State;
+use crate::services::ai::{ApiKeyPolicy, ProviderDescriptor, ProviderRegistry};
+use crate::services::documents::DocumentWatcher;
+use crate::services::{tools, ChatManager, McpManager};
 use crate::settings::{Settings, SettingsManager, Validate};
 use super::{CommandResult, CommandError};
 
@@ -25,6 +29,13 @@ pub async fn get_settings(
 
 /// Updates the application settings
 /// 
+/// The built-in tools take on the new permissions, MCP servers are
+/// started, stopped or restarted to match the new settings, providers are
+/// created again with their new configuration, and the document folders
+/// are watched and indexed again. Chats look up passages of the
+/// documents as configured from then on. Tool calls always allowed from
+/// chats are kept, even if `settings` were read before they were allowed.
+/// 
 /// # Arguments
 /// * `settings` - The new settings to apply
 /// 
@@ -34,23 +45,69 @@ pub async fn get_settings(
 /// - Settings cannot be updated
 #[tauri::command]
 pub async fn update_settings(
-    settings: Settings,
-    settings_manager: State<'_, SettingsManager>
+    mut settings: Settings,
+    settings_manager: State<'_, SettingsManager>,
+    chat_manager: State<'_, ChatManager>,
+    mcp_manager: State<'_, McpManager>,
+    document_watcher: State<'_, DocumentWatcher>
 ) -> CommandResult<()> {
     // Validate settings before updating
     settings.validate()
         .map_err(|e| CommandError::InvalidInput(e))?;
 
+    // Tool calls may have been always allowed since the settings were read
+    let stored = settings_manager.get_settings().await?;
+    for key in stored.tools.always_allow {
+        if !settings.tools.always_allow.contains(&key) {
+            settings.tools.always_allow.push(key);
+        }
+    }
+
+    let mcp_servers = settings.mcp_servers.clone();
     settings_manager
-        .update_settings(settings)
-        .await
-        .map_err(CommandError::from)
+        .update_settings(settings.clone())
+        .await?;
+    tools::register_builtin(chat_manager.tools(), &settings.tools);
+    mcp_manager.sync(&mcp_servers);
+    super::chat::apply_provider_settings(&chat_manager, &settings_manager, &settings).await;
+    super::documents::apply_document_settings(&document_watcher, &chat_manager, &settings_manager, &settings).await;
+    document_watcher.sync_in_background();
+    Ok(())
+}
+
+/// Checks that a provider exists and accepts an API key
+/// 
+/// Providers are resolved through the provider registry, so any registered
+/// kind or configured provider instance is accepted.
+async fn validate_provider(provider: &str, settings_manager: &SettingsManager) -> CommandResult<()> {
+    let settings = settings_manager.get_settings().await?;
+    let (descriptor, _) = ProviderRegistry::global()
+        .resolve(provider, &settings.ai_providers)
+        .map_err(|_| CommandError::InvalidInput(format!("Invalid provider: {}", provider)))?;
+
+    if descriptor.api_key == ApiKeyPolicy::Unsupported {
+        return Err(CommandError::InvalidInput(format!("{} does not use an API key", descriptor.display_name)));
+    }
+    Ok(())
+}
+
+/// Lists the provider kinds known to the registry
+/// 
+/// Each entry describes the provider's display name, API key policy and
+/// the provider-specific options it accepts, so the settings UI can render
+/// a form without hardcoding providers.
+#[tauri::command]
+pub async fn list_providers() -> CommandResult<Vec<ProviderDescriptor>> {
+    Ok(ProviderRegistry::global().descriptors())
 }
 
 /// Stores an API key for a specific provider
 /// 
+/// Providers are created again with the new key, and the documents are
+/// indexed and used again, in case they were waiting for it.
+/// 
 /// # Arguments
-/// * `provider` - The name of the AI provider (e.g., "openai", "anthropic")
+/// * `provider` - The id of the AI provider (e.g., "openai", "anthropic")
 /// * `key` - The API key to store
 /// 
 /// # Errors
@@ -61,17 +118,20 @@ pub async fn update_settings(
 pub async fn store_api_key(
     provider: String,
     key: String,
-    settings_manager: State<'_, SettingsManager>
+    settings_manager: State<'_, SettingsManager>,
+    chat_manager: State<'_, ChatManager>,
+    document_watcher: State<'_, DocumentWatcher>
 ) -> CommandResult<()> {
-    // Validate provider name
-    if !["openai", "anthropic"].contains(&provider.as_str()) {
-        return Err(CommandError::InvalidInput(format!("Invalid provider: {}", provider)));
-    }
+    validate_provider(&provider, &settings_manager).await?;
 
     settings_manager
         .store_api_key(&provider, &key)
-        .await
-        .map_err(CommandError::from)
+        .await?;
+    let settings = settings_manager.get_settings().await?;
+    super::chat::apply_provider_settings(&chat_manager, &settings_manager, &settings).await;
+    super::documents::apply_document_settings(&document_watcher, &chat_manager, &settings_manager, &settings).await;
+    document_watcher.sync_in_background();
+    Ok(())
 }
 
 /// Retrieves an API key for a specific provider
@@ -88,10 +148,7 @@ pub async fn get_api_key(
     provider: String,
     settings_manager: State<'_, SettingsManager>
 ) -> CommandResult<String> {
-    // Validate provider name
-    if !["openai", "anthropic"].contains(&provider.as_str()) {
-        return Err(CommandError::InvalidInput(format!("Invalid provider: {}", provider)));
-    }
+    validate_provider(&provider, &settings_manager).await?;
 
     settings_manager
         .get_api_key(&provider)
@@ -101,6 +158,8 @@ pub async fn get_api_key(
 
 /// Deletes an API key for a specific provider
 /// 
+/// Providers are created again without the key.
+/// 
 /// # Arguments
 /// * `provider` - The name of the AI provider
 /// 
@@ -111,15 +170,15 @@ pub async fn get_api_key(
 #[tauri::command]
 pub async fn delete_api_key(
     provider: String,
-    settings_manager: State<'_, SettingsManager>
+    settings_manager: State<'_, SettingsManager>,
+    chat_manager: State<'_, ChatManager>
 ) -> CommandResult<()> {
-    // Validate provider name
-    if !["openai", "anthropic"].contains(&provider.as_str()) {
-        return Err(CommandError::InvalidInput(format!("Invalid provider: {}", provider)));
-    }
+    validate_provider(&provider, &settings_manager).await?;
 
     settings_manager
         .delete_api_key(&provider)
-        .await
-        .map_err(CommandError::from)
+        .await?;
+    let settings = settings_manager.get_settings().await?;
+    super::chat::apply_provider_settings(&chat_manager, &settings_manager, &settings).await;
+    Ok(())
 } 
\ No newline at end of file
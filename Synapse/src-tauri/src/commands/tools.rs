@@ -0,0 +1,84 @@
+//! Tool commands
+//!
+//! This module handles tool-related commands including:
+//! - Listing the tools models can call
+//! - Forwarding approval requests of tool calls to the main window
+//! - Answering approval requests and remembering "always allow" decisions
+
+use log::error;
+use tauri::{State, Window};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::services::ai::{ApprovalDecision, ToolDefinition};
+use crate::services::ChatManager;
+use crate::settings::SettingsManager;
+use super::{CommandResult, CommandError};
+
+/// Name of the event carrying `ApprovalRequest`s of tool calls
+pub const TOOL_APPROVAL_EVENT: &str = "tool_approval";
+
+/// Forwards approval requests of tool calls to a window as `tool_approval` events
+///
+/// The window answers them through `respond_to_tool_approval`.
+pub fn forward_tool_approvals(chat_manager: &ChatManager, window: Window) {
+    let mut requests = chat_manager.tools().permissions().subscribe();
+    tauri::async_runtime::spawn(async move {
+        loop {
+            match requests.recv().await {
+                Ok(request) => {
+                    if let Err(e) = window.emit(TOOL_APPROVAL_EVENT, &request) {
+                        error!("Failed to emit tool approval request: {}", e);
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    error!("Dropped {} tool approval requests", skipped);
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+/// Lists the tools models can call
+#[tauri::command]
+pub async fn list_tools(
+    chat_manager: State<'_, ChatManager>
+) -> CommandResult<Vec<ToolDefinition>> {
+    Ok(chat_manager.tools().definitions())
+}
+
+/// Answers the approval request of a tool call
+///
+/// When the action is always allowed, the decision is saved to the
+/// settings so it applies to later sessions as well.
+///
+/// # Arguments
+/// * `request_id` - Id of the `tool_approval` event being answered
+/// * `decision` - `deny`, `allow` or `always_allow`
+///
+/// # Errors
+/// Returns an error if:
+/// - No request with this id is waiting
+/// - The decision cannot be saved
+#[tauri::command]
+pub async fn respond_to_tool_approval(
+    request_id: String,
+    decision: ApprovalDecision,
+    chat_manager: State<'_, ChatManager>,
+    settings_manager: State<'_, SettingsManager>
+) -> CommandResult<()> {
+    let request = chat_manager
+        .tools()
+        .permissions()
+        .respond(&request_id, decision)
+        .map_err(CommandError::from)?;
+
+    if decision == ApprovalDecision::AlwaysAllow {
+        let mut settings = settings_manager.get_settings().await?;
+        if !settings.tools.always_allow.contains(&request.key) {
+            settings.tools.always_allow.push(request.key);
+            settings_manager.update_settings(settings).await?;
+        }
+    }
+    Ok(())
+}
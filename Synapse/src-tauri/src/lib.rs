@@ -4,9 +4,20 @@
 //! It provides the core functionality that can be shared between different
 //! entry points (desktop, mobile, etc.).
 
+use std::sync::Arc;
+
 use tauri::Builder;
 use commands::window::{get_window_position, set_window_position, open_settings_window};
-use commands::settings::{get_settings, update_settings, store_api_key, get_api_key, delete_api_key};
+use commands::settings::{get_settings, update_settings, store_api_key, get_api_key, delete_api_key, list_providers};
+use commands::chat::{
+    create_session, list_sessions, get_session, delete_session, rename_session, export_session,
+    import_sessions, search_chats, add_attachment, send_message, edit_message, regenerate_message, continue_message,
+    list_branches, switch_branch, fork_session, set_context_strategy, update_session_settings,
+    stream_chat_completion,
+};
+use commands::documents::{index_documents, search_documents, document_index_status};
+use commands::mcp::{list_mcp_servers, restart_mcp_server};
+use commands::tools::{list_tools, respond_to_tool_approval};
 
 pub mod commands;
 pub mod settings;
@@ -19,14 +30,51 @@ mod mobile;
 #[cfg(mobile)]
 pub use mobile::*;
 
+/// Runs Synapse as an MCP server instead of the desktop app
+///
+/// # Errors
+/// Returns an error if the settings or chat history cannot be opened, or
+/// the transport fails
+pub async fn run_mcp_server(transport: services::mcp::ServerTransport) -> utils::AppResult<()> {
+    let settings_manager = settings::SettingsManager::new()
+        .await
+        .map_err(|e| utils::AppError::internal(e.to_string()))?;
+    let chat_store = services::chat::ChatStore::open(&settings_manager.config_dir().join("chats.db"))?;
+    let chat_manager = Arc::new(services::ChatManager::new(chat_store));
+
+    let server = services::McpServer::new(chat_manager, Arc::new(settings_manager));
+    Arc::new(server).listen(transport).await
+}
+
 /// Initialize the core application builder with common configuration
 pub async fn create_app() -> Builder<tauri::Wry> {
     let settings_manager = settings::SettingsManager::new()
         .await
         .expect("Failed to initialize settings manager");
+    let chat_store = services::chat::ChatStore::open(&settings_manager.config_dir().join("chats.db"))
+        .expect("Failed to open chat history");
+    let settings = settings_manager.get_settings()
+        .await
+        .expect("Failed to read settings");
+    let document_store = services::documents::DocumentStore::open(&settings_manager.config_dir().join("documents.db"))
+        .expect("Failed to open document index");
+    let chat_manager = services::ChatManager::new(chat_store);
+    services::tools::register_builtin(chat_manager.tools(), &settings.tools);
+    let mcp_manager = services::McpManager::new(chat_manager.tools().clone());
+    mcp_manager.sync(&settings.mcp_servers);
+    let document_index = services::DocumentIndex::new(document_store);
+    let document_watcher = services::DocumentWatcher::new(document_index.clone());
+    commands::chat::apply_provider_settings(&chat_manager, &settings_manager, &settings).await;
+    commands::documents::apply_document_settings(&document_watcher, &chat_manager, &settings_manager, &settings)
+        .await;
+    document_watcher.sync_in_background();
 
     Builder::default()
         .manage(settings_manager)
+        .manage(chat_manager)
+        .manage(mcp_manager)
+        .manage(document_index)
+        .manage(document_watcher)
         .invoke_handler(tauri::generate_handler![
             // Window commands
             get_window_position,
@@ -39,5 +87,40 @@ pub async fn create_app() -> Builder<tauri::Wry> {
             store_api_key,
             get_api_key,
             delete_api_key,
+            list_providers,
+
+            // Chat commands
+            create_session,
+            list_sessions,
+            get_session,
+            delete_session,
+            rename_session,
+            export_session,
+            import_sessions,
+            search_chats,
+            add_attachment,
+            send_message,
+            edit_message,
+            regenerate_message,
+            continue_message,
+            list_branches,
+            switch_branch,
+            fork_session,
+            set_context_strategy,
+            update_session_settings,
+            stream_chat_completion,
+
+            // Tool commands
+            list_tools,
+            respond_to_tool_approval,
+
+            // Document commands
+            index_documents,
+            search_documents,
+            document_index_status,
+
+            // MCP commands
+            list_mcp_servers,
+            restart_mcp_server,
         ])
 }
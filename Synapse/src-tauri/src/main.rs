@@ -162,6 +162,24 @@ fn setup_window_events(window: &Window) -> WindowResult<()> {
 async fn main() {
     // Initialize logging
     env_logger::init();
+
+    // `--mcp` and `--mcp-socket` run a headless MCP server instead of the app
+    match synapse_lib::services::mcp::ServerTransport::from_args(std::env::args().skip(1)) {
+        Ok(Some(transport)) => {
+            info!("Starting Synapse as an MCP server");
+            if let Err(e) = synapse_lib::run_mcp_server(transport).await {
+                error!("MCP server failed: {}", e);
+                std::process::exit(1);
+            }
+            return;
+        }
+        Ok(None) => {}
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(2);
+        }
+    }
+
     info!("Initializing Synapse application");
 
     let app = synapse_lib::create_app()
@@ -176,6 +194,17 @@ async fn main() {
             window_management::setup_window(&window)?;
             setup_window_events(&window)?;
             setup_global_shortcut(app)?;
+
+            // Forward background chat updates, such as generated titles
+            let chat_manager = app.state::<synapse_lib::services::ChatManager>();
+            synapse_lib::commands::chat::forward_chat_events(&chat_manager, window.clone());
+
+            // Ask the user before tools access anything outside the approved folders
+            synapse_lib::commands::tools::forward_tool_approvals(&chat_manager, window.clone());
+
+            // Report the progress of indexing the user's documents
+            let document_watcher = app.state::<synapse_lib::services::DocumentWatcher>();
+            synapse_lib::commands::documents::forward_index_events(&document_watcher, window.clone());
             
             #[cfg(any(windows, target_os = "macos"))]
             set_shadow(&window, true).expect("Failed to set window shadow");
@@ -0,0 +1,722 @@
+//! Anthropic provider
+//!
+//! This module implements the `AIProvider` trait on top of the Anthropic
+//! Messages API.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::{future, StreamExt};
+use reqwest::{Client, RequestBuilder, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use crate::utils::{AppError, AppResult};
+use super::registry::{ApiKeyPolicy, ProviderDescriptor, ProviderRegistry};
+use super::{
+    sse, split_deltas, AIProvider, ChatCompletion, ChatCompletionDelta, ChatCompletionParams,
+    content, ChatCompletionStream, CompletionUsage, ContentPart, Message, MessageContent,
+    PartSource, Role, ToolCall, ToolDefinition,
+};
+
+/// Base URL of the public Anthropic API
+const DEFAULT_BASE_URL: &str = "https://api.anthropic.com/v1";
+
+/// API version sent with every request
+const API_VERSION: &str = "2023-06-01";
+
+/// Output token limit used when the caller does not set one,
+/// since the Messages API requires `max_tokens`
+const DEFAULT_MAX_TOKENS: i32 = 1024;
+
+/// Models offered for selection
+const MODELS: &[&str] = &[
+    "claude-3-5-sonnet-latest",
+    "claude-3-5-haiku-latest",
+    "claude-3-opus-latest",
+];
+
+/// Registers the Anthropic provider
+pub(crate) fn register(registry: &ProviderRegistry) {
+    registry.register(
+        ProviderDescriptor::new("anthropic", "Anthropic", ApiKeyPolicy::Required, |context| async move {
+            let provider: Arc<dyn AIProvider> = Arc::new(AnthropicProvider::new(context.require_api_key()?));
+            Ok(provider)
+        })
+        .with_default_model("claude-3-5-haiku-latest"),
+    );
+}
+
+/// AI provider backed by the Anthropic Messages API
+#[derive(Debug, Clone)]
+pub struct AnthropicProvider {
+    /// HTTP client used for all requests
+    client: Client,
+    /// API key sent in the `x-api-key` header
+    api_key: String,
+    /// Base URL of the API, without a trailing slash
+    base_url: String,
+}
+
+impl AnthropicProvider {
+    /// Creates a new provider using the public Anthropic endpoint
+    pub fn new(api_key: String) -> Self {
+        Self {
+            client: Client::new(),
+            api_key,
+            base_url: DEFAULT_BASE_URL.to_string(),
+        }
+    }
+
+    /// Overrides the base URL of the API
+    pub fn with_base_url<S: Into<String>>(mut self, base_url: S) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Builds the full URL for an API path
+    fn endpoint(&self, path: &str) -> String {
+        format!("{}/{}", self.base_url, path)
+    }
+
+    /// Attaches the authentication and version headers to a request
+    fn authorize(&self, request: RequestBuilder, api_key: &str) -> RequestBuilder {
+        request
+            .header("x-api-key", api_key)
+            .header("anthropic-version", API_VERSION)
+    }
+
+    /// Builds the request body for a chat completion
+    ///
+    /// The Messages API has no system role, so the system prompt and any
+    /// system messages in the history are lifted into the top-level
+    /// `system` field.
+    fn build_request(messages: &[Message], params: &ChatCompletionParams) -> MessagesRequest {
+        let mut system_parts: Vec<String> = params.system_prompt.iter().cloned().collect();
+        let mut wire_messages = Vec::with_capacity(messages.len());
+
+        for message in messages {
+            if message.role == Role::System {
+                system_parts.push(message.content.text());
+            } else {
+                // Tool results are sent back as part of a user turn
+                let role = match message.role {
+                    Role::Tool => Role::User,
+                    role => role,
+                };
+                wire_messages.push(WireMessage {
+                    role: role.to_string(),
+                    content: wire_content(&message.content),
+                });
+            }
+        }
+
+        MessagesRequest {
+            model: params.model.clone(),
+            max_tokens: if params.max_tokens > 0 { params.max_tokens } else { DEFAULT_MAX_TOKENS },
+            system: (!system_parts.is_empty()).then(|| system_parts.join("\n\n")),
+            messages: wire_messages,
+            temperature: params.temperature,
+            tools: params.tools.iter().map(WireTool::from).collect(),
+            stream: false,
+        }
+    }
+
+    /// Sends a request to the Messages endpoint, failing on unsuccessful responses
+    async fn send_messages_request(&self, request: &MessagesRequest) -> AppResult<reqwest::Response> {
+        let response = self
+            .authorize(self.client.post(self.endpoint("messages")), &self.api_key)
+            .json(request)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(error_from_response(response).await);
+        }
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl AIProvider for AnthropicProvider {
+    fn name(&self) -> &str {
+        "anthropic"
+    }
+
+    fn available_models(&self) -> Vec<String> {
+        MODELS.iter().map(|model| model.to_string()).collect()
+    }
+
+    fn supports_prefill(&self) -> bool {
+        true
+    }
+
+    async fn create_chat_completion(
+        &self,
+        messages: Vec<Message>,
+        params: ChatCompletionParams
+    ) -> AppResult<ChatCompletion> {
+        let request = Self::build_request(&messages, &params);
+        let response = self.send_messages_request(&request).await?;
+
+        let body: MessagesResponse = response.json().await?;
+        let mut text = String::new();
+        let mut calls = Vec::new();
+        for block in body.content {
+            match block {
+                ResponseBlock::Text { text: block } => text.push_str(&block),
+                ResponseBlock::ToolUse { id, name, input } => calls.push(ToolCall { id, name, arguments: input }),
+                ResponseBlock::Other => {}
+            }
+        }
+
+        Ok(ChatCompletion {
+            message: Message::new(
+                body.role.parse().unwrap_or(Role::Assistant),
+                MessageContent::reply(text, calls),
+            ),
+            usage: CompletionUsage {
+                prompt_tokens: body.usage.input_tokens,
+                completion_tokens: body.usage.output_tokens,
+                total_tokens: body.usage.input_tokens + body.usage.output_tokens,
+            },
+            finish_reason: body.stop_reason,
+        })
+    }
+
+    async fn create_chat_completion_stream(
+        &self,
+        messages: Vec<Message>,
+        params: ChatCompletionParams
+    ) -> AppResult<ChatCompletionStream> {
+        let mut request = Self::build_request(&messages, &params);
+        request.stream = true;
+        let response = self.send_messages_request(&request).await?;
+
+        // Input tokens are reported when the message starts, output tokens when it ends
+        let deltas = sse::events(response)
+            .scan(0, |input_tokens, event| {
+                let result = event.and_then(|event| parse_stream_event(&event.data, input_tokens));
+                future::ready(Some(result))
+            })
+            .flat_map(split_deltas);
+        Ok(Box::pin(deltas))
+    }
+
+    async fn validate_api_key(&self, api_key: &str) -> AppResult<bool> {
+        // Listing a single model is authenticated but does not consume tokens
+        let response = self
+            .authorize(self.client.get(self.endpoint("models")), api_key)
+            .query(&[("limit", "1")])
+            .send()
+            .await?;
+
+        match response.status() {
+            status if status.is_success() => Ok(true),
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Ok(false),
+            _ => Err(error_from_response(response).await),
+        }
+    }
+}
+
+/// Converts an unsuccessful response into an API error, keeping the
+/// error type reported by the API
+async fn error_from_response(response: reqwest::Response) -> AppError {
+    let status = response.status();
+    let text = response.text().await.unwrap_or_default();
+
+    match serde_json::from_str::<ErrorResponse>(&text) {
+        Ok(ErrorResponse { error }) => {
+            AppError::api(format!("{} ({}): {}", status, error.kind, error.message))
+        }
+        Err(_) => AppError::api(format!("{}: {}", status, text)),
+    }
+}
+
+/// Converts one event of a streamed message into deltas
+fn parse_stream_event(data: &str, input_tokens: &mut i32) -> AppResult<Vec<ChatCompletionDelta>> {
+    let deltas = match serde_json::from_str(data)? {
+        StreamEvent::MessageStart { message } => {
+            *input_tokens = message.usage.input_tokens;
+            Vec::new()
+        }
+        StreamEvent::ContentBlockDelta { delta } => delta.text
+            .map(|text| vec![ChatCompletionDelta::Content { text }])
+            .unwrap_or_default(),
+        StreamEvent::MessageDelta { delta, usage } => {
+            let mut deltas = vec![ChatCompletionDelta::Usage {
+                usage: CompletionUsage {
+                    prompt_tokens: *input_tokens,
+                    completion_tokens: usage.output_tokens,
+                    total_tokens: *input_tokens + usage.output_tokens,
+                },
+            }];
+            if let Some(reason) = delta.stop_reason {
+                deltas.push(ChatCompletionDelta::Finish { reason });
+            }
+            deltas
+        }
+        StreamEvent::Error { error } => {
+            return Err(AppError::api(format!("{}: {}", error.kind, error.message)));
+        }
+        StreamEvent::Other => Vec::new(),
+    };
+    Ok(deltas)
+}
+
+/// Request body of the Messages endpoint
+#[derive(Debug, Serialize)]
+struct MessagesRequest {
+    model: String,
+    max_tokens: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<String>,
+    messages: Vec<WireMessage>,
+    temperature: f32,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<WireTool>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+}
+
+/// A tool definition as represented on the wire
+#[derive(Debug, Serialize)]
+struct WireTool {
+    name: String,
+    description: String,
+    input_schema: serde_json::Value,
+}
+
+impl From<&ToolDefinition> for WireTool {
+    fn from(definition: &ToolDefinition) -> Self {
+        Self {
+            name: definition.name.clone(),
+            description: definition.description.clone(),
+            input_schema: definition.parameters.clone(),
+        }
+    }
+}
+
+/// A message as represented on the wire
+#[derive(Debug, Serialize)]
+struct WireMessage {
+    role: String,
+    content: WireContent,
+}
+
+/// Message content, as a plain string or as content blocks
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum WireContent {
+    Text(String),
+    Blocks(Vec<WireBlock>),
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum WireBlock {
+    Text {
+        text: String,
+    },
+    Image {
+        source: WireSource,
+    },
+    Document {
+        source: WireSource,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        title: Option<String>,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        input: serde_json::Value,
+    },
+    ToolResult {
+        tool_use_id: String,
+        content: String,
+        #[serde(skip_serializing_if = "std::ops::Not::not")]
+        is_error: bool,
+    },
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum WireSource {
+    Base64 { media_type: String, data: String },
+    Text { media_type: String, data: String },
+}
+
+/// Translates message content into text, image and document blocks
+///
+/// PDFs are sent as base64 documents and text files as plain text documents.
+fn wire_content(content: &MessageContent) -> WireContent {
+    if content.is_text_only() {
+        return WireContent::Text(content.text());
+    }
+
+    let blocks = content.parts.iter().filter_map(|part| Some(match part {
+        // Empty text blocks are rejected by the API
+        ContentPart::Text { text } if text.is_empty() => return None,
+        ContentPart::Text { text } => WireBlock::Text { text: text.clone() },
+        ContentPart::ToolCall(call) => WireBlock::ToolUse {
+            id: call.id.clone(),
+            name: call.name.clone(),
+            input: call.arguments.clone(),
+        },
+        ContentPart::ToolResult(result) => WireBlock::ToolResult {
+            tool_use_id: result.call_id.clone(),
+            content: result.content.clone(),
+            is_error: result.is_error,
+        },
+        ContentPart::Image { mime_type, source: PartSource::Base64 { data } } => WireBlock::Image {
+            source: WireSource::Base64 { media_type: mime_type.clone(), data: data.clone() },
+        },
+        ContentPart::File { name, mime_type, source: PartSource::Base64 { data }, .. }
+            if mime_type == "application/pdf" =>
+        {
+            WireBlock::Document {
+                source: WireSource::Base64 { media_type: mime_type.clone(), data: data.clone() },
+                title: Some(name.clone()),
+            }
+        }
+        ContentPart::File { name, mime_type, source: PartSource::Base64 { data }, .. }
+            if content::is_text_mime(mime_type) =>
+        {
+            match content::decode_base64(data) {
+                Ok(bytes) => WireBlock::Document {
+                    source: WireSource::Text {
+                        media_type: "text/plain".to_string(),
+                        data: String::from_utf8_lossy(&bytes).into_owned(),
+                    },
+                    title: Some(name.clone()),
+                },
+                Err(_) => WireBlock::Text { text: content::unsupported_part(part) },
+            }
+        }
+        _ => WireBlock::Text { text: content::unsupported_part(part) },
+    }));
+    WireContent::Blocks(blocks.collect())
+}
+
+/// Response body of the Messages endpoint
+#[derive(Debug, Deserialize)]
+struct MessagesResponse {
+    role: String,
+    content: Vec<ResponseBlock>,
+    stop_reason: Option<String>,
+    usage: Usage,
+}
+
+/// A content block of a response
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ResponseBlock {
+    Text { text: String },
+    ToolUse { id: String, name: String, input: serde_json::Value },
+    #[serde(other)]
+    Other,
+}
+
+/// A content block delta of a streamed message
+#[derive(Debug, Deserialize)]
+struct ContentBlock {
+    text: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Usage {
+    input_tokens: i32,
+    output_tokens: i32,
+}
+
+/// An event of a streamed message
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StreamEvent {
+    MessageStart { message: StreamMessage },
+    ContentBlockDelta { delta: ContentBlock },
+    MessageDelta { delta: MessageDelta, usage: OutputUsage },
+    Error { error: ErrorDetail },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamMessage {
+    usage: Usage,
+}
+
+#[derive(Debug, Deserialize)]
+struct MessageDelta {
+    stop_reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OutputUsage {
+    output_tokens: i32,
+}
+
+/// Error body returned by the API
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: ErrorDetail,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorDetail {
+    #[serde(rename = "type")]
+    kind: String,
+    message: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::ai::ToolResult;
+    use mockito::{Matcher, Server};
+    use serde_json::json;
+
+    fn message(role: Role, content: &str) -> Message {
+        Message::new(role, content)
+    }
+
+    #[tokio::test]
+    async fn test_create_chat_completion() {
+        let mut server = Server::new_async().await;
+        let mock = server.mock("POST", "/messages")
+            .match_header("x-api-key", "test-key")
+            .match_header("anthropic-version", API_VERSION)
+            .match_body(Matcher::Json(json!({
+                "model": "claude-3-5-haiku-latest",
+                "max_tokens": DEFAULT_MAX_TOKENS,
+                "system": "Be brief.",
+                "messages": [{ "role": "user", "content": "Hello" }],
+                "temperature": 0.0
+            })))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(json!({
+                "id": "msg_1",
+                "type": "message",
+                "role": "assistant",
+                "content": [{ "type": "text", "text": "Hi there!" }],
+                "stop_reason": "end_turn",
+                "usage": { "input_tokens": 10, "output_tokens": 4 }
+            }).to_string())
+            .create_async()
+            .await;
+
+        let provider = AnthropicProvider::new("test-key".to_string()).with_base_url(server.url());
+        let params = ChatCompletionParams {
+            model: "claude-3-5-haiku-latest".to_string(),
+            temperature: 0.0,
+            max_tokens: 0,
+            system_prompt: Some("Be brief.".to_string()),
+            tools: Vec::new(),
+        };
+        let completion = provider
+            .create_chat_completion(vec![message(Role::User, "Hello")], params)
+            .await
+            .unwrap();
+
+        mock.assert_async().await;
+        assert_eq!(completion.message.role, Role::Assistant);
+        assert_eq!(completion.message.content, "Hi there!");
+        assert_eq!(completion.usage.prompt_tokens, 10);
+        assert_eq!(completion.usage.completion_tokens, 4);
+        assert_eq!(completion.usage.total_tokens, 14);
+        assert_eq!(completion.finish_reason.as_deref(), Some("end_turn"));
+    }
+
+    #[tokio::test]
+    async fn test_create_chat_completion_stream() {
+        let body = [
+            r#"event: message_start
+data: {"type":"message_start","message":{"id":"msg_1","role":"assistant","content":[],"usage":{"input_tokens":10,"output_tokens":1}}}"#,
+            r#"event: content_block_start
+data: {"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}"#,
+            "event: ping\ndata: {\"type\": \"ping\"}",
+            r#"event: content_block_delta
+data: {"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Hi"}}"#,
+            r#"event: content_block_delta
+data: {"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":" there!"}}"#,
+            r#"event: content_block_stop
+data: {"type":"content_block_stop","index":0}"#,
+            r#"event: message_delta
+data: {"type":"message_delta","delta":{"stop_reason":"max_tokens","stop_sequence":null},"usage":{"output_tokens":4}}"#,
+            r#"event: message_stop
+data: {"type":"message_stop"}"#,
+        ].join("\n\n") + "\n\n";
+
+        let mut server = Server::new_async().await;
+        server.mock("POST", "/messages")
+            .match_body(Matcher::PartialJson(json!({ "stream": true })))
+            .with_status(200)
+            .with_header("content-type", "text/event-stream")
+            .with_body(body)
+            .create_async()
+            .await;
+
+        let provider = AnthropicProvider::new("test-key".to_string()).with_base_url(server.url());
+        let params = ChatCompletionParams {
+            model: "claude-3-5-haiku-latest".to_string(),
+            temperature: 0.0,
+            max_tokens: 4,
+            system_prompt: None,
+            tools: Vec::new(),
+        };
+        let deltas: Vec<_> = provider
+            .create_chat_completion_stream(vec![message(Role::User, "Hello")], params)
+            .await
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+            .await;
+
+        assert_eq!(deltas, vec![
+            ChatCompletionDelta::Content { text: "Hi".to_string() },
+            ChatCompletionDelta::Content { text: " there!".to_string() },
+            ChatCompletionDelta::Usage {
+                usage: CompletionUsage { prompt_tokens: 10, completion_tokens: 4, total_tokens: 14 },
+            },
+            ChatCompletionDelta::Finish { reason: "max_tokens".to_string() },
+        ]);
+    }
+
+    #[test]
+    fn test_system_messages_are_lifted() {
+        let params = ChatCompletionParams {
+            model: "claude-3-5-haiku-latest".to_string(),
+            temperature: 0.7,
+            max_tokens: 256,
+            system_prompt: Some("You are Synapse.".to_string()),
+            tools: Vec::new(),
+        };
+        let messages = vec![message(Role::System, "Answer in French."), message(Role::User, "Hello")];
+
+        let request = AnthropicProvider::build_request(&messages, &params);
+
+        assert_eq!(request.system.as_deref(), Some("You are Synapse.\n\nAnswer in French."));
+        assert_eq!(request.max_tokens, 256);
+        assert_eq!(request.messages.len(), 1);
+        assert_eq!(request.messages[0].role, "user");
+    }
+
+    #[tokio::test]
+    async fn test_tool_use() {
+        let call = ToolCall {
+            id: "toolu_1".to_string(),
+            name: "weather".to_string(),
+            arguments: json!({ "city": "Oslo" }),
+        };
+        let history = vec![
+            message(Role::User, "Weather in Oslo?"),
+            Message::new(Role::Assistant, MessageContent::reply(String::new(), vec![call.clone()])),
+            Message::new(Role::Tool, vec![ContentPart::ToolResult(ToolResult::success("toolu_1", "Sunny"))]),
+        ];
+
+        let mut server = Server::new_async().await;
+        let mock = server.mock("POST", "/messages")
+            .match_body(Matcher::PartialJson(json!({
+                "messages": [
+                    { "role": "user", "content": "Weather in Oslo?" },
+                    { "role": "assistant", "content": [
+                        { "type": "tool_use", "id": "toolu_1", "name": "weather", "input": { "city": "Oslo" } }
+                    ] },
+                    { "role": "user", "content": [
+                        { "type": "tool_result", "tool_use_id": "toolu_1", "content": "Sunny" }
+                    ] }
+                ],
+                "tools": [{ "name": "weather", "description": "Current weather", "input_schema": { "type": "object" } }]
+            })))
+            .with_status(200)
+            .with_body(json!({
+                "role": "assistant",
+                "content": [
+                    { "type": "text", "text": "Checking again." },
+                    { "type": "tool_use", "id": "toolu_2", "name": "weather", "input": { "city": "Bergen" } }
+                ],
+                "stop_reason": "tool_use",
+                "usage": { "input_tokens": 30, "output_tokens": 12 }
+            }).to_string())
+            .create_async()
+            .await;
+
+        let provider = AnthropicProvider::new("test-key".to_string()).with_base_url(server.url());
+        let params = ChatCompletionParams {
+            model: "claude-3-5-haiku-latest".to_string(),
+            temperature: 0.0,
+            max_tokens: 64,
+            system_prompt: None,
+            tools: vec![ToolDefinition {
+                name: "weather".to_string(),
+                description: "Current weather".to_string(),
+                parameters: json!({ "type": "object" }),
+            }],
+        };
+        let completion = provider.create_chat_completion(history, params).await.unwrap();
+
+        mock.assert_async().await;
+        assert_eq!(completion.message.content.text(), "Checking again.");
+        let calls: Vec<_> = completion.message.content.tool_calls().collect();
+        assert_eq!(calls[0].id, "toolu_2");
+        assert_eq!(calls[0].arguments, json!({ "city": "Bergen" }));
+        assert_eq!(completion.finish_reason.as_deref(), Some("tool_use"));
+    }
+
+    #[tokio::test]
+    async fn test_api_error_keeps_type() {
+        let mut server = Server::new_async().await;
+        server.mock("POST", "/messages")
+            .with_status(529)
+            .with_body(json!({
+                "type": "error",
+                "error": { "type": "overloaded_error", "message": "Overloaded" }
+            }).to_string())
+            .create_async()
+            .await;
+
+        let provider = AnthropicProvider::new("test-key".to_string()).with_base_url(server.url());
+        let params = ChatCompletionParams {
+            model: "claude-3-5-haiku-latest".to_string(),
+            temperature: 0.0,
+            max_tokens: 16,
+            system_prompt: None,
+            tools: Vec::new(),
+        };
+        let error = provider
+            .create_chat_completion(vec![message(Role::User, "Hello")], params)
+            .await
+            .unwrap_err();
+
+        match error {
+            AppError::Api(msg) => {
+                assert!(msg.contains("overloaded_error"));
+                assert!(msg.contains("Overloaded"));
+            }
+            other => panic!("Unexpected error: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_validate_api_key() {
+        let mut server = Server::new_async().await;
+        server.mock("GET", "/models")
+            .match_query(Matcher::UrlEncoded("limit".into(), "1".into()))
+            .match_header("x-api-key", "good-key")
+            .with_status(200)
+            .with_body(r#"{"data":[],"has_more":false}"#)
+            .create_async()
+            .await;
+        server.mock("GET", "/models")
+            .match_query(Matcher::Any)
+            .match_header("x-api-key", "bad-key")
+            .with_status(401)
+            .with_body(r#"{"type":"error","error":{"type":"authentication_error","message":"invalid x-api-key"}}"#)
+            .create_async()
+            .await;
+
+        let provider = AnthropicProvider::new(String::new()).with_base_url(server.url());
+        assert!(provider.validate_api_key("good-key").await.unwrap());
+        assert!(!provider.validate_api_key("bad-key").await.unwrap());
+    }
+}
@@ -0,0 +1,251 @@
+//! Tool approval
+//!
+//! This module lets the user decide whether a tool call may go ahead. Tools
+//! report calls that need consent, such as writing files or reading outside
+//! the approved folders, through `Tool::approval`. The registry then
+//! publishes an `ApprovalRequest` and waits for the user's decision. Actions
+//! the user chose to always allow are remembered by key; persisting them is
+//! up to the caller answering the request.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::{broadcast, oneshot};
+
+use crate::utils::{AppError, AppResult};
+
+/// How long a tool call waits for the user before it is denied
+pub const APPROVAL_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Number of approval requests buffered for slow subscribers
+const REQUEST_CAPACITY: usize = 16;
+
+/// An action of a tool call that needs the user's approval
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Approval {
+    /// Identifies the action when the user chooses to always allow it
+    pub key: String,
+    /// What the call is about to do, in words shown to the user
+    pub description: String,
+}
+
+/// A tool call waiting for the user's decision
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ApprovalRequest {
+    /// Id to answer the request with
+    pub id: String,
+    /// Name of the tool being called
+    pub tool: String,
+    /// What the call is about to do
+    pub description: String,
+    /// Arguments of the call
+    pub arguments: Value,
+    /// Key remembered if the user always allows the action
+    pub key: String,
+}
+
+/// The user's answer to an approval request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApprovalDecision {
+    /// Do not run the call
+    Deny,
+    /// Run this call only
+    Allow,
+    /// Run this call and skip asking for the same action again
+    AlwaysAllow,
+}
+
+/// A request waiting for an answer
+#[derive(Debug)]
+struct PendingApproval {
+    /// The request as published
+    request: ApprovalRequest,
+    /// Wakes the waiting tool call
+    sender: oneshot::Sender<ApprovalDecision>,
+}
+
+/// What tools may do without asking, and the requests waiting for the user
+///
+/// Clones share their state, so tools holding a clone see changes made
+/// through the registry.
+#[derive(Debug, Clone)]
+pub struct ToolPermissions {
+    /// Folders tools may read without asking
+    roots: Arc<RwLock<Vec<PathBuf>>>,
+    /// Keys of actions the user always allows
+    always_allowed: Arc<RwLock<HashSet<String>>>,
+    /// Requests waiting for an answer, keyed by id
+    pending: Arc<Mutex<HashMap<String, PendingApproval>>>,
+    /// Publishes new requests
+    requests: broadcast::Sender<ApprovalRequest>,
+}
+
+impl Default for ToolPermissions {
+    fn default() -> Self {
+        Self {
+            roots: Arc::default(),
+            always_allowed: Arc::default(),
+            pending: Arc::default(),
+            requests: broadcast::channel(REQUEST_CAPACITY).0,
+        }
+    }
+}
+
+impl ToolPermissions {
+    /// Creates permissions without approved folders or actions
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Folders tools may read without asking
+    pub fn roots(&self) -> Vec<PathBuf> {
+        self.roots.read().map(|roots| roots.clone()).unwrap_or_default()
+    }
+
+    /// Replaces the approved folders
+    pub fn set_roots(&self, roots: Vec<PathBuf>) {
+        if let Ok(mut current) = self.roots.write() {
+            *current = roots;
+        }
+    }
+
+    /// Whether a path lies within one of the approved folders
+    ///
+    /// Both the path and the folders are compared in canonical form, so
+    /// `..` components and symbolic links cannot lead out of a folder.
+    pub fn is_within_roots(&self, path: &Path) -> bool {
+        let Ok(path) = path.canonicalize() else {
+            return false;
+        };
+        self.roots()
+            .iter()
+            .filter_map(|root| root.canonicalize().ok())
+            .any(|root| path.starts_with(root))
+    }
+
+    /// Replaces the actions the user always allows
+    pub fn set_always_allowed<I: IntoIterator<Item = String>>(&self, keys: I) {
+        if let Ok(mut always_allowed) = self.always_allowed.write() {
+            *always_allowed = keys.into_iter().collect();
+        }
+    }
+
+    /// Whether the user always allows an action
+    pub fn is_always_allowed(&self, key: &str) -> bool {
+        self.always_allowed
+            .read()
+            .map(|always_allowed| always_allowed.contains(key))
+            .unwrap_or(false)
+    }
+
+    /// Subscribes to approval requests
+    ///
+    /// Calls needing approval are denied while nobody is subscribed.
+    pub fn subscribe(&self) -> broadcast::Receiver<ApprovalRequest> {
+        self.requests.subscribe()
+    }
+
+    /// Asks the user whether a tool call may perform an action
+    ///
+    /// Returns immediately for actions the user always allows. Otherwise
+    /// the request is published and the call waits for `respond`; it is
+    /// denied if nobody is listening or no answer arrives within
+    /// `APPROVAL_TIMEOUT`.
+    pub async fn approve(&self, tool: &str, arguments: &Value, approval: Approval) -> bool {
+        if self.is_always_allowed(&approval.key) {
+            return true;
+        }
+
+        let request = ApprovalRequest {
+            id: uuid::Uuid::new_v4().to_string(),
+            tool: tool.to_string(),
+            description: approval.description,
+            arguments: arguments.clone(),
+            key: approval.key,
+        };
+        let id = request.id.clone();
+        let (sender, receiver) = oneshot::channel();
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.insert(id.clone(), PendingApproval { request: request.clone(), sender });
+        }
+
+        let decision = match self.requests.send(request) {
+            Ok(_) => tokio::time::timeout(APPROVAL_TIMEOUT, receiver).await.ok().and_then(Result::ok),
+            Err(_) => None,
+        };
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.remove(&id);
+        }
+        matches!(decision, Some(ApprovalDecision::Allow | ApprovalDecision::AlwaysAllow))
+    }
+
+    /// Answers a pending approval request
+    ///
+    /// # Returns
+    /// The request that was answered
+    ///
+    /// # Errors
+    /// Returns an error if no request with this id is waiting
+    pub fn respond(&self, id: &str, decision: ApprovalDecision) -> AppResult<ApprovalRequest> {
+        let pending = self
+            .pending
+            .lock()
+            .map_err(|_| AppError::internal("Approval state is poisoned"))?
+            .remove(id)
+            .ok_or_else(|| AppError::not_found("Approval request not found"))?;
+
+        if decision == ApprovalDecision::AlwaysAllow {
+            if let Ok(mut always_allowed) = self.always_allowed.write() {
+                always_allowed.insert(pending.request.key.clone());
+            }
+        }
+        // The call may have timed out in the meantime, which needs no answer
+        let _ = pending.sender.send(decision);
+        Ok(pending.request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn approval() -> Approval {
+        Approval {
+            key: "read_file:/etc".to_string(),
+            description: "Read /etc/hosts".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_approval_round_trip() {
+        let permissions = ToolPermissions::new();
+        let arguments = serde_json::json!({ "path": "/etc/hosts" });
+
+        // Without anyone to ask, calls are denied
+        assert!(!permissions.approve("read_file", &arguments, approval()).await);
+
+        let mut requests = permissions.subscribe();
+        let answering = permissions.clone();
+        let answer = tokio::spawn(async move {
+            let request = requests.recv().await.unwrap();
+            assert_eq!(request.tool, "read_file");
+            answering.respond(&request.id, ApprovalDecision::Deny).unwrap();
+
+            let request = requests.recv().await.unwrap();
+            answering.respond(&request.id, ApprovalDecision::AlwaysAllow).unwrap()
+        });
+
+        assert!(!permissions.approve("read_file", &arguments, approval()).await);
+        assert!(permissions.approve("read_file", &arguments, approval()).await);
+        assert_eq!(answer.await.unwrap().key, "read_file:/etc");
+
+        // Always allowed actions no longer ask, and answered requests are gone
+        assert!(permissions.approve("read_file", &arguments, approval()).await);
+        assert!(permissions.respond("unknown", ApprovalDecision::Allow).is_err());
+    }
+}
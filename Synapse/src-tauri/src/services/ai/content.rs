@@ -0,0 +1,264 @@
+//! Message content
+//!
+//! This module defines the parts a message is made of: text, images, file
+//! attachments, and the tool calls and results of a tool loop. Content
+//! consisting of text only serializes as a plain string, so messages written
+//! before attachments existed read back unchanged and the frontend can keep
+//! treating text messages as strings.
+
+use std::fmt;
+
+use base64::Engine;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::utils::{AppError, AppResult};
+use super::tools::{ToolCall, ToolResult};
+
+/// Where the data of an image or file part comes from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PartSource {
+    /// The data itself, base64 encoded
+    Base64 { data: String },
+    /// An attachment stored with the session, by id
+    Attachment { id: String },
+}
+
+/// One part of a message
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+    /// Plain text
+    Text { text: String },
+    /// An image, such as a screenshot or photo
+    Image {
+        /// MIME type of the image, e.g. `image/png`
+        mime_type: String,
+        /// The image data
+        source: PartSource,
+    },
+    /// A document or other file
+    File {
+        /// File name shown to the user and the model
+        name: String,
+        /// MIME type of the file, e.g. `application/pdf`
+        mime_type: String,
+        /// Size of the file in bytes
+        size: u64,
+        /// The file data
+        source: PartSource,
+    },
+    /// A tool call made by the assistant
+    ToolCall(ToolCall),
+    /// The result of a tool call
+    ToolResult(ToolResult),
+}
+
+impl ContentPart {
+    /// Creates a text part
+    pub fn text<T: Into<String>>(text: T) -> Self {
+        Self::Text { text: text.into() }
+    }
+
+    /// The source of an image or file part
+    pub fn source(&self) -> Option<&PartSource> {
+        match self {
+            Self::Image { source, .. } | Self::File { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+
+    /// Mutable access to the source of an image or file part
+    pub fn source_mut(&mut self) -> Option<&mut PartSource> {
+        match self {
+            Self::Image { source, .. } | Self::File { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// The content of a message as a list of parts
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MessageContent {
+    /// The parts, in the order they are shown
+    pub parts: Vec<ContentPart>,
+}
+
+impl MessageContent {
+    /// Content of a model's reply: its text followed by its tool calls
+    pub fn reply(text: String, calls: Vec<ToolCall>) -> Self {
+        let mut parts = Vec::with_capacity(calls.len() + 1);
+        if !text.is_empty() || calls.is_empty() {
+            parts.push(ContentPart::Text { text });
+        }
+        parts.extend(calls.into_iter().map(ContentPart::ToolCall));
+        Self { parts }
+    }
+
+    /// Text of all text parts, separated by blank lines
+    pub fn text(&self) -> String {
+        let texts: Vec<&str> = self.parts
+            .iter()
+            .filter_map(|part| match part {
+                ContentPart::Text { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        texts.join("\n\n")
+    }
+
+    /// Whether the content consists of text only
+    pub fn is_text_only(&self) -> bool {
+        self.parts.iter().all(|part| matches!(part, ContentPart::Text { .. }))
+    }
+
+    /// Image and file parts
+    pub fn attachments(&self) -> impl Iterator<Item = &ContentPart> {
+        self.parts
+            .iter()
+            .filter(|part| matches!(part, ContentPart::Image { .. } | ContentPart::File { .. }))
+    }
+
+    /// Tool calls made by the assistant
+    pub fn tool_calls(&self) -> impl Iterator<Item = &ToolCall> {
+        self.parts.iter().filter_map(|part| match part {
+            ContentPart::ToolCall(call) => Some(call),
+            _ => None,
+        })
+    }
+
+    /// Results of tool calls
+    pub fn tool_results(&self) -> impl Iterator<Item = &ToolResult> {
+        self.parts.iter().filter_map(|part| match part {
+            ContentPart::ToolResult(result) => Some(result),
+            _ => None,
+        })
+    }
+
+    /// Appends text to the last text part, or adds one
+    pub fn push_text(&mut self, text: &str) {
+        match self.parts.last_mut() {
+            Some(ContentPart::Text { text: last }) => last.push_str(text),
+            _ => self.parts.push(ContentPart::text(text)),
+        }
+    }
+
+    /// Removes trailing whitespace from the last text part
+    pub fn trim_end(&mut self) {
+        if let Some(ContentPart::Text { text }) = self.parts.last_mut() {
+            text.truncate(text.trim_end().len());
+        }
+    }
+
+    /// Replaces the text, keeping images and files
+    pub fn with_text(&self, text: String) -> Self {
+        let mut parts = vec![ContentPart::text(text)];
+        parts.extend(self.attachments().cloned());
+        Self { parts }
+    }
+}
+
+impl From<String> for MessageContent {
+    fn from(text: String) -> Self {
+        Self {
+            parts: vec![ContentPart::Text { text }],
+        }
+    }
+}
+
+impl From<&str> for MessageContent {
+    fn from(text: &str) -> Self {
+        Self::from(text.to_string())
+    }
+}
+
+impl From<Vec<ContentPart>> for MessageContent {
+    fn from(parts: Vec<ContentPart>) -> Self {
+        Self { parts }
+    }
+}
+
+impl PartialEq<&str> for MessageContent {
+    fn eq(&self, other: &&str) -> bool {
+        self.is_text_only() && self.text() == *other
+    }
+}
+
+impl fmt::Display for MessageContent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text())
+    }
+}
+
+impl Serialize for MessageContent {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.parts.as_slice() {
+            [] => serializer.serialize_str(""),
+            [ContentPart::Text { text }] => serializer.serialize_str(text),
+            parts => parts.serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for MessageContent {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Text(String),
+            Parts(Vec<ContentPart>),
+        }
+
+        Ok(match Repr::deserialize(deserializer)? {
+            Repr::Text(text) => Self::from(text),
+            Repr::Parts(parts) => Self { parts },
+        })
+    }
+}
+
+/// Decodes base64 data
+pub fn decode_base64(data: &str) -> AppResult<Vec<u8>> {
+    base64::engine::general_purpose::STANDARD
+        .decode(data.trim())
+        .map_err(|e| AppError::invalid_input(format!("Invalid base64 data: {}", e)))
+}
+
+/// Encodes data as base64
+pub fn encode_base64(data: &[u8]) -> String {
+    base64::engine::general_purpose::STANDARD.encode(data)
+}
+
+/// Placeholder sent instead of a part a provider cannot represent
+pub fn unsupported_part(part: &ContentPart) -> String {
+    match part {
+        ContentPart::Text { text } => text.clone(),
+        ContentPart::Image { mime_type, .. } => format!("[Image ({}) not supported here]", mime_type),
+        ContentPart::File { name, mime_type, .. } => {
+            format!("[File {} ({}) not supported here]", name, mime_type)
+        }
+        ContentPart::ToolCall(call) => format!("[Called tool {} with {}]", call.name, call.arguments),
+        ContentPart::ToolResult(result) => format!("[Tool result: {}]", result.content),
+    }
+}
+
+/// Whether a MIME type denotes text a model can read inline
+pub fn is_text_mime(mime_type: &str) -> bool {
+    mime_type.starts_with("text/")
+        || matches!(mime_type, "application/json" | "application/xml" | "application/x-yaml")
+}
+
+/// Renders a text file as text for models without document support
+///
+/// # Errors
+/// Returns an error if the part is not a text file with base64 data
+pub fn inline_text_file(part: &ContentPart) -> AppResult<String> {
+    match part {
+        ContentPart::File { name, mime_type, source: PartSource::Base64 { data }, .. }
+            if is_text_mime(mime_type) =>
+        {
+            let text = String::from_utf8_lossy(&decode_base64(data)?).into_owned();
+            Ok(format!("<file name=\"{}\">\n{}\n</file>", name, text))
+        }
+        _ => Err(AppError::invalid_input("Not a text file")),
+    }
+}
@@ -2,22 +2,156 @@
 //! 
 //! This module handles interactions with AI providers like OpenAI and Anthropic.
 //! It provides a unified interface for making chat completions and managing API keys.
+//! Providers are looked up by id through the `ProviderRegistry`.
 
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::stream::{self, BoxStream};
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
-use std::fmt::Debug;
-use crate::utils::AppResult;
+use std::fmt::{self, Debug};
+use std::str::FromStr;
+use crate::utils::{AppError, AppResult};
+
+pub mod anthropic;
+pub mod approval;
+pub mod content;
+pub mod ollama;
+pub mod openai;
+pub mod registry;
+pub mod shell;
+pub mod sse;
+pub mod tools;
+
+pub use anthropic::AnthropicProvider;
+pub use approval::{Approval, ApprovalDecision, ApprovalRequest, ToolPermissions};
+pub use content::{ContentPart, MessageContent, PartSource};
+pub use ollama::OllamaProvider;
+pub use openai::OpenAIProvider;
+pub use registry::{ApiKeyPolicy, ProviderDescriptor, ProviderRegistry};
+pub use shell::ShellTool;
+pub use tools::{Tool, ToolCall, ToolDefinition, ToolRegistry, ToolResult};
+
+/// The author of a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Instructions for the model
+    System,
+    /// A message written by the user
+    User,
+    /// A reply generated by a model
+    Assistant,
+    /// The result of a tool call
+    Tool,
+}
+
+impl Role {
+    /// Name of the role as used by provider APIs
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::System => "system",
+            Self::User => "user",
+            Self::Assistant => "assistant",
+            Self::Tool => "tool",
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Role {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "system" => Ok(Self::System),
+            "user" => Ok(Self::User),
+            "assistant" => Ok(Self::Assistant),
+            "tool" => Ok(Self::Tool),
+            _ => Err(AppError::invalid_input(format!("Unknown message role: {}", s))),
+        }
+    }
+}
+
+/// How an assistant message was generated
+///
+/// The fields are serialized inline with the message, so messages stored
+/// before metadata existed read back with everything unset.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MessageMetadata {
+    /// Id of the provider that generated the message
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    /// Model that generated the message
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Why the model stopped generating the message
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finish_reason: Option<String>,
+    /// Tokens used by the request that generated the message
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<CompletionUsage>,
+    /// Milliseconds between sending the request and receiving the reply
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+    /// Why generating the message failed, if it did
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
 
 /// Represents a chat message
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Message {
-    /// The role of the message sender (system, user, assistant)
-    pub role: String,
-    /// The content of the message
-    pub content: String,
-    /// Timestamp of when the message was created
-    pub timestamp: i64,
+    /// Unique identifier of the message
+    #[serde(default = "new_message_id")]
+    pub id: String,
+    /// Id of the message this one replies to, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<String>,
+    /// The author of the message
+    pub role: Role,
+    /// The content of the message; a plain string unless it has attachments
+    pub content: MessageContent,
+    /// When the message was created, serialized as milliseconds since the epoch
+    #[serde(with = "chrono::serde::ts_milliseconds")]
+    pub timestamp: DateTime<Utc>,
+    /// How the message was generated; empty for messages not written by a model
+    #[serde(flatten)]
+    pub metadata: MessageMetadata,
+}
+
+impl Message {
+    /// Creates a message timestamped with the current time
+    pub fn new<C: Into<MessageContent>>(role: Role, content: C) -> Self {
+        Self {
+            id: new_message_id(),
+            parent_id: None,
+            role,
+            content: content.into(),
+            timestamp: Utc::now(),
+            metadata: MessageMetadata::default(),
+        }
+    }
+
+    /// Whether generation stopped because it reached the token limit
+    pub fn is_truncated(&self) -> bool {
+        matches!(self.metadata.finish_reason.as_deref(), Some("length" | "max_tokens"))
+    }
+
+    /// Whether generating the message failed
+    pub fn is_failed(&self) -> bool {
+        self.metadata.error.is_some()
+    }
+}
+
+/// Generates a new random message id
+pub fn new_message_id() -> String {
+    uuid::Uuid::new_v4().to_string()
 }
 
 /// Represents chat completion parameters
@@ -31,6 +165,9 @@ pub struct ChatCompletionParams {
     pub max_tokens: i32,
     /// System prompt to use
     pub system_prompt: Option<String>,
+    /// Tools the model may call
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<ToolDefinition>,
 }
 
 /// Represents a chat completion response
@@ -40,10 +177,13 @@ pub struct ChatCompletion {
     pub message: Message,
     /// Usage statistics
     pub usage: CompletionUsage,
+    /// Why the model stopped generating, as reported by the provider
+    #[serde(default)]
+    pub finish_reason: Option<String>,
 }
 
 /// Represents token usage statistics
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct CompletionUsage {
     /// Number of prompt tokens used
     pub prompt_tokens: i32,
@@ -53,6 +193,33 @@ pub struct CompletionUsage {
     pub total_tokens: i32,
 }
 
+/// An incremental update of a streamed chat completion
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChatCompletionDelta {
+    /// A chunk of generated text
+    Content { text: String },
+    /// Usage statistics for the whole completion
+    Usage { usage: CompletionUsage },
+    /// Why the model stopped generating
+    Finish { reason: String },
+}
+
+/// Stream of deltas returned by a streaming chat completion
+pub type ChatCompletionStream = BoxStream<'static, AppResult<ChatCompletionDelta>>;
+
+/// Splits the result of parsing one stream event into per-delta results,
+/// so that it can be flattened into a `ChatCompletionStream`
+pub(crate) fn split_deltas(
+    result: AppResult<Vec<ChatCompletionDelta>>
+) -> stream::Iter<std::vec::IntoIter<AppResult<ChatCompletionDelta>>> {
+    let deltas = match result {
+        Ok(deltas) => deltas.into_iter().map(Ok).collect(),
+        Err(e) => vec![Err(e)],
+    };
+    stream::iter(deltas)
+}
+
 /// Trait that must be implemented by all AI providers
 #[async_trait]
 pub trait AIProvider: Send + Sync + Debug {
@@ -61,6 +228,11 @@ pub trait AIProvider: Send + Sync + Debug {
     
     /// Returns the available models
     fn available_models(&self) -> Vec<String>;
+
+    /// Whether a trailing assistant message is continued instead of answered
+    fn supports_prefill(&self) -> bool {
+        false
+    }
     
     /// Creates a chat completion
     async fn create_chat_completion(
@@ -68,25 +240,59 @@ pub trait AIProvider: Send + Sync + Debug {
         messages: Vec<Message>,
         params: ChatCompletionParams
     ) -> AppResult<ChatCompletion>;
+
+    /// Creates a chat completion, yielding deltas as they are generated
+    ///
+    /// Providers without streaming support fall back to a single content
+    /// delta carrying the whole response.
+    async fn create_chat_completion_stream(
+        &self,
+        messages: Vec<Message>,
+        params: ChatCompletionParams
+    ) -> AppResult<ChatCompletionStream> {
+        let completion = self.create_chat_completion(messages, params).await?;
+
+        let mut deltas = vec![
+            Ok(ChatCompletionDelta::Content { text: completion.message.content.text() }),
+            Ok(ChatCompletionDelta::Usage { usage: completion.usage }),
+        ];
+        if let Some(reason) = completion.finish_reason {
+            deltas.push(Ok(ChatCompletionDelta::Finish { reason }));
+        }
+        Ok(Box::pin(stream::iter(deltas)))
+    }
+    
+    /// Embeds texts as vectors for retrieval, one vector per text in order
+    ///
+    /// Providers without an embeddings API return an error.
+    async fn embed(&self, _texts: Vec<String>, _model: &str) -> AppResult<Vec<Vec<f32>>> {
+        Err(AppError::invalid_input(format!("{} does not support embeddings", self.name())))
+    }
     
     /// Validates the API key
     async fn validate_api_key(&self, api_key: &str) -> AppResult<bool>;
 }
 
-/// Factory for creating AI providers
-pub struct AIProviderFactory;
-
-impl AIProviderFactory {
-    /// Creates a new AI provider instance based on the provider name
-    pub async fn create_provider(
-        provider_name: &str,
-        _api_key: String,
-    ) -> AppResult<Arc<dyn AIProvider>> {
-        match provider_name {
-            // We'll implement these providers later
-            "openai" => Err(crate::utils::AppError::invalid_input("OpenAI provider not implemented yet")),
-            "anthropic" => Err(crate::utils::AppError::invalid_input("Anthropic provider not implemented yet")),
-            _ => Err(crate::utils::AppError::invalid_input("Unknown provider")),
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_messages_serialize_compatibly() {
+        // Messages stored before roles and metadata were typed
+        let stored = r#"{"role":"assistant","content":"Hi","timestamp":1700000000000,"provider":"openai","model":"gpt-4o"}"#;
+        let message: Message = serde_json::from_str(stored).unwrap();
+        assert_eq!(message.role, Role::Assistant);
+        assert_eq!(message.timestamp.timestamp_millis(), 1_700_000_000_000);
+        assert_eq!(message.metadata.model.as_deref(), Some("gpt-4o"));
+
+        let mut message = Message::new(Role::Tool, "42");
+        message.metadata.usage = Some(CompletionUsage { prompt_tokens: 3, completion_tokens: 1, total_tokens: 4 });
+        let value = serde_json::to_value(&message).unwrap();
+        assert_eq!(value["role"], "tool");
+        assert_eq!(value["usage"]["total_tokens"], 4);
+        assert!(value["timestamp"].is_i64());
+        assert!(value.get("error").is_none());
+        assert!(serde_json::from_str::<Message>(r#"{"role":"robot","content":"","timestamp":0}"#).is_err());
     }
-} 
\ No newline at end of file
+}
@@ -0,0 +1,540 @@
+//! Ollama provider
+//!
+//! This module implements the `AIProvider` trait on top of the Ollama HTTP
+//! API, for models running on a local or self-hosted server.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, RwLock};
+
+use async_trait::async_trait;
+use futures::stream::{self, BoxStream, StreamExt};
+use serde::{Deserialize, Serialize};
+use log::warn;
+
+use crate::utils::{AppError, AppResult};
+use super::registry::{ApiKeyPolicy, ConfigField, ConfigFieldKind, ProviderDescriptor, ProviderRegistry};
+use super::{
+    split_deltas, AIProvider, ChatCompletion, ChatCompletionDelta, ChatCompletionParams,
+    content, new_message_id, ChatCompletionStream, CompletionUsage, ContentPart, Message,
+    MessageContent, PartSource, Role, ToolCall, ToolDefinition,
+};
+
+/// Address of a default local Ollama installation
+pub const DEFAULT_BASE_URL: &str = "http://localhost:11434";
+
+/// Registers the Ollama provider
+pub(crate) fn register(registry: &ProviderRegistry) {
+    registry.register(
+        ProviderDescriptor::new("ollama", "Ollama", ApiKeyPolicy::Unsupported, |context| async move {
+            let base_url = context.config.option_str("base_url").unwrap_or(DEFAULT_BASE_URL);
+            let provider = OllamaProvider::new(base_url);
+
+            // The server may simply not be running yet; keep the provider usable
+            if let Err(e) = provider.refresh_models().await {
+                warn!("Failed to fetch Ollama models from {}: {}", base_url, e);
+            }
+            let provider: Arc<dyn AIProvider> = Arc::new(provider);
+            Ok(provider)
+        })
+        .with_default_embedding_model("nomic-embed-text")
+        .with_field(ConfigField::new("base_url", "Server URL", ConfigFieldKind::Url)),
+    );
+}
+
+/// AI provider backed by an Ollama server
+#[derive(Debug)]
+pub struct OllamaProvider {
+    /// HTTP client used for all requests
+    client: reqwest::Client,
+    /// Base URL of the server, without a trailing slash
+    base_url: String,
+    /// Models installed on the server, as of the last refresh
+    models: RwLock<Vec<String>>,
+}
+
+impl OllamaProvider {
+    /// Creates a new provider for the server at `base_url`
+    ///
+    /// The model list starts out empty; call `refresh_models` to fill it.
+    pub fn new<S: Into<String>>(base_url: S) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            models: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Builds the full URL for an API path
+    fn endpoint(&self, path: &str) -> String {
+        format!("{}/{}", self.base_url, path)
+    }
+
+    /// Fetches the list of installed models from the server
+    pub async fn refresh_models(&self) -> AppResult<Vec<String>> {
+        let response = self.client.get(self.endpoint("api/tags")).send().await?;
+        if !response.status().is_success() {
+            return Err(error_from_response(response).await);
+        }
+
+        let body: TagsResponse = response.json().await?;
+        let models: Vec<String> = body.models.into_iter().map(|model| model.name).collect();
+
+        *self.models.write().map_err(|_| AppError::internal("Model list lock poisoned"))? = models.clone();
+        Ok(models)
+    }
+
+    /// Builds the request body for a chat completion
+    fn build_request(messages: &[Message], params: &ChatCompletionParams, stream: bool) -> ChatRequest {
+        let mut wire_messages = Vec::with_capacity(messages.len() + 1);
+
+        if let Some(system_prompt) = &params.system_prompt {
+            wire_messages.push(WireMessage {
+                role: "system".to_string(),
+                content: system_prompt.clone(),
+                ..WireMessage::default()
+            });
+        }
+
+        wire_messages.extend(messages.iter().flat_map(wire_messages_for));
+
+        ChatRequest {
+            model: params.model.clone(),
+            messages: wire_messages,
+            tools: params.tools.iter().map(WireTool::from).collect(),
+            stream,
+            options: ModelOptions {
+                temperature: params.temperature,
+                num_predict: (params.max_tokens > 0).then_some(params.max_tokens),
+            },
+        }
+    }
+
+    /// Sends a chat request, failing on unsuccessful responses
+    async fn send_chat_request(&self, request: &ChatRequest) -> AppResult<reqwest::Response> {
+        let response = self.client
+            .post(self.endpoint("api/chat"))
+            .json(request)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(error_from_response(response).await);
+        }
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl AIProvider for OllamaProvider {
+    fn name(&self) -> &str {
+        "ollama"
+    }
+
+    fn available_models(&self) -> Vec<String> {
+        self.models
+            .read()
+            .map(|models| models.clone())
+            .unwrap_or_default()
+    }
+
+    async fn create_chat_completion(
+        &self,
+        messages: Vec<Message>,
+        params: ChatCompletionParams
+    ) -> AppResult<ChatCompletion> {
+        let request = Self::build_request(&messages, &params, false);
+        let response = self.send_chat_request(&request).await?;
+
+        let body: ChatResponse = response.json().await?;
+        let usage = body.usage();
+        let message = body.message.unwrap_or_default();
+        let calls = message.tool_calls
+            .into_iter()
+            .map(|call| ToolCall {
+                // Ollama does not identify calls, so results are matched by order
+                id: new_message_id(),
+                name: call.function.name,
+                arguments: call.function.arguments,
+            })
+            .collect();
+
+        Ok(ChatCompletion {
+            message: Message::new(
+                message.role.parse().unwrap_or(Role::Assistant),
+                MessageContent::reply(message.content, calls),
+            ),
+            usage,
+            finish_reason: body.done_reason,
+        })
+    }
+
+    async fn create_chat_completion_stream(
+        &self,
+        messages: Vec<Message>,
+        params: ChatCompletionParams
+    ) -> AppResult<ChatCompletionStream> {
+        let request = Self::build_request(&messages, &params, true);
+        let response = self.send_chat_request(&request).await?;
+
+        let deltas = json_lines(response)
+            .flat_map(|line| split_deltas(line.and_then(|line| parse_stream_line(&line))));
+        Ok(Box::pin(deltas))
+    }
+
+    async fn embed(&self, texts: Vec<String>, model: &str) -> AppResult<Vec<Vec<f32>>> {
+        let count = texts.len();
+        let response = self.client
+            .post(self.endpoint("api/embed"))
+            .json(&EmbedRequest { model: model.to_string(), input: texts })
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(error_from_response(response).await);
+        }
+
+        let body: EmbedResponse = response.json().await?;
+        if body.embeddings.len() != count {
+            return Err(AppError::api(format!("Expected {} embeddings, got {}", count, body.embeddings.len())));
+        }
+        Ok(body.embeddings)
+    }
+
+    /// Ollama has no API keys, so this only checks that the server is reachable
+    async fn validate_api_key(&self, _api_key: &str) -> AppResult<bool> {
+        let response = self.client.get(self.endpoint("api/tags")).send().await?;
+        Ok(response.status().is_success())
+    }
+}
+
+/// Converts an unsuccessful response into an API error
+async fn error_from_response(response: reqwest::Response) -> AppError {
+    let status = response.status();
+    let text = response.text().await.unwrap_or_default();
+
+    match serde_json::from_str::<ErrorResponse>(&text) {
+        Ok(ErrorResponse { error }) => AppError::api(format!("{}: {}", status, error)),
+        Err(_) => AppError::api(format!("{}: {}", status, text)),
+    }
+}
+
+/// Splits a streaming response body into lines of newline-delimited JSON
+fn json_lines(response: reqwest::Response) -> BoxStream<'static, AppResult<String>> {
+    let state = (response.bytes_stream().boxed(), Vec::new(), VecDeque::new(), false);
+
+    stream::unfold(state, |(mut body, mut buffer, mut pending, mut finished)| async move {
+        loop {
+            if let Some(line) = pending.pop_front() {
+                return Some((Ok(line), (body, buffer, pending, finished)));
+            }
+            if finished {
+                return None;
+            }
+
+            match body.next().await {
+                Some(Ok(chunk)) => {
+                    buffer.extend_from_slice(&chunk);
+                    while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
+                        let line: Vec<u8> = buffer.drain(..=pos).collect();
+                        let line = String::from_utf8_lossy(&line).trim().to_string();
+                        if !line.is_empty() {
+                            pending.push_back(line);
+                        }
+                    }
+                }
+                Some(Err(e)) => {
+                    finished = true;
+                    return Some((Err(e.into()), (body, buffer, pending, finished)));
+                }
+                None => {
+                    finished = true;
+                    let line = String::from_utf8_lossy(&std::mem::take(&mut buffer)).trim().to_string();
+                    if !line.is_empty() {
+                        pending.push_back(line);
+                    }
+                }
+            }
+        }
+    })
+    .boxed()
+}
+
+/// Converts one line of a streamed chat response into deltas
+fn parse_stream_line(line: &str) -> AppResult<Vec<ChatCompletionDelta>> {
+    let chunk: ChatResponse = serde_json::from_str(line)?;
+    if let Some(error) = chunk.error {
+        return Err(AppError::api(error));
+    }
+
+    let mut deltas = Vec::new();
+    if let Some(text) = chunk.message.as_ref().map(|message| &message.content).filter(|text| !text.is_empty()) {
+        deltas.push(ChatCompletionDelta::Content { text: text.clone() });
+    }
+    if chunk.done {
+        deltas.push(ChatCompletionDelta::Usage { usage: chunk.usage() });
+        if let Some(reason) = chunk.done_reason {
+            deltas.push(ChatCompletionDelta::Finish { reason });
+        }
+    }
+    Ok(deltas)
+}
+
+/// Request body of the chat endpoint
+#[derive(Debug, Serialize)]
+struct ChatRequest {
+    model: String,
+    messages: Vec<WireMessage>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<WireTool>,
+    stream: bool,
+    options: ModelOptions,
+}
+
+/// A tool definition as represented on the wire
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum WireTool {
+    Function { function: ToolDefinition },
+}
+
+impl From<&ToolDefinition> for WireTool {
+    fn from(definition: &ToolDefinition) -> Self {
+        Self::Function { function: definition.clone() }
+    }
+}
+
+/// Sampling options understood by Ollama
+#[derive(Debug, Serialize)]
+struct ModelOptions {
+    temperature: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_predict: Option<i32>,
+}
+
+/// A message as represented on the wire
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct WireMessage {
+    role: String,
+    content: String,
+    /// Base64 encoded images for vision models
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    images: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tool_calls: Vec<WireToolCall>,
+}
+
+/// A tool call as represented on the wire
+#[derive(Debug, Serialize, Deserialize)]
+struct WireToolCall {
+    function: WireFunctionCall,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct WireFunctionCall {
+    name: String,
+    arguments: serde_json::Value,
+}
+
+/// Translates a message, sending images separately and inlining text files
+///
+/// Every tool result becomes a message of its own.
+fn wire_messages_for(message: &Message) -> Vec<WireMessage> {
+    if message.role == Role::Tool {
+        return message.content
+            .tool_results()
+            .map(|result| WireMessage {
+                role: "tool".to_string(),
+                content: result.content.clone(),
+                ..WireMessage::default()
+            })
+            .collect();
+    }
+
+    let mut texts = Vec::new();
+    let mut images = Vec::new();
+    let mut tool_calls = Vec::new();
+    for part in &message.content.parts {
+        match part {
+            ContentPart::Text { text } => texts.push(text.clone()),
+            ContentPart::Image { source: PartSource::Base64 { data }, .. } => images.push(data.clone()),
+            ContentPart::ToolCall(call) => tool_calls.push(WireToolCall {
+                function: WireFunctionCall { name: call.name.clone(), arguments: call.arguments.clone() },
+            }),
+            ContentPart::ToolResult(_) => {}
+            _ => texts.push(content::inline_text_file(part).unwrap_or_else(|_| content::unsupported_part(part))),
+        }
+    }
+
+    vec![WireMessage {
+        role: message.role.to_string(),
+        content: texts.join("\n\n"),
+        images,
+        tool_calls,
+    }]
+}
+
+/// Response body of the chat endpoint, or one line of a streamed response
+#[derive(Debug, Deserialize)]
+struct ChatResponse {
+    message: Option<WireMessage>,
+    #[serde(default)]
+    done: bool,
+    done_reason: Option<String>,
+    #[serde(default)]
+    prompt_eval_count: i32,
+    #[serde(default)]
+    eval_count: i32,
+    error: Option<String>,
+}
+
+impl ChatResponse {
+    /// Token usage as reported by the final response
+    fn usage(&self) -> CompletionUsage {
+        CompletionUsage {
+            prompt_tokens: self.prompt_eval_count,
+            completion_tokens: self.eval_count,
+            total_tokens: self.prompt_eval_count + self.eval_count,
+        }
+    }
+}
+
+/// Response body of the tags endpoint
+#[derive(Debug, Deserialize)]
+struct TagsResponse {
+    models: Vec<ModelTag>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelTag {
+    name: String,
+}
+
+/// Request body of the embed endpoint
+#[derive(Debug, Serialize)]
+struct EmbedRequest {
+    model: String,
+    input: Vec<String>,
+}
+
+/// Response body of the embed endpoint
+#[derive(Debug, Deserialize)]
+struct EmbedResponse {
+    embeddings: Vec<Vec<f32>>,
+}
+
+/// Error body returned by the server
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::{Matcher, Server};
+    use serde_json::json;
+
+    fn params() -> ChatCompletionParams {
+        ChatCompletionParams {
+            model: "llama3.2:latest".to_string(),
+            temperature: 0.2,
+            max_tokens: 64,
+            system_prompt: None,
+            tools: Vec::new(),
+        }
+    }
+
+    fn user_message(content: &str) -> Message {
+        Message::new(Role::User, content)
+    }
+
+    #[tokio::test]
+    async fn test_refresh_models() {
+        let mut server = Server::new_async().await;
+        server.mock("GET", "/api/tags")
+            .with_status(200)
+            .with_body(json!({
+                "models": [
+                    { "name": "llama3.2:latest", "size": 2019393189 },
+                    { "name": "qwen2.5-coder:7b", "size": 4683087332u64 }
+                ]
+            }).to_string())
+            .create_async()
+            .await;
+
+        let provider = OllamaProvider::new(server.url());
+        assert!(provider.available_models().is_empty());
+
+        provider.refresh_models().await.unwrap();
+        assert_eq!(provider.available_models(), vec!["llama3.2:latest", "qwen2.5-coder:7b"]);
+    }
+
+    #[tokio::test]
+    async fn test_create_chat_completion() {
+        let mut server = Server::new_async().await;
+        let mock = server.mock("POST", "/api/chat")
+            .match_body(Matcher::Json(json!({
+                "model": "llama3.2:latest",
+                "messages": [{ "role": "user", "content": "Hello" }],
+                "stream": false,
+                "options": { "temperature": 0.2, "num_predict": 64 }
+            })))
+            .with_status(200)
+            .with_body(json!({
+                "model": "llama3.2:latest",
+                "message": { "role": "assistant", "content": "Hi there!" },
+                "done": true,
+                "done_reason": "stop",
+                "prompt_eval_count": 26,
+                "eval_count": 4
+            }).to_string())
+            .create_async()
+            .await;
+
+        let provider = OllamaProvider::new(server.url());
+        let completion = provider
+            .create_chat_completion(vec![user_message("Hello")], params())
+            .await
+            .unwrap();
+
+        mock.assert_async().await;
+        assert_eq!(completion.message.content, "Hi there!");
+        assert_eq!(completion.usage.total_tokens, 30);
+        assert_eq!(completion.finish_reason.as_deref(), Some("stop"));
+    }
+
+    #[tokio::test]
+    async fn test_create_chat_completion_stream() {
+        let body = [
+            r#"{"model":"llama3.2:latest","message":{"role":"assistant","content":"Hi"},"done":false}"#,
+            r#"{"model":"llama3.2:latest","message":{"role":"assistant","content":" there!"},"done":false}"#,
+            r#"{"model":"llama3.2:latest","message":{"role":"assistant","content":""},"done":true,"done_reason":"stop","prompt_eval_count":26,"eval_count":4}"#,
+        ].join("\n");
+
+        let mut server = Server::new_async().await;
+        server.mock("POST", "/api/chat")
+            .match_body(Matcher::PartialJson(json!({ "stream": true })))
+            .with_status(200)
+            .with_header("content-type", "application/x-ndjson")
+            .with_body(body)
+            .create_async()
+            .await;
+
+        let provider = OllamaProvider::new(server.url());
+        let deltas: Vec<_> = provider
+            .create_chat_completion_stream(vec![user_message("Hello")], params())
+            .await
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+            .await;
+
+        assert_eq!(deltas, vec![
+            ChatCompletionDelta::Content { text: "Hi".to_string() },
+            ChatCompletionDelta::Content { text: " there!".to_string() },
+            ChatCompletionDelta::Usage {
+                usage: CompletionUsage { prompt_tokens: 26, completion_tokens: 4, total_tokens: 30 },
+            },
+            ChatCompletionDelta::Finish { reason: "stop".to_string() },
+        ]);
+    }
+}
@@ -0,0 +1,882 @@
+//! OpenAI provider
+//!
+//! This module implements the `AIProvider` trait on top of the OpenAI
+//! Chat Completions API. The same provider also serves any server speaking
+//! the OpenAI wire format at a different base URL.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::{future, StreamExt};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Client, Method, RequestBuilder, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use crate::utils::{AppError, AppResult};
+use super::registry::{ApiKeyPolicy, ConfigField, ConfigFieldKind, ProviderDescriptor, ProviderRegistry};
+use super::{
+    sse, split_deltas, AIProvider, ChatCompletion, ChatCompletionDelta, ChatCompletionParams,
+    content, ChatCompletionStream, CompletionUsage, ContentPart, Message, MessageContent,
+    PartSource, Role, ToolCall, ToolDefinition,
+};
+
+/// Base URL of the public OpenAI API
+const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+
+/// Models offered for selection
+const MODELS: &[&str] = &[
+    "gpt-4o",
+    "gpt-4o-mini",
+    "gpt-4-turbo",
+    "gpt-3.5-turbo",
+];
+
+/// Registers the OpenAI provider and the generic OpenAI-compatible provider
+pub(crate) fn register(registry: &ProviderRegistry) {
+    registry.register(
+        ProviderDescriptor::new("openai", "OpenAI", ApiKeyPolicy::Required, |context| async move {
+            let provider: Arc<dyn AIProvider> = Arc::new(OpenAIProvider::new(context.require_api_key()?));
+            Ok(provider)
+        })
+        .with_default_model("gpt-4o-mini")
+        .with_default_embedding_model("text-embedding-3-small"),
+    );
+
+    registry.register(
+        ProviderDescriptor::new(
+            "openai-compatible",
+            "OpenAI-compatible endpoint",
+            ApiKeyPolicy::Optional,
+            |context| async move {
+                let base_url = context.config
+                    .option_str("base_url")
+                    .ok_or_else(|| AppError::invalid_input("OpenAI-compatible endpoint has no base URL"))?;
+                let provider = OpenAIProvider::compatible(&context.id, base_url)
+                    .with_api_key(context.api_key.clone())
+                    .with_headers(&context.config.option_map("headers"))?
+                    .with_models(context.config.option_list("models"));
+                let provider: Arc<dyn AIProvider> = Arc::new(provider);
+                Ok(provider)
+            },
+        )
+        .multiple_instances()
+        .with_field(ConfigField::new("base_url", "Base URL", ConfigFieldKind::Url).required())
+        .with_field(ConfigField::new("headers", "Extra headers", ConfigFieldKind::StringMap))
+        .with_field(ConfigField::new("models", "Models", ConfigFieldKind::StringList)),
+    );
+}
+
+/// AI provider backed by the OpenAI Chat Completions API
+#[derive(Debug, Clone)]
+pub struct OpenAIProvider {
+    /// HTTP client used for all requests
+    client: Client,
+    /// Name reported by the provider
+    name: String,
+    /// API key sent as a bearer token, if the server requires one
+    api_key: Option<String>,
+    /// Base URL of the API, without a trailing slash
+    base_url: String,
+    /// Additional headers sent with every request
+    headers: HeaderMap,
+    /// Models offered for selection
+    models: Vec<String>,
+}
+
+impl OpenAIProvider {
+    /// Creates a new provider using the public OpenAI endpoint
+    pub fn new(api_key: String) -> Self {
+        Self {
+            client: Client::new(),
+            name: "openai".to_string(),
+            api_key: Some(api_key),
+            base_url: DEFAULT_BASE_URL.to_string(),
+            headers: HeaderMap::new(),
+            models: MODELS.iter().map(|model| model.to_string()).collect(),
+        }
+    }
+
+    /// Creates a provider for an OpenAI-compatible server
+    ///
+    /// The provider starts without an API key or models; use the `with_*`
+    /// methods to configure them.
+    pub fn compatible<N: Into<String>, U: Into<String>>(name: N, base_url: U) -> Self {
+        Self {
+            client: Client::new(),
+            name: name.into(),
+            api_key: None,
+            base_url: String::new(),
+            headers: HeaderMap::new(),
+            models: Vec::new(),
+        }
+        .with_base_url(base_url)
+    }
+
+    /// Overrides the base URL of the API
+    pub fn with_base_url<S: Into<String>>(mut self, base_url: S) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Sets the API key, or removes it when `None`
+    pub fn with_api_key(mut self, api_key: Option<String>) -> Self {
+        self.api_key = api_key.filter(|key| !key.is_empty());
+        self
+    }
+
+    /// Sets additional headers sent with every request
+    ///
+    /// # Errors
+    /// Returns an error if a header name or value is not valid HTTP
+    pub fn with_headers(mut self, headers: &HashMap<String, String>) -> AppResult<Self> {
+        let mut header_map = HeaderMap::with_capacity(headers.len());
+        for (name, value) in headers {
+            let header_name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| AppError::invalid_input(format!("Invalid header name: {}", name)))?;
+            let header_value = HeaderValue::from_str(value)
+                .map_err(|_| AppError::invalid_input(format!("Invalid value for header: {}", name)))?;
+            header_map.insert(header_name, header_value);
+        }
+        self.headers = header_map;
+        Ok(self)
+    }
+
+    /// Sets the models offered for selection
+    pub fn with_models(mut self, models: Vec<String>) -> Self {
+        self.models = models;
+        self
+    }
+
+    /// Builds the full URL for an API path
+    fn endpoint(&self, path: &str) -> String {
+        format!("{}/{}", self.base_url, path)
+    }
+
+    /// Starts a request with the configured headers and the given API key
+    fn request(&self, method: Method, path: &str, api_key: Option<&str>) -> RequestBuilder {
+        let request = self.client
+            .request(method, self.endpoint(path))
+            .headers(self.headers.clone());
+
+        match api_key {
+            Some(api_key) => request.bearer_auth(api_key),
+            None => request,
+        }
+    }
+
+    /// Builds the request body for a chat completion
+    fn build_request(messages: &[Message], params: &ChatCompletionParams) -> ChatRequest {
+        let mut wire_messages = Vec::with_capacity(messages.len() + 1);
+
+        if let Some(system_prompt) = &params.system_prompt {
+            wire_messages.push(WireMessage::text("system", system_prompt.clone()));
+        }
+
+        for message in messages {
+            if message.role == Role::Tool {
+                // Every result is a message of its own
+                wire_messages.extend(message.content.tool_results().map(|result| WireMessage {
+                    tool_call_id: Some(result.call_id.clone()),
+                    ..WireMessage::text("tool", result.content.clone())
+                }));
+                continue;
+            }
+
+            let tool_calls: Vec<WireToolCall> = message.content.tool_calls().map(WireToolCall::from).collect();
+            let content = wire_content(&message.content);
+            wire_messages.push(WireMessage {
+                role: message.role.to_string(),
+                content: (tool_calls.is_empty() || !content.is_empty()).then_some(content),
+                tool_calls,
+                tool_call_id: None,
+            });
+        }
+
+        ChatRequest {
+            model: params.model.clone(),
+            messages: wire_messages,
+            temperature: params.temperature,
+            max_tokens: (params.max_tokens > 0).then_some(params.max_tokens),
+            tools: params.tools.iter().map(WireTool::from).collect(),
+            stream: false,
+            stream_options: None,
+        }
+    }
+
+    /// Sends a chat completion request, failing on unsuccessful responses
+    async fn send_chat_request(&self, request: &ChatRequest) -> AppResult<reqwest::Response> {
+        let response = self
+            .request(Method::POST, "chat/completions", self.api_key.as_deref())
+            .json(request)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(error_from_response(response).await);
+        }
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl AIProvider for OpenAIProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn available_models(&self) -> Vec<String> {
+        self.models.clone()
+    }
+
+    async fn create_chat_completion(
+        &self,
+        messages: Vec<Message>,
+        params: ChatCompletionParams
+    ) -> AppResult<ChatCompletion> {
+        let request = Self::build_request(&messages, &params);
+        let response = self.send_chat_request(&request).await?;
+
+        let body: ChatResponse = response.json().await?;
+        let choice = body.choices
+            .into_iter()
+            .next()
+            .ok_or_else(|| AppError::api("Response contained no choices"))?;
+        let usage = body.usage.unwrap_or_default();
+
+        let calls = choice.message.tool_calls.into_iter().map(ToolCall::from).collect();
+        Ok(ChatCompletion {
+            message: Message::new(
+                choice.message.role.parse().unwrap_or(Role::Assistant),
+                MessageContent::reply(choice.message.content.unwrap_or_default(), calls),
+            ),
+            usage: CompletionUsage {
+                prompt_tokens: usage.prompt_tokens,
+                completion_tokens: usage.completion_tokens,
+                total_tokens: usage.total_tokens,
+            },
+            finish_reason: choice.finish_reason,
+        })
+    }
+
+    async fn create_chat_completion_stream(
+        &self,
+        messages: Vec<Message>,
+        params: ChatCompletionParams
+    ) -> AppResult<ChatCompletionStream> {
+        let mut request = Self::build_request(&messages, &params);
+        request.stream = true;
+        request.stream_options = Some(StreamOptions { include_usage: true });
+        let response = self.send_chat_request(&request).await?;
+
+        let deltas = sse::events(response)
+            .take_while(|event| future::ready(!matches!(event, Ok(event) if event.data == "[DONE]")))
+            .flat_map(|event| split_deltas(event.and_then(|event| parse_stream_chunk(&event.data))));
+        Ok(Box::pin(deltas))
+    }
+
+    async fn embed(&self, texts: Vec<String>, model: &str) -> AppResult<Vec<Vec<f32>>> {
+        let count = texts.len();
+        let response = self
+            .request(Method::POST, "embeddings", self.api_key.as_deref())
+            .json(&EmbeddingRequest { model: model.to_string(), input: texts })
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(error_from_response(response).await);
+        }
+
+        let mut body: EmbeddingResponse = response.json().await?;
+        if body.data.len() != count {
+            return Err(AppError::api(format!("Expected {} embeddings, got {}", count, body.data.len())));
+        }
+        body.data.sort_by_key(|embedding| embedding.index);
+        Ok(body.data.into_iter().map(|embedding| embedding.embedding).collect())
+    }
+
+    async fn validate_api_key(&self, api_key: &str) -> AppResult<bool> {
+        let api_key = Some(api_key).filter(|key| !key.is_empty());
+        let response = self
+            .request(Method::GET, "models", api_key)
+            .send()
+            .await?;
+
+        match response.status() {
+            status if status.is_success() => Ok(true),
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Ok(false),
+            _ => Err(error_from_response(response).await),
+        }
+    }
+}
+
+/// Converts an unsuccessful response into an API error
+async fn error_from_response(response: reqwest::Response) -> AppError {
+    let status = response.status();
+    let text = response.text().await.unwrap_or_default();
+
+    match serde_json::from_str::<ErrorResponse>(&text) {
+        Ok(ErrorResponse { error }) => match error.kind {
+            Some(kind) => AppError::api(format!("{} ({}): {}", status, kind, error.message)),
+            None => AppError::api(format!("{}: {}", status, error.message)),
+        },
+        Err(_) => AppError::api(format!("{}: {}", status, text)),
+    }
+}
+
+/// Converts one chunk of a streamed completion into deltas
+fn parse_stream_chunk(data: &str) -> AppResult<Vec<ChatCompletionDelta>> {
+    let chunk: StreamChunk = serde_json::from_str(data)?;
+    if let Some(error) = chunk.error {
+        return Err(match error.kind {
+            Some(kind) => AppError::api(format!("{}: {}", kind, error.message)),
+            None => AppError::api(error.message),
+        });
+    }
+
+    let mut deltas = Vec::new();
+    for choice in chunk.choices {
+        if let Some(text) = choice.delta.content.filter(|text| !text.is_empty()) {
+            deltas.push(ChatCompletionDelta::Content { text });
+        }
+        if let Some(reason) = choice.finish_reason {
+            deltas.push(ChatCompletionDelta::Finish { reason });
+        }
+    }
+    if let Some(usage) = chunk.usage {
+        deltas.push(ChatCompletionDelta::Usage {
+            usage: CompletionUsage {
+                prompt_tokens: usage.prompt_tokens,
+                completion_tokens: usage.completion_tokens,
+                total_tokens: usage.total_tokens,
+            },
+        });
+    }
+    Ok(deltas)
+}
+
+/// Request body of the Chat Completions endpoint
+#[derive(Debug, Serialize)]
+struct ChatRequest {
+    model: String,
+    messages: Vec<WireMessage>,
+    temperature: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<i32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<WireTool>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<StreamOptions>,
+}
+
+#[derive(Debug, Serialize)]
+struct StreamOptions {
+    include_usage: bool,
+}
+
+/// A message as represented on the wire
+#[derive(Debug, Serialize)]
+struct WireMessage {
+    role: String,
+    /// Null for assistant messages consisting of tool calls only
+    content: Option<WireContent>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tool_calls: Vec<WireToolCall>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_call_id: Option<String>,
+}
+
+impl WireMessage {
+    fn text(role: &str, text: String) -> Self {
+        Self {
+            role: role.to_string(),
+            content: Some(WireContent::Text(text)),
+            tool_calls: Vec::new(),
+            tool_call_id: None,
+        }
+    }
+}
+
+/// A tool definition as represented on the wire
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum WireTool {
+    Function { function: ToolDefinition },
+}
+
+impl From<&ToolDefinition> for WireTool {
+    fn from(definition: &ToolDefinition) -> Self {
+        Self::Function { function: definition.clone() }
+    }
+}
+
+/// A tool call as represented on the wire, with its arguments as a JSON string
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum WireToolCall {
+    Function { id: String, function: WireFunctionCall },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct WireFunctionCall {
+    name: String,
+    arguments: String,
+}
+
+impl From<&ToolCall> for WireToolCall {
+    fn from(call: &ToolCall) -> Self {
+        Self::Function {
+            id: call.id.clone(),
+            function: WireFunctionCall {
+                name: call.name.clone(),
+                arguments: call.arguments.to_string(),
+            },
+        }
+    }
+}
+
+impl From<WireToolCall> for ToolCall {
+    fn from(call: WireToolCall) -> Self {
+        let WireToolCall::Function { id, function } = call;
+        // Malformed arguments are passed on as a string for the tool to reject
+        let arguments = serde_json::from_str(&function.arguments)
+            .unwrap_or(serde_json::Value::String(function.arguments));
+        Self { id, name: function.name, arguments }
+    }
+}
+
+/// Message content, as a plain string or as parts for vision and files
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum WireContent {
+    Text(String),
+    Parts(Vec<WirePart>),
+}
+
+impl WireContent {
+    fn is_empty(&self) -> bool {
+        match self {
+            Self::Text(text) => text.is_empty(),
+            Self::Parts(parts) => parts.is_empty(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum WirePart {
+    Text { text: String },
+    ImageUrl { image_url: ImageUrl },
+    File { file: WireFile },
+}
+
+#[derive(Debug, Serialize)]
+struct ImageUrl {
+    url: String,
+}
+
+#[derive(Debug, Serialize)]
+struct WireFile {
+    filename: String,
+    file_data: String,
+}
+
+/// Translates message content into text, image and file parts
+///
+/// Images and PDFs are sent as data URLs; text files are inlined. Tool
+/// calls and results are sent separately.
+fn wire_content(content: &MessageContent) -> WireContent {
+    if content.attachments().next().is_none() {
+        return WireContent::Text(content.text());
+    }
+
+    let parts = content.parts.iter().filter_map(|part| Some(match part {
+        ContentPart::ToolCall(_) | ContentPart::ToolResult(_) => return None,
+        ContentPart::Text { text } => WirePart::Text { text: text.clone() },
+        ContentPart::Image { mime_type, source: PartSource::Base64 { data } } => WirePart::ImageUrl {
+            image_url: ImageUrl { url: format!("data:{};base64,{}", mime_type, data) },
+        },
+        ContentPart::File { name, mime_type, source: PartSource::Base64 { data }, .. }
+            if mime_type == "application/pdf" =>
+        {
+            WirePart::File {
+                file: WireFile {
+                    filename: name.clone(),
+                    file_data: format!("data:{};base64,{}", mime_type, data),
+                },
+            }
+        }
+        _ => WirePart::Text {
+            text: content::inline_text_file(part).unwrap_or_else(|_| content::unsupported_part(part)),
+        },
+    }));
+    WireContent::Parts(parts.collect())
+}
+
+/// A message in a response
+#[derive(Debug, Deserialize)]
+struct ResponseMessage {
+    role: String,
+    content: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<WireToolCall>,
+}
+
+/// Response body of the Chat Completions endpoint
+#[derive(Debug, Deserialize)]
+struct ChatResponse {
+    choices: Vec<Choice>,
+    usage: Option<Usage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Choice {
+    message: ResponseMessage,
+    finish_reason: Option<String>,
+}
+
+/// One chunk of a streamed completion
+#[derive(Debug, Deserialize)]
+struct StreamChunk {
+    #[serde(default)]
+    choices: Vec<StreamChoice>,
+    usage: Option<Usage>,
+    error: Option<ErrorDetail>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamChoice {
+    delta: StreamDelta,
+    finish_reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamDelta {
+    content: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Usage {
+    prompt_tokens: i32,
+    completion_tokens: i32,
+    total_tokens: i32,
+}
+
+/// Request body of the embeddings endpoint
+#[derive(Debug, Serialize)]
+struct EmbeddingRequest {
+    model: String,
+    input: Vec<String>,
+}
+
+/// Response body of the embeddings endpoint
+#[derive(Debug, Deserialize)]
+struct EmbeddingResponse {
+    data: Vec<Embedding>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Embedding {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+/// Error body returned by the API
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: ErrorDetail,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorDetail {
+    message: String,
+    #[serde(rename = "type")]
+    kind: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::ai::ToolResult;
+    use mockito::{Matcher, Server};
+    use serde_json::json;
+
+    fn params() -> ChatCompletionParams {
+        ChatCompletionParams {
+            model: "gpt-4o-mini".to_string(),
+            temperature: 0.5,
+            max_tokens: 128,
+            system_prompt: Some("Be brief.".to_string()),
+            tools: Vec::new(),
+        }
+    }
+
+    fn user_message(content: &str) -> Message {
+        Message::new(Role::User, content)
+    }
+
+    #[tokio::test]
+    async fn test_create_chat_completion() {
+        let mut server = Server::new_async().await;
+        let mock = server.mock("POST", "/chat/completions")
+            .match_header("authorization", "Bearer test-key")
+            .match_body(Matcher::Json(json!({
+                "model": "gpt-4o-mini",
+                "messages": [
+                    { "role": "system", "content": "Be brief." },
+                    { "role": "user", "content": "Hello" }
+                ],
+                "temperature": 0.5,
+                "max_tokens": 128
+            })))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(json!({
+                "id": "chatcmpl-1",
+                "object": "chat.completion",
+                "choices": [{
+                    "index": 0,
+                    "message": { "role": "assistant", "content": "Hi there!" },
+                    "finish_reason": "stop"
+                }],
+                "usage": { "prompt_tokens": 12, "completion_tokens": 3, "total_tokens": 15 }
+            }).to_string())
+            .create_async()
+            .await;
+
+        let provider = OpenAIProvider::new("test-key".to_string()).with_base_url(server.url());
+        let completion = provider
+            .create_chat_completion(vec![user_message("Hello")], params())
+            .await
+            .unwrap();
+
+        mock.assert_async().await;
+        assert_eq!(completion.message.role, Role::Assistant);
+        assert_eq!(completion.message.content, "Hi there!");
+        assert_eq!(completion.usage.prompt_tokens, 12);
+        assert_eq!(completion.usage.completion_tokens, 3);
+        assert_eq!(completion.usage.total_tokens, 15);
+        assert_eq!(completion.finish_reason.as_deref(), Some("stop"));
+    }
+
+    #[tokio::test]
+    async fn test_create_chat_completion_stream() {
+        let body = [
+            r#"data: {"choices":[{"index":0,"delta":{"role":"assistant","content":""},"finish_reason":null}]}"#,
+            r#"data: {"choices":[{"index":0,"delta":{"content":"Hi"},"finish_reason":null}]}"#,
+            r#"data: {"choices":[{"index":0,"delta":{"content":" there!"},"finish_reason":null}]}"#,
+            r#"data: {"choices":[{"index":0,"delta":{},"finish_reason":"stop"}]}"#,
+            r#"data: {"choices":[],"usage":{"prompt_tokens":12,"completion_tokens":3,"total_tokens":15}}"#,
+            "data: [DONE]",
+        ].join("\n\n") + "\n\n";
+
+        let mut server = Server::new_async().await;
+        server.mock("POST", "/chat/completions")
+            .match_body(Matcher::PartialJson(json!({
+                "stream": true,
+                "stream_options": { "include_usage": true }
+            })))
+            .with_status(200)
+            .with_header("content-type", "text/event-stream")
+            .with_body(body)
+            .create_async()
+            .await;
+
+        let provider = OpenAIProvider::new("test-key".to_string()).with_base_url(server.url());
+        let deltas: Vec<_> = provider
+            .create_chat_completion_stream(vec![user_message("Hello")], params())
+            .await
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+            .await;
+
+        assert_eq!(deltas, vec![
+            ChatCompletionDelta::Content { text: "Hi".to_string() },
+            ChatCompletionDelta::Content { text: " there!".to_string() },
+            ChatCompletionDelta::Finish { reason: "stop".to_string() },
+            ChatCompletionDelta::Usage {
+                usage: CompletionUsage { prompt_tokens: 12, completion_tokens: 3, total_tokens: 15 },
+            },
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_tool_calls() {
+        let call = ToolCall {
+            id: "call_1".to_string(),
+            name: "weather".to_string(),
+            arguments: json!({ "city": "Oslo" }),
+        };
+        let history = vec![
+            user_message("Weather in Oslo?"),
+            Message::new(Role::Assistant, MessageContent::reply(String::new(), vec![call])),
+            Message::new(Role::Tool, vec![ContentPart::ToolResult(ToolResult::success("call_1", "Sunny"))]),
+        ];
+
+        let mut server = Server::new_async().await;
+        let mock = server.mock("POST", "/chat/completions")
+            .match_body(Matcher::PartialJson(json!({
+                "messages": [
+                    { "role": "system", "content": "Be brief." },
+                    { "role": "user", "content": "Weather in Oslo?" },
+                    { "role": "assistant", "content": null, "tool_calls": [{
+                        "type": "function",
+                        "id": "call_1",
+                        "function": { "name": "weather", "arguments": r#"{"city":"Oslo"}"# }
+                    }] },
+                    { "role": "tool", "content": "Sunny", "tool_call_id": "call_1" }
+                ],
+                "tools": [{
+                    "type": "function",
+                    "function": { "name": "weather", "description": "Current weather", "parameters": { "type": "object" } }
+                }]
+            })))
+            .with_status(200)
+            .with_body(json!({
+                "choices": [{
+                    "message": {
+                        "role": "assistant",
+                        "content": null,
+                        "tool_calls": [{
+                            "id": "call_2",
+                            "type": "function",
+                            "function": { "name": "weather", "arguments": r#"{"city":"Bergen"}"# }
+                        }]
+                    },
+                    "finish_reason": "tool_calls"
+                }]
+            }).to_string())
+            .create_async()
+            .await;
+
+        let provider = OpenAIProvider::new("test-key".to_string()).with_base_url(server.url());
+        let params = ChatCompletionParams {
+            tools: vec![ToolDefinition {
+                name: "weather".to_string(),
+                description: "Current weather".to_string(),
+                parameters: json!({ "type": "object" }),
+            }],
+            ..params()
+        };
+        let completion = provider.create_chat_completion(history, params).await.unwrap();
+
+        mock.assert_async().await;
+        assert_eq!(completion.message.content.text(), "");
+        let calls: Vec<_> = completion.message.content.tool_calls().collect();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].id, "call_2");
+        assert_eq!(calls[0].arguments, json!({ "city": "Bergen" }));
+    }
+
+    #[tokio::test]
+    async fn test_api_error_is_reported() {
+        let mut server = Server::new_async().await;
+        server.mock("POST", "/chat/completions")
+            .with_status(404)
+            .with_body(json!({
+                "error": { "message": "The model does not exist", "type": "invalid_request_error" }
+            }).to_string())
+            .create_async()
+            .await;
+
+        let provider = OpenAIProvider::new("test-key".to_string()).with_base_url(server.url());
+        let error = provider
+            .create_chat_completion(vec![user_message("Hello")], params())
+            .await
+            .unwrap_err();
+
+        match error {
+            AppError::Api(msg) => {
+                assert!(msg.contains("invalid_request_error"));
+                assert!(msg.contains("The model does not exist"));
+            }
+            other => panic!("Unexpected error: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_compatible_server() {
+        let mut server = Server::new_async().await;
+        let mock = server.mock("POST", "/v1/chat/completions")
+            .match_header("authorization", Matcher::Missing)
+            .match_header("x-gateway-team", "platform")
+            .with_status(200)
+            .with_body(json!({
+                "choices": [{
+                    "message": { "role": "assistant", "content": "Hello from vLLM" },
+                    "finish_reason": "stop"
+                }]
+            }).to_string())
+            .create_async()
+            .await;
+
+        let headers = HashMap::from([("X-Gateway-Team".to_string(), "platform".to_string())]);
+        let provider = OpenAIProvider::compatible("local-vllm", format!("{}/v1/", server.url()))
+            .with_api_key(Some(String::new()))
+            .with_headers(&headers)
+            .unwrap()
+            .with_models(vec!["mistral-7b".to_string()]);
+
+        assert_eq!(provider.name(), "local-vllm");
+        assert_eq!(provider.available_models(), vec!["mistral-7b"]);
+
+        let completion = provider
+            .create_chat_completion(vec![user_message("Hello")], params())
+            .await
+            .unwrap();
+
+        mock.assert_async().await;
+        assert_eq!(completion.message.content, "Hello from vLLM");
+        assert_eq!(completion.usage, CompletionUsage::default());
+    }
+
+    #[tokio::test]
+    async fn test_validate_api_key() {
+        let mut server = Server::new_async().await;
+        server.mock("GET", "/models")
+            .match_header("authorization", "Bearer good-key")
+            .with_status(200)
+            .with_body(r#"{"object":"list","data":[]}"#)
+            .create_async()
+            .await;
+        server.mock("GET", "/models")
+            .match_header("authorization", "Bearer bad-key")
+            .with_status(401)
+            .with_body(r#"{"error":{"message":"Incorrect API key provided","type":"invalid_request_error"}}"#)
+            .create_async()
+            .await;
+
+        let provider = OpenAIProvider::new(String::new()).with_base_url(server.url());
+        assert!(provider.validate_api_key("good-key").await.unwrap());
+        assert!(!provider.validate_api_key("bad-key").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_embed() {
+        let mut server = Server::new_async().await;
+        server.mock("POST", "/embeddings")
+            .match_body(Matcher::PartialJson(json!({
+                "model": "text-embedding-3-small",
+                "input": ["first", "second"]
+            })))
+            .with_status(200)
+            .with_body(json!({
+                "object": "list",
+                "data": [
+                    { "object": "embedding", "index": 1, "embedding": [0.0, 1.0] },
+                    { "object": "embedding", "index": 0, "embedding": [1.0, 0.0] }
+                ]
+            }).to_string())
+            .create_async()
+            .await;
+
+        let provider = OpenAIProvider::new("test-key".to_string()).with_base_url(server.url());
+        let embeddings = provider
+            .embed(vec!["first".to_string(), "second".to_string()], "text-embedding-3-small")
+            .await
+            .unwrap();
+        assert_eq!(embeddings, vec![vec![1.0, 0.0], vec![0.0, 1.0]]);
+    }
+}
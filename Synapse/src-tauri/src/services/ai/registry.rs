@@ -0,0 +1,416 @@
+//! Provider registry
+//!
+//! This module keeps track of the kinds of AI providers Synapse can talk to.
+//! Each provider module registers a descriptor with its id, display name,
+//! configuration schema and constructor. Commands, settings validation and
+//! provider creation all consult the registry instead of hardcoding names.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, OnceLock, RwLock};
+
+use futures::future::{BoxFuture, FutureExt};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::settings::{AIProviderSettings, ProviderConfig};
+use crate::utils::{AppError, AppResult};
+use super::{anthropic, ollama, openai, AIProvider, ChatCompletionParams};
+
+/// Whether a provider kind uses an API key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiKeyPolicy {
+    /// Requests fail without an API key
+    Required,
+    /// An API key is sent if one is stored
+    Optional,
+    /// The provider never uses an API key
+    Unsupported,
+}
+
+/// The type of value a configuration field holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfigFieldKind {
+    /// A free-form string
+    String,
+    /// An `http://` or `https://` URL
+    Url,
+    /// A list of strings
+    StringList,
+    /// A map from strings to strings
+    StringMap,
+}
+
+/// Describes one provider-specific option stored in `ProviderConfig::options`
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigField {
+    /// Key of the option
+    pub key: String,
+    /// Human readable label for the settings UI
+    pub label: String,
+    /// Type of the option's value
+    pub kind: ConfigFieldKind,
+    /// Whether the option must be set
+    pub required: bool,
+}
+
+impl ConfigField {
+    /// Creates a new optional field
+    pub fn new<K: Into<String>, L: Into<String>>(key: K, label: L, kind: ConfigFieldKind) -> Self {
+        Self {
+            key: key.into(),
+            label: label.into(),
+            kind,
+            required: false,
+        }
+    }
+
+    /// Marks the field as required
+    pub fn required(mut self) -> Self {
+        self.required = true;
+        self
+    }
+
+    /// Checks that a value matches the field's type
+    fn validate(&self, value: &Value) -> Result<(), String> {
+        let valid = match self.kind {
+            ConfigFieldKind::String => value.is_string(),
+            ConfigFieldKind::Url => value
+                .as_str()
+                .map(|url| url.starts_with("http://") || url.starts_with("https://"))
+                .unwrap_or(false),
+            ConfigFieldKind::StringList => value
+                .as_array()
+                .map(|items| items.iter().all(Value::is_string))
+                .unwrap_or(false),
+            ConfigFieldKind::StringMap => value
+                .as_object()
+                .map(|entries| entries.values().all(Value::is_string))
+                .unwrap_or(false),
+        };
+
+        if valid {
+            Ok(())
+        } else {
+            Err(format!("Invalid value for {}", self.label))
+        }
+    }
+}
+
+/// Everything a constructor needs to create a provider instance
+#[derive(Debug, Clone)]
+pub struct ProviderContext {
+    /// Id the provider is selected by
+    pub id: String,
+    /// The stored API key, if any
+    pub api_key: Option<String>,
+    /// The provider's configuration
+    pub config: ProviderConfig,
+}
+
+impl ProviderContext {
+    /// Returns the API key, failing if none is stored
+    pub fn require_api_key(&self) -> AppResult<String> {
+        self.api_key
+            .clone()
+            .filter(|key| !key.is_empty())
+            .ok_or_else(|| AppError::invalid_input(format!("No API key stored for provider: {}", self.id)))
+    }
+}
+
+/// Function creating a provider from its context
+pub type ProviderConstructor =
+    Arc<dyn Fn(ProviderContext) -> BoxFuture<'static, AppResult<Arc<dyn AIProvider>>> + Send + Sync>;
+
+/// Describes a kind of provider and how to create it
+#[derive(Clone, Serialize)]
+pub struct ProviderDescriptor {
+    /// Registry id of the provider kind
+    pub id: String,
+    /// Human readable name
+    pub display_name: String,
+    /// Whether the provider uses an API key
+    pub api_key: ApiKeyPolicy,
+    /// Whether several instances can be configured under their own ids
+    pub multiple_instances: bool,
+    /// Model selected when the user has not configured one
+    pub default_model: Option<String>,
+    /// Model used to embed documents when the user has not chosen one
+    pub default_embedding_model: Option<String>,
+    /// Provider-specific options
+    pub config_schema: Vec<ConfigField>,
+    /// Creates provider instances
+    #[serde(skip)]
+    constructor: ProviderConstructor,
+}
+
+impl ProviderDescriptor {
+    /// Creates a new descriptor
+    pub fn new<I, N, F, Fut>(id: I, display_name: N, api_key: ApiKeyPolicy, constructor: F) -> Self
+    where
+        I: Into<String>,
+        N: Into<String>,
+        F: Fn(ProviderContext) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = AppResult<Arc<dyn AIProvider>>> + Send + 'static,
+    {
+        Self {
+            id: id.into(),
+            display_name: display_name.into(),
+            api_key,
+            multiple_instances: false,
+            default_model: None,
+            default_embedding_model: None,
+            config_schema: Vec::new(),
+            constructor: Arc::new(move |context| constructor(context).boxed()),
+        }
+    }
+
+    /// Allows several instances of this kind to be configured
+    pub fn multiple_instances(mut self) -> Self {
+        self.multiple_instances = true;
+        self
+    }
+
+    /// Sets the model selected when the user has not configured one
+    pub fn with_default_model<S: Into<String>>(mut self, model: S) -> Self {
+        self.default_model = Some(model.into());
+        self
+    }
+
+    /// Sets the model used to embed documents when the user has not chosen one
+    pub fn with_default_embedding_model<S: Into<String>>(mut self, model: S) -> Self {
+        self.default_embedding_model = Some(model.into());
+        self
+    }
+
+    /// Adds a provider-specific option to the configuration schema
+    pub fn with_field(mut self, field: ConfigField) -> Self {
+        self.config_schema.push(field);
+        self
+    }
+
+    /// Builds the configuration used when the user has not saved one
+    pub fn default_config(&self) -> ProviderConfig {
+        let mut config = ProviderConfig::new(&self.id, &self.id);
+        if let Some(model) = &self.default_model {
+            config.model = model.clone();
+        }
+        config
+    }
+
+    /// Checks provider-specific options against the configuration schema
+    pub fn validate_options(&self, config: &ProviderConfig) -> Result<(), String> {
+        for field in &self.config_schema {
+            match config.options.get(&field.key) {
+                Some(value) => field.validate(value)?,
+                None if field.required => return Err(format!("{} is required", field.label)),
+                None => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for ProviderDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProviderDescriptor")
+            .field("id", &self.id)
+            .field("display_name", &self.display_name)
+            .field("api_key", &self.api_key)
+            .field("multiple_instances", &self.multiple_instances)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Registry of all known provider kinds
+#[derive(Debug, Default)]
+pub struct ProviderRegistry {
+    /// Descriptors keyed by provider kind
+    descriptors: RwLock<BTreeMap<String, ProviderDescriptor>>,
+}
+
+impl ProviderRegistry {
+    /// Creates an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a registry containing the built-in providers
+    pub fn with_builtins() -> Self {
+        let registry = Self::new();
+        openai::register(&registry);
+        anthropic::register(&registry);
+        ollama::register(&registry);
+        registry
+    }
+
+    /// Returns the application-wide registry
+    pub fn global() -> &'static ProviderRegistry {
+        static REGISTRY: OnceLock<ProviderRegistry> = OnceLock::new();
+        REGISTRY.get_or_init(Self::with_builtins)
+    }
+
+    /// Registers a provider kind, replacing any previous one with the same id
+    pub fn register(&self, descriptor: ProviderDescriptor) {
+        if let Ok(mut descriptors) = self.descriptors.write() {
+            descriptors.insert(descriptor.id.clone(), descriptor);
+        }
+    }
+
+    /// Looks up a provider kind
+    pub fn get(&self, kind: &str) -> Option<ProviderDescriptor> {
+        self.descriptors.read().ok()?.get(kind).cloned()
+    }
+
+    /// Lists all registered provider kinds
+    pub fn descriptors(&self) -> Vec<ProviderDescriptor> {
+        self.descriptors
+            .read()
+            .map(|descriptors| descriptors.values().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Resolves a provider id to its descriptor and configuration
+    ///
+    /// The id may name a configured provider, or a single-instance kind
+    /// that has not been configured yet, in which case defaults are used.
+    ///
+    /// # Errors
+    /// Returns an error if the id or its kind is unknown
+    pub fn resolve(
+        &self,
+        id: &str,
+        settings: &AIProviderSettings,
+    ) -> AppResult<(ProviderDescriptor, ProviderConfig)> {
+        if let Some(config) = settings.provider(id) {
+            let descriptor = self.get(&config.kind).ok_or_else(|| {
+                AppError::invalid_input(format!("Unknown provider kind: {}", config.kind))
+            })?;
+            return Ok((descriptor, config.clone()));
+        }
+
+        match self.get(id) {
+            Some(descriptor) if !descriptor.multiple_instances => {
+                let config = descriptor.default_config();
+                Ok((descriptor, config))
+            }
+            _ => Err(AppError::invalid_input(format!("Unknown provider: {}", id))),
+        }
+    }
+
+    /// Creates a provider instance by id
+    ///
+    /// # Errors
+    /// Returns an error if the provider is unknown or cannot be created
+    pub async fn create(
+        &self,
+        id: &str,
+        api_key: Option<String>,
+        settings: &AIProviderSettings,
+    ) -> AppResult<Arc<dyn AIProvider>> {
+        let (descriptor, config) = self.resolve(id, settings)?;
+        let api_key = match descriptor.api_key {
+            ApiKeyPolicy::Unsupported => None,
+            _ => api_key,
+        };
+
+        (descriptor.constructor)(ProviderContext {
+            id: id.to_string(),
+            api_key,
+            config,
+        })
+        .await
+    }
+
+    /// Creates a provider instance and the parameters configured for it
+    ///
+    /// Without a configured model, the provider's first available model is
+    /// used.
+    ///
+    /// # Errors
+    /// Returns an error if the provider cannot be created, or no model is
+    /// configured or available
+    pub async fn create_configured(
+        &self,
+        id: &str,
+        api_key: Option<String>,
+        settings: &AIProviderSettings,
+    ) -> AppResult<(Arc<dyn AIProvider>, ChatCompletionParams)> {
+        let (_, config) = self.resolve(id, settings)?;
+        let provider = self.create(id, api_key, settings).await?;
+
+        let model = if config.model.is_empty() {
+            provider.available_models()
+                .into_iter()
+                .next()
+                .ok_or_else(|| AppError::invalid_input(format!("No model configured for {}", id)))?
+        } else {
+            config.model
+        };
+
+        let params = ChatCompletionParams {
+            model,
+            temperature: config.temperature,
+            max_tokens: i32::try_from(config.max_tokens).unwrap_or(i32::MAX),
+            system_prompt: None,
+            tools: Vec::new(),
+        };
+        Ok((provider, params))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn compatible_config(id: &str) -> ProviderConfig {
+        let mut config = ProviderConfig::new(id, "openai-compatible");
+        config.name = Some("LM Studio".to_string());
+        config.options.insert("base_url".to_string(), json!("http://localhost:1234/v1"));
+        config.options.insert("models".to_string(), json!(["qwen2.5-7b-instruct"]));
+        config
+    }
+
+    #[tokio::test]
+    async fn test_resolve_and_create() {
+        let registry = ProviderRegistry::with_builtins();
+        let settings = AIProviderSettings {
+            providers: vec![compatible_config("lm-studio")],
+            ..Default::default()
+        };
+
+        // Built-in kinds resolve without configuration
+        let (descriptor, config) = registry.resolve("anthropic", &settings).unwrap();
+        assert_eq!(descriptor.api_key, ApiKeyPolicy::Required);
+        assert_eq!(config.kind, "anthropic");
+        assert!(registry.create("anthropic", None, &settings).await.is_err());
+
+        // Configured instances are selectable by their own id
+        let provider = registry.create("lm-studio", None, &settings).await.unwrap();
+        assert_eq!(provider.name(), "lm-studio");
+        assert_eq!(provider.available_models(), vec!["qwen2.5-7b-instruct"]);
+
+        // Multi-instance kinds need a configuration
+        assert!(registry.resolve("openai-compatible", &settings).is_err());
+        assert!(registry.resolve("missing", &settings).is_err());
+    }
+
+    #[test]
+    fn test_validate_options() {
+        let registry = ProviderRegistry::with_builtins();
+        let descriptor = registry.get("openai-compatible").unwrap();
+
+        let mut config = compatible_config("gateway");
+        assert!(descriptor.validate_options(&config).is_ok());
+
+        config.options.insert("headers".to_string(), json!({ "X-Team": 42 }));
+        assert!(descriptor.validate_options(&config).is_err());
+
+        config.options.clear();
+        assert!(descriptor.validate_options(&config).is_err());
+    }
+}
@@ -0,0 +1,361 @@
+//! Shell command tool
+//!
+//! This module contains a tool that lets a model run shell commands, for
+//! example to find out which process uses a port. Every command is shown to
+//! the user verbatim and only runs once they allow it, unless it matches the
+//! configured allowlist. Commands run with a minimal environment in the
+//! configured working directory, are stopped together with the processes
+//! they started after a timeout, and only the start of their output is
+//! passed back.
+
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::Command;
+
+use crate::settings::ShellSettings;
+use crate::utils::{AppError, AppResult};
+use super::approval::{Approval, ToolPermissions};
+use super::tools::Tool;
+
+/// Environment variables passed on to commands; all others are removed
+const PASSED_ENV_VARS: &[&str] = &[
+    "PATH", "HOME", "USER", "LOGNAME", "LANG", "LC_ALL", "TERM", "TMPDIR",
+    "SYSTEMROOT", "COMSPEC", "PATHEXT", "TEMP", "TMP", "USERPROFILE",
+];
+
+/// How long output is still collected after the shell exited
+const OUTPUT_DRAIN_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Characters that let a command line run more than the command it starts with
+const SHELL_METACHARACTERS: &[char] = &[';', '|', '&', '<', '>', '$', '`', '(', ')', '%', '^', '\n', '\r'];
+
+/// Runs shell commands after the user approves them
+#[derive(Debug, Clone)]
+pub struct ShellTool {
+    /// Approved folders and decisions
+    permissions: ToolPermissions,
+    /// Working directory, limits and allowlist
+    settings: ShellSettings,
+}
+
+impl ShellTool {
+    /// Creates the tool
+    pub fn new(permissions: ToolPermissions, settings: ShellSettings) -> Self {
+        Self { permissions, settings }
+    }
+
+    /// Directory commands run in
+    ///
+    /// Defaults to the first approved folder, or the temporary directory
+    /// if no folder is approved.
+    pub fn working_directory(&self) -> PathBuf {
+        self.settings
+            .working_directory
+            .clone()
+            .or_else(|| self.permissions.roots().into_iter().next())
+            .unwrap_or_else(std::env::temp_dir)
+    }
+
+    /// Whether a command line may run without approval
+    ///
+    /// An allowlist entry matches the command line itself and the same
+    /// command with further arguments. Command lines that chain, pipe,
+    /// redirect or substitute are never allowlisted, so an allowed command
+    /// cannot smuggle in another one.
+    pub fn is_allowlisted(&self, command: &str) -> bool {
+        let command = command.trim();
+        if command.contains(SHELL_METACHARACTERS) {
+            return false;
+        }
+        self.settings.allowed_commands.iter().any(|allowed| {
+            let allowed = allowed.trim();
+            !allowed.is_empty()
+                && command
+                    .strip_prefix(allowed)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+        })
+    }
+}
+
+/// Reads the `command` argument
+fn command_argument(arguments: &Value) -> AppResult<&str> {
+    arguments
+        .get("command")
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|command| !command.is_empty())
+        .ok_or_else(|| AppError::invalid_input("Missing argument: command"))
+}
+
+/// Builds the process running a command line through the system shell
+fn shell_command(command: &str) -> Command {
+    #[cfg(windows)]
+    let mut process = {
+        // cmd does not follow the quoting `arg` applies, so the command
+        // line is passed on exactly as the user approved it
+        let mut process = Command::new("cmd");
+        process.arg("/C").raw_arg(command);
+        process
+    };
+    #[cfg(not(windows))]
+    let mut process = {
+        let mut process = Command::new("sh");
+        process.arg("-c").arg(command);
+        process
+    };
+
+    process.env_clear();
+    for name in PASSED_ENV_VARS {
+        if let Some(value) = std::env::var_os(name) {
+            process.env(name, value);
+        }
+    }
+    process
+}
+
+/// Output of a stream, of which at most a limit is kept
+#[derive(Debug, Default)]
+struct Captured {
+    /// Bytes kept
+    kept: Vec<u8>,
+    /// Number of bytes read
+    total: usize,
+}
+
+/// Reads a stream to its end, keeping at most `limit` bytes
+///
+/// The rest is read and discarded so the command does not block on a full
+/// pipe. Output is collected as it arrives, so it is kept when reading is
+/// given up early.
+async fn read_capped<R: AsyncRead + Unpin>(stream: Option<R>, limit: usize, captured: &mut Captured) {
+    let Some(mut stream) = stream else {
+        return;
+    };
+    let mut buffer = [0u8; 8192];
+    while let Ok(read) = stream.read(&mut buffer).await {
+        if read == 0 {
+            break;
+        }
+        let room = limit.saturating_sub(captured.kept.len()).min(read);
+        captured.kept.extend_from_slice(&buffer[..room]);
+        captured.total += read;
+    }
+}
+
+/// Stops the processes a command started, which share the process group
+/// led by its shell
+///
+/// The group outlives the shell as long as any of them runs, so this also
+/// works once the shell exited and `Child::id` no longer returns its id.
+fn kill_process_group(group: Option<u32>) {
+    #[cfg(unix)]
+    if let Some(group) = group.and_then(|group| libc::pid_t::try_from(group).ok()) {
+        // SAFETY: sending a signal does not touch this process's memory
+        unsafe {
+            libc::killpg(group, libc::SIGKILL);
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = group;
+}
+
+/// Renders captured output, noting how much was cut off
+fn render_output(label: &str, captured: Captured) -> Option<String> {
+    let Captured { kept, total } = captured;
+    if total == 0 {
+        return None;
+    }
+    let mut text = format!("[{}]\n{}", label, String::from_utf8_lossy(&kept).trim_end());
+    if total > kept.len() {
+        text.push_str(&format!("\n[Truncated: showing {} of {} bytes]", kept.len(), total));
+    }
+    Some(text)
+}
+
+#[async_trait]
+impl Tool for ShellTool {
+    fn name(&self) -> &str {
+        "run_command"
+    }
+
+    fn description(&self) -> &str {
+        "Runs a shell command on the user's computer and returns its exit code and output. \
+         The user sees the exact command line and has to approve it. Prefer short, read-only \
+         commands; long-running commands are stopped after a timeout, and processes left in \
+         the background are stopped when the command exits."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "command": { "type": "string", "description": "The command line to run" }
+            },
+            "required": ["command"]
+        })
+    }
+
+    async fn execute(&self, arguments: Value) -> AppResult<String> {
+        let command = command_argument(&arguments)?;
+        let limit = self.settings.max_output_bytes;
+        let timeout = Duration::from_secs(self.settings.timeout_secs);
+
+        let mut process = shell_command(command);
+        #[cfg(unix)]
+        process.process_group(0);
+        let mut child = process
+            .current_dir(self.working_directory())
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+        let group = child.id();
+        let stdout = child.stdout.take();
+        let stderr = child.stderr.take();
+
+        let mut stdout_output = Captured::default();
+        let mut stderr_output = Captured::default();
+        let run = async {
+            let reading = async {
+                tokio::join!(
+                    read_capped(stdout, limit, &mut stdout_output),
+                    read_capped(stderr, limit, &mut stderr_output)
+                )
+            };
+            tokio::pin!(reading);
+            let (status, read) = tokio::select! {
+                status = child.wait() => (status, false),
+                _ = &mut reading => (child.wait().await, true),
+            };
+            // Processes left in the background are stopped with the shell;
+            // only what they have written so far is collected
+            kill_process_group(group);
+            if !read {
+                let _ = tokio::time::timeout(OUTPUT_DRAIN_TIMEOUT, reading).await;
+            }
+            status
+        };
+        let Ok(status) = tokio::time::timeout(timeout, run).await else {
+            kill_process_group(group);
+            let _ = child.start_kill();
+            return Err(AppError::internal(format!(
+                "Command did not finish within {} seconds and was stopped",
+                timeout.as_secs()
+            )));
+        };
+
+        let status = status?;
+        let mut sections = vec![match status.code() {
+            Some(code) => format!("Exit code: {}", code),
+            None => "Terminated by a signal".to_string(),
+        }];
+        sections.extend(render_output("stdout", stdout_output));
+        sections.extend(render_output("stderr", stderr_output));
+        Ok(sections.join("\n"))
+    }
+
+    fn approval(&self, arguments: &Value) -> Option<Approval> {
+        let command = command_argument(arguments).ok()?;
+        if self.is_allowlisted(command) {
+            return None;
+        }
+        Some(Approval {
+            key: format!("{}:{}", self.name(), command),
+            description: format!("Run `{}` in {}", command, self.working_directory().display()),
+        })
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_shell_tool_limits_commands() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let tool = ShellTool::new(
+            ToolPermissions::new(),
+            ShellSettings {
+                working_directory: Some(temp_dir.path().to_path_buf()),
+                timeout_secs: 1,
+                max_output_bytes: 16,
+                allowed_commands: vec!["echo".to_string(), "git status".to_string()],
+            },
+        );
+
+        // Allowlisted commands skip approval unless they run something else too
+        assert!(tool.approval(&json!({ "command": "echo hi" })).is_none());
+        assert!(tool.approval(&json!({ "command": "git status --short" })).is_none());
+        assert!(tool.approval(&json!({ "command": "git push" })).is_some());
+        assert!(tool.approval(&json!({ "command": "echo hi; rm -rf ~" })).is_some());
+        assert!(tool.approval(&json!({ "command": "echoes" })).is_some());
+        let approval = tool.approval(&json!({ "command": "ls -la" })).unwrap();
+        assert!(approval.description.contains("`ls -la`"));
+
+        std::env::set_var("SYNAPSE_TEST_SECRET", "hunter2");
+        let output = tool
+            .execute(json!({ "command": "echo 0123456789abcdefghij; echo \"secret=$SYNAPSE_TEST_SECRET\" >&2; exit 3" }))
+            .await
+            .unwrap();
+        assert!(output.starts_with("Exit code: 3"));
+        assert!(output.ends_with("[stderr]\nsecret="));
+        assert!(output.contains("[stdout]\n0123456789abcdef\n[Truncated: showing 16 of 21 bytes]"));
+
+        assert!(tool.execute(json!({ "command": "sleep 5" })).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_background_processes_do_not_block_commands() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let tool = ShellTool::new(
+            ToolPermissions::new(),
+            ShellSettings {
+                working_directory: Some(temp_dir.path().to_path_buf()),
+                timeout_secs: 2,
+                max_output_bytes: 1024,
+                allowed_commands: Vec::new(),
+            },
+        );
+
+        // The command is done when the shell exits, and `sleep` is stopped with it
+        let started = std::time::Instant::now();
+        let output = tool
+            .execute(json!({ "command": "sleep 30 & echo $! > background.pid; echo started" }))
+            .await
+            .unwrap();
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert_eq!(output, "Exit code: 0\n[stdout]\nstarted");
+        assert_stopped(&temp_dir.path().join("background.pid"));
+
+        // On a timeout the processes the command started are stopped as well
+        let error = tool
+            .execute(json!({ "command": "sleep 30 & echo $! > sleep.pid; sleep 30" }))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("did not finish within 2 seconds"));
+        assert_stopped(&temp_dir.path().join("sleep.pid"));
+    }
+
+    /// Asserts that the process whose id is in `pid_file` stops shortly
+    fn assert_stopped(pid_file: &std::path::Path) {
+        let pid = std::fs::read_to_string(pid_file).unwrap();
+        let mut running = true;
+        for _ in 0..50 {
+            // Killed processes nobody reaped yet show up as zombies
+            let ps = std::process::Command::new("ps").args(["-o", "stat=", "-p", pid.trim()]).output().unwrap();
+            let state = String::from_utf8_lossy(&ps.stdout).trim().to_string();
+            running = !state.is_empty() && !state.starts_with('Z');
+            if !running {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert!(!running, "background process {} is still running", pid.trim());
+    }
+}
@@ -0,0 +1,145 @@
+//! Server-sent events
+//!
+//! This module decodes `text/event-stream` response bodies, as used by the
+//! streaming endpoints of the OpenAI and Anthropic APIs.
+
+use std::collections::VecDeque;
+
+use futures::stream::{self, BoxStream, StreamExt};
+
+use crate::utils::AppResult;
+
+/// A single server-sent event
+#[derive(Debug, Clone, PartialEq)]
+pub struct SseEvent {
+    /// The event type, if the server named one
+    pub event: Option<String>,
+    /// The event data, with multiple `data:` lines joined by newlines
+    pub data: String,
+}
+
+/// Incremental decoder turning raw body chunks into events
+#[derive(Debug, Default)]
+pub struct SseDecoder {
+    /// Bytes received after the last complete line
+    buffer: Vec<u8>,
+    /// Event type of the event being assembled
+    event: Option<String>,
+    /// Data lines of the event being assembled
+    data: Vec<String>,
+}
+
+impl SseDecoder {
+    /// Feeds a chunk of the body and returns the events it completed
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut events = Vec::new();
+        while let Some(pos) = self.buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\n', '\r']);
+
+            if let Some(event) = self.process_line(line) {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    /// Flushes any event left unterminated at the end of the body
+    pub fn finish(&mut self) -> Option<SseEvent> {
+        if !self.buffer.is_empty() {
+            let line = String::from_utf8_lossy(&std::mem::take(&mut self.buffer)).into_owned();
+            if let Some(event) = self.process_line(line.trim_end_matches('\r')) {
+                return Some(event);
+            }
+        }
+        self.dispatch()
+    }
+
+    /// Handles one line of the stream, returning an event on a blank line
+    fn process_line(&mut self, line: &str) -> Option<SseEvent> {
+        if line.is_empty() {
+            return self.dispatch();
+        }
+        if line.starts_with(':') {
+            // Comment, used by servers as a keep-alive
+            return None;
+        }
+
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+
+        match field {
+            "event" => self.event = Some(value.to_string()),
+            "data" => self.data.push(value.to_string()),
+            _ => {}
+        }
+        None
+    }
+
+    /// Emits the event assembled so far, if it carried any data
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        let event = self.event.take();
+        if self.data.is_empty() {
+            return None;
+        }
+        Some(SseEvent {
+            event,
+            data: std::mem::take(&mut self.data).join("\n"),
+        })
+    }
+}
+
+/// Turns a streaming HTTP response into a stream of events
+pub fn events(response: reqwest::Response) -> BoxStream<'static, AppResult<SseEvent>> {
+    let state = (response.bytes_stream().boxed(), SseDecoder::default(), VecDeque::new(), false);
+
+    stream::unfold(state, |(mut body, mut decoder, mut pending, mut finished)| async move {
+        loop {
+            if let Some(event) = pending.pop_front() {
+                return Some((Ok(event), (body, decoder, pending, finished)));
+            }
+            if finished {
+                return None;
+            }
+
+            match body.next().await {
+                Some(Ok(chunk)) => pending.extend(decoder.push(&chunk)),
+                Some(Err(e)) => {
+                    finished = true;
+                    return Some((Err(e.into()), (body, decoder, pending, finished)));
+                }
+                None => {
+                    finished = true;
+                    pending.extend(decoder.finish());
+                }
+            }
+        }
+    })
+    .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decoder_handles_split_chunks() {
+        let mut decoder = SseDecoder::default();
+
+        assert!(decoder.push(b"event: message_start\nda").is_empty());
+        let events = decoder.push(b"ta: {\"a\":1}\r\n\r\n: keep-alive\n\ndata: line one\ndata: line two\n\n");
+
+        assert_eq!(events, vec![
+            SseEvent { event: Some("message_start".to_string()), data: "{\"a\":1}".to_string() },
+            SseEvent { event: None, data: "line one\nline two".to_string() },
+        ]);
+
+        decoder.push(b"data: [DONE]");
+        assert_eq!(decoder.finish(), Some(SseEvent { event: None, data: "[DONE]".to_string() }));
+    }
+}
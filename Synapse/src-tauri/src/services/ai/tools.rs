@@ -0,0 +1,186 @@
+//! Tools
+//!
+//! This module defines the tools a model can call while generating a reply.
+//! A tool describes its arguments with a JSON schema and runs
+//! asynchronously. Tools are kept in a `ToolRegistry`; its definitions are
+//! sent with completion requests, and the calls a model makes are executed
+//! through it. Each provider translates definitions, calls and results into
+//! its own wire format. Calls a tool marks as needing approval only run once
+//! the user allows them.
+
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::sync::{Arc, RwLock};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::utils::AppResult;
+use super::approval::{Approval, ToolPermissions};
+
+/// A tool as described to a model
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolDefinition {
+    /// Name the model calls the tool by
+    pub name: String,
+    /// What the tool does and when to use it
+    pub description: String,
+    /// JSON schema of the arguments object
+    pub parameters: Value,
+}
+
+/// A call to a tool made by a model
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCall {
+    /// Id the provider assigned to the call
+    pub id: String,
+    /// Name of the tool to call
+    pub name: String,
+    /// Arguments, matching the tool's schema
+    pub arguments: Value,
+}
+
+/// The outcome of a tool call, sent back to the model
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolResult {
+    /// Id of the call this is the result of
+    pub call_id: String,
+    /// Output of the tool, or a description of what went wrong
+    pub content: String,
+    /// Whether the call failed
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub is_error: bool,
+}
+
+impl ToolResult {
+    /// Creates a successful result
+    pub fn success<C: Into<String>>(call_id: &str, content: C) -> Self {
+        Self {
+            call_id: call_id.to_string(),
+            content: content.into(),
+            is_error: false,
+        }
+    }
+
+    /// Creates a failed result
+    pub fn error<C: Into<String>>(call_id: &str, content: C) -> Self {
+        Self {
+            call_id: call_id.to_string(),
+            content: content.into(),
+            is_error: true,
+        }
+    }
+}
+
+/// Trait that must be implemented by all tools
+#[async_trait]
+pub trait Tool: Send + Sync + Debug {
+    /// Returns the name the model calls the tool by
+    fn name(&self) -> &str;
+
+    /// Returns a description of what the tool does
+    fn description(&self) -> &str;
+
+    /// Returns the JSON schema of the tool's arguments
+    fn parameters(&self) -> Value;
+
+    /// Runs the tool
+    ///
+    /// # Returns
+    /// The output passed back to the model
+    ///
+    /// # Errors
+    /// Returns an error if the arguments are invalid or the tool fails; the
+    /// error is reported to the model as the result of the call
+    async fn execute(&self, arguments: Value) -> AppResult<String>;
+
+    /// Reports whether a call needs the user's approval before it runs
+    ///
+    /// Tools that change anything, or reach beyond the approved folders,
+    /// return the action to approve. Calls run through the registry only
+    /// execute once the user allows them.
+    fn approval(&self, _arguments: &Value) -> Option<Approval> {
+        None
+    }
+
+    /// Describes the tool to a model
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: self.name().to_string(),
+            description: self.description().to_string(),
+            parameters: self.parameters(),
+        }
+    }
+}
+
+/// Registry of the tools available to models
+///
+/// Clones share the same tools, so tools registered later, for example by
+/// a connected server, become available everywhere.
+#[derive(Debug, Clone, Default)]
+pub struct ToolRegistry {
+    /// Tools keyed by name
+    tools: Arc<RwLock<BTreeMap<String, Arc<dyn Tool>>>>,
+    /// What tools may do without asking the user
+    permissions: ToolPermissions,
+}
+
+impl ToolRegistry {
+    /// Creates an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// What tools may do without asking, and the calls waiting for approval
+    pub fn permissions(&self) -> &ToolPermissions {
+        &self.permissions
+    }
+
+    /// Registers a tool, replacing any previous one with the same name
+    pub fn register(&self, tool: Arc<dyn Tool>) {
+        if let Ok(mut tools) = self.tools.write() {
+            tools.insert(tool.name().to_string(), tool);
+        }
+    }
+
+    /// Removes a tool
+    pub fn unregister(&self, name: &str) {
+        if let Ok(mut tools) = self.tools.write() {
+            tools.remove(name);
+        }
+    }
+
+    /// Looks up a tool
+    pub fn get(&self, name: &str) -> Option<Arc<dyn Tool>> {
+        self.tools.read().ok()?.get(name).cloned()
+    }
+
+    /// Describes all registered tools
+    pub fn definitions(&self) -> Vec<ToolDefinition> {
+        self.tools
+            .read()
+            .map(|tools| tools.values().map(|tool| tool.definition()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Runs a tool call
+    ///
+    /// Failures, including calls to unknown tools and calls the user did
+    /// not allow, are returned as error results so the model can react to
+    /// them.
+    pub async fn execute(&self, call: &ToolCall) -> ToolResult {
+        let Some(tool) = self.get(&call.name) else {
+            return ToolResult::error(&call.id, format!("Unknown tool: {}", call.name));
+        };
+        if let Some(approval) = tool.approval(&call.arguments) {
+            if !self.permissions.approve(&call.name, &call.arguments, approval).await {
+                return ToolResult::error(&call.id, "The user did not allow this call");
+            }
+        }
+        match tool.execute(call.arguments.clone()).await {
+            Ok(output) => ToolResult::success(&call.id, output),
+            Err(e) => ToolResult::error(&call.id, e.to_string()),
+        }
+    }
+}
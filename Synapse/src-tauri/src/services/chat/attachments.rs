@@ -0,0 +1,271 @@
+//! Message attachments
+//!
+//! This module stores the images and files attached to messages. Attachments
+//! live next to the chat database, one directory per session, and messages
+//! refer to them by id. The type of every attachment is sniffed from its
+//! contents rather than trusted from its name, and only types the providers
+//! can handle are accepted.
+
+use std::path::{Path, PathBuf};
+
+use crate::utils::{AppError, AppResult};
+use super::super::ai::content::{decode_base64, encode_base64};
+use super::super::ai::{ContentPart, MessageContent, PartSource};
+
+/// Largest image accepted, in bytes
+pub const MAX_IMAGE_SIZE: usize = 20 * 1024 * 1024;
+
+/// Largest file accepted, in bytes
+pub const MAX_FILE_SIZE: usize = 32 * 1024 * 1024;
+
+/// Image types recognised by their leading bytes
+const IMAGE_SIGNATURES: &[(&[u8], &str)] = &[
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+];
+
+/// MIME types of text files, by extension
+const TEXT_TYPES: &[(&str, &str)] = &[
+    ("md", "text/markdown"),
+    ("csv", "text/csv"),
+    ("html", "text/html"),
+    ("json", "application/json"),
+    ("xml", "application/xml"),
+    ("yaml", "application/x-yaml"),
+    ("yml", "application/x-yaml"),
+];
+
+/// Stores attachments on disk, grouped by session
+#[derive(Debug, Clone)]
+pub struct AttachmentStore {
+    /// Directory holding one subdirectory per session
+    root: PathBuf,
+}
+
+impl AttachmentStore {
+    /// Creates a store rooted at `root`; directories are created on first write
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    /// Stores an attachment and returns the part referring to it
+    ///
+    /// # Errors
+    /// Returns an error if the type is not supported, the attachment is too
+    /// large or it cannot be written
+    pub async fn save(&self, session_id: &str, name: &str, data: &[u8]) -> AppResult<ContentPart> {
+        let id = uuid::Uuid::new_v4().to_string();
+        let part = sniff(name, data, PartSource::Attachment { id: id.clone() })?;
+
+        let dir = self.session_dir(session_id)?;
+        tokio::fs::create_dir_all(&dir).await?;
+        tokio::fs::write(dir.join(&id), data).await?;
+        Ok(part)
+    }
+
+    /// Reads the data of an attachment
+    ///
+    /// # Errors
+    /// Returns an error if the attachment does not exist
+    pub async fn load(&self, session_id: &str, id: &str) -> AppResult<Vec<u8>> {
+        validate_id(id)?;
+        let path = self.session_dir(session_id)?.join(id);
+        tokio::fs::read(&path).await.map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => AppError::not_found("Attachment not found"),
+            _ => e.into(),
+        })
+    }
+
+    /// Moves inline data of `content` into the store
+    ///
+    /// Inline parts are checked the same way as saved attachments, so
+    /// nothing unsupported or oversized ends up in a message.
+    pub async fn store_inline(&self, session_id: &str, content: &mut MessageContent) -> AppResult<()> {
+        for part in &mut content.parts {
+            let data = match part.source() {
+                Some(PartSource::Base64 { data }) => decode_base64(data)?,
+                _ => continue,
+            };
+            let name = match part {
+                ContentPart::File { name, .. } => name.clone(),
+                _ => String::new(),
+            };
+            *part = self.save(session_id, &name, &data).await?;
+        }
+        Ok(())
+    }
+
+    /// Replaces attachment references in `content` with their data
+    ///
+    /// Attachments that no longer exist, such as those of sessions imported
+    /// from an export, are replaced with a note saying so.
+    ///
+    /// # Errors
+    /// Returns an error if an attachment cannot be read
+    pub async fn resolve(&self, session_id: &str, content: &mut MessageContent) -> AppResult<()> {
+        for part in &mut content.parts {
+            let Some(PartSource::Attachment { id }) = part.source() else {
+                continue;
+            };
+            match self.load(session_id, id).await {
+                Ok(data) => {
+                    if let Some(source) = part.source_mut() {
+                        *source = PartSource::Base64 { data: encode_base64(&data) };
+                    }
+                }
+                Err(AppError::NotFound(_)) => *part = ContentPart::text(missing_note(part)),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies the attachments `content` refers to from one session to another
+    pub async fn copy(&self, from_session: &str, to_session: &str, content: &MessageContent) -> AppResult<()> {
+        let from = self.session_dir(from_session)?;
+        let to = self.session_dir(to_session)?;
+        for part in content.attachments() {
+            if let Some(PartSource::Attachment { id }) = part.source() {
+                validate_id(id)?;
+                tokio::fs::create_dir_all(&to).await?;
+                tokio::fs::copy(from.join(id), to.join(id)).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Deletes all attachments of a session
+    pub async fn delete_session(&self, session_id: &str) -> AppResult<()> {
+        match tokio::fs::remove_dir_all(self.session_dir(session_id)?).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn session_dir(&self, session_id: &str) -> AppResult<PathBuf> {
+        validate_id(session_id)?;
+        Ok(self.root.join(session_id))
+    }
+}
+
+/// Text standing in for an attachment that no longer exists
+fn missing_note(part: &ContentPart) -> String {
+    match part {
+        ContentPart::File { name, .. } => format!("[Attachment {} is no longer available]", name),
+        _ => "[An image is no longer available]".to_string(),
+    }
+}
+
+/// Rejects ids that could escape the attachment directory
+fn validate_id(id: &str) -> AppResult<()> {
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(AppError::invalid_input("Invalid attachment id"));
+    }
+    Ok(())
+}
+
+/// Determines the type of an attachment from its contents
+///
+/// Images become image parts and everything else file parts. Text is
+/// recognised as valid UTF-8 and typed by the extension of `name`.
+///
+/// # Errors
+/// Returns an error if the type is not supported or the data is too large
+pub fn sniff(name: &str, data: &[u8], source: PartSource) -> AppResult<ContentPart> {
+    let image = IMAGE_SIGNATURES
+        .iter()
+        .find(|(signature, _)| data.starts_with(signature))
+        .map(|(_, mime_type)| *mime_type)
+        .or_else(|| (data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP").then_some("image/webp"));
+
+    if let Some(mime_type) = image {
+        if data.len() > MAX_IMAGE_SIZE {
+            return Err(AppError::invalid_input(format!(
+                "Images may be at most {} MB",
+                MAX_IMAGE_SIZE / (1024 * 1024)
+            )));
+        }
+        return Ok(ContentPart::Image { mime_type: mime_type.to_string(), source });
+    }
+
+    let mime_type = if data.starts_with(b"%PDF-") {
+        "application/pdf"
+    } else if !data.contains(&0) && std::str::from_utf8(data).is_ok() {
+        let extension = Path::new(name)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+        TEXT_TYPES
+            .iter()
+            .find(|(known, _)| *known == extension)
+            .map(|(_, mime_type)| *mime_type)
+            .unwrap_or("text/plain")
+    } else {
+        return Err(AppError::invalid_input("Unsupported attachment type"));
+    };
+
+    if data.len() > MAX_FILE_SIZE {
+        return Err(AppError::invalid_input(format!(
+            "Files may be at most {} MB",
+            MAX_FILE_SIZE / (1024 * 1024)
+        )));
+    }
+    let name = match name.trim() {
+        "" => "attachment".to_string(),
+        name => name.to_string(),
+    };
+    Ok(ContentPart::File {
+        name,
+        mime_type: mime_type.to_string(),
+        size: data.len() as u64,
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_attachments_are_sniffed_and_stored() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let store = AttachmentStore::new(temp_dir.path().to_path_buf());
+
+        let png = b"\x89PNG\r\n\x1a\nrest of the image";
+        let part = store.save("session", "photo.txt", png).await.unwrap();
+        let ContentPart::Image { mime_type, source: PartSource::Attachment { id } } = &part else {
+            panic!("expected a stored image, got {:?}", part);
+        };
+        assert_eq!(mime_type, "image/png");
+        assert_eq!(store.load("session", id).await.unwrap(), png);
+
+        let part = store.save("session", "notes.md", b"# Notes").await.unwrap();
+        assert!(matches!(part, ContentPart::File { ref mime_type, size: 7, .. } if mime_type == "text/markdown"));
+
+        assert!(store.save("session", "program.exe", b"MZ\x90\x00\x03").await.is_err());
+        assert!(store.load("session", "../chats.db").await.is_err());
+        assert!(sniff("big.txt", &vec![b'a'; MAX_FILE_SIZE + 1], PartSource::Base64 { data: String::new() }).is_err());
+
+        let mut content = MessageContent::from(vec![ContentPart::text("Look"), part]);
+        store.resolve("session", &mut content).await.unwrap();
+        assert!(content.attachments().all(|part| matches!(part.source(), Some(PartSource::Base64 { .. }))));
+
+        // References to attachments that are gone become notes
+        let missing = ContentPart::File {
+            name: "report.pdf".to_string(),
+            mime_type: "application/pdf".to_string(),
+            size: 3,
+            source: PartSource::Attachment { id: "missing".to_string() },
+        };
+        let mut content = MessageContent::from(vec![ContentPart::text("See"), missing]);
+        store.resolve("session", &mut content).await.unwrap();
+        assert_eq!(content.attachments().count(), 0);
+        assert_eq!(content.text(), "See\n\n[Attachment report.pdf is no longer available]");
+
+        store.delete_session("session").await.unwrap();
+        assert!(store.load("session", id).await.is_err());
+    }
+}
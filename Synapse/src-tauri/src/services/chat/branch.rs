@@ -0,0 +1,159 @@
+//! Conversation branches
+//!
+//! Messages in a session form a tree: every message points at the message
+//! it replies to through `parent_id`. Editing a message adds a sibling next
+//! to it instead of overwriting it, so earlier versions of a conversation
+//! stay available as branches. The session's `active_leaf` selects the
+//! branch that is shown and sent to the provider.
+
+use serde::{Deserialize, Serialize};
+
+use super::super::ai::Message;
+use super::ChatSession;
+
+/// One of the alternative messages at a point in the conversation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Branch {
+    /// The first message of the branch
+    pub message: Message,
+    /// The most recent message reachable from `message`
+    pub leaf_id: String,
+    /// Whether the branch is part of the active conversation
+    pub active: bool,
+}
+
+impl ChatSession {
+    /// Looks up a message by id
+    pub fn message(&self, id: &str) -> Option<&Message> {
+        self.messages.iter().find(|message| message.id == id)
+    }
+
+    /// Lists the replies to a message, or the first messages if `parent_id` is `None`
+    ///
+    /// Replies are returned in the order they were created.
+    pub fn children(&self, parent_id: Option<&str>) -> Vec<&Message> {
+        self.messages
+            .iter()
+            .filter(|message| message.parent_id.as_deref() == parent_id)
+            .collect()
+    }
+
+    /// Returns the messages from the start of the conversation to `id`
+    pub fn branch_to(&self, id: &str) -> Vec<&Message> {
+        let mut branch = Vec::new();
+        let mut next = self.message(id);
+        while let Some(message) = next {
+            // A malformed tree could contain a cycle
+            if branch.len() >= self.messages.len() {
+                break;
+            }
+            branch.push(message);
+            next = message.parent_id.as_deref().and_then(|parent| self.message(parent));
+        }
+        branch.reverse();
+        branch
+    }
+
+    /// Returns the messages of the active branch, oldest first
+    pub fn active_branch(&self) -> Vec<&Message> {
+        self.active_leaf
+            .as_deref()
+            .map(|leaf| self.branch_to(leaf))
+            .unwrap_or_default()
+    }
+
+    /// Finds the most recent leaf below a message by following its newest replies
+    pub fn latest_leaf(&self, id: &str) -> String {
+        let mut leaf = id.to_string();
+        let mut steps = 0;
+        while let Some(child) = self.children(Some(&leaf)).last() {
+            steps += 1;
+            if steps > self.messages.len() {
+                break;
+            }
+            leaf = child.id.clone();
+        }
+        leaf
+    }
+
+    /// Lists the alternatives at the point in the conversation where `id` is
+    ///
+    /// The message itself is included among its siblings.
+    pub fn branches_at(&self, id: &str) -> Option<Vec<Branch>> {
+        let message = self.message(id)?;
+        let active: Vec<&str> = self.active_branch().iter().map(|m| m.id.as_str()).collect();
+
+        let branches = self
+            .children(message.parent_id.as_deref())
+            .into_iter()
+            .map(|sibling| Branch {
+                message: sibling.clone(),
+                leaf_id: self.latest_leaf(&sibling.id),
+                active: active.contains(&sibling.id.as_str()),
+            })
+            .collect();
+        Some(branches)
+    }
+
+    /// Links messages without parents into a single branch in their stored order
+    ///
+    /// Used for sessions written before messages formed a tree.
+    pub(crate) fn link_linear(&mut self) {
+        let mut parent: Option<String> = None;
+        for message in &mut self.messages {
+            message.parent_id = parent.take();
+            parent = Some(message.id.clone());
+        }
+        self.active_leaf = parent;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use crate::services::ai::Role;
+    use crate::services::chat::{ContextStrategy, SessionSettings};
+
+    fn reply(content: &str, parent: Option<&Message>) -> Message {
+        let mut message = Message::new(Role::User, content);
+        message.parent_id = parent.map(|parent| parent.id.clone());
+        message
+    }
+
+    #[test]
+    fn test_branches() {
+        let first = reply("first", None);
+        let answer = reply("answer", Some(&first));
+        let edited = reply("edited", None);
+        let edited_answer = reply("edited answer", Some(&edited));
+
+        let mut session = ChatSession {
+            id: "session".to_string(),
+            title: "Branches".to_string(),
+            messages: vec![first.clone(), answer.clone(), edited.clone(), edited_answer.clone()],
+            active_leaf: Some(answer.id.clone()),
+            context_strategy: ContextStrategy::default(),
+            context_summary: None,
+            settings: SessionSettings::default(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+
+        let contents: Vec<_> = session.active_branch().iter().map(|m| m.content.text()).collect();
+        assert_eq!(contents, vec!["first", "answer"]);
+
+        let branches = session.branches_at(&edited.id).unwrap();
+        assert_eq!(branches.len(), 2);
+        assert!(branches[0].active && !branches[1].active);
+        assert_eq!(branches[1].leaf_id, edited_answer.id);
+
+        session.active_leaf = Some(session.latest_leaf(&edited.id));
+        let contents: Vec<_> = session.active_branch().iter().map(|m| m.content.text()).collect();
+        assert_eq!(contents, vec!["edited", "edited answer"]);
+
+        session.link_linear();
+        assert_eq!(session.active_branch().len(), 4);
+        assert!(session.branches_at("missing").is_none());
+    }
+}
@@ -0,0 +1,312 @@
+//! Context window management
+//!
+//! This module decides which messages of a conversation are sent to the
+//! model. Token counts are estimated per message and compared against the
+//! model's context length. System messages and the latest turns are always
+//! kept; older turns are dropped, or replaced by a running summary when the
+//! session uses the summarizing strategy.
+
+use serde::{Deserialize, Serialize};
+
+use crate::utils::{AppError, AppResult};
+use super::super::ai::{AIProvider, ChatCompletionParams, ContentPart, Message, Role};
+
+/// Context length assumed for models not listed in `CONTEXT_LENGTHS`
+pub const DEFAULT_CONTEXT_LENGTH: usize = 8192;
+
+/// Tokens added per message for role markers and separators
+const MESSAGE_OVERHEAD: usize = 4;
+
+/// Average number of characters per token in English text
+const CHARS_PER_TOKEN: usize = 4;
+
+/// Tokens assumed for an image, roughly what providers charge for a tiled image
+const IMAGE_TOKENS: usize = 1_000;
+
+/// Context lengths of known models, matched by the longest prefix
+const CONTEXT_LENGTHS: &[(&str, usize)] = &[
+    ("gpt-4o", 128_000),
+    ("gpt-4.1", 1_047_576),
+    ("gpt-4-turbo", 128_000),
+    ("gpt-4-32k", 32_768),
+    ("gpt-4", 8_192),
+    ("gpt-3.5-turbo", 16_385),
+    ("o1", 200_000),
+    ("o3", 200_000),
+    ("o4", 200_000),
+    ("claude", 200_000),
+    ("llama3.1", 128_000),
+    ("llama3.2", 128_000),
+    ("llama3.3", 128_000),
+    ("llama3", 8_192),
+    ("mistral", 32_768),
+    ("mixtral", 32_768),
+    ("qwen2.5", 32_768),
+    ("gemma2", 8_192),
+    ("phi3", 128_000),
+];
+
+/// Tokens a running summary may use
+const SUMMARY_MAX_TOKENS: i32 = 512;
+
+const SUMMARY_PROMPT: &str = "You maintain a running summary of a conversation so it can \
+continue after older messages are removed. Update the summary with the new messages. Keep \
+facts, decisions, names, code identifiers and open questions. Reply with the summary only.";
+
+/// How older messages are handled once a conversation outgrows the context
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContextStrategy {
+    /// Drop the oldest turns
+    #[default]
+    Truncate,
+    /// Replace the oldest turns with a running summary
+    Summarize,
+}
+
+impl ContextStrategy {
+    /// Name of the strategy as stored in the database
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Truncate => "truncate",
+            Self::Summarize => "summarize",
+        }
+    }
+
+    /// Parses a stored strategy name, defaulting to truncation
+    pub fn parse(name: &str) -> Self {
+        match name {
+            "summarize" => Self::Summarize,
+            _ => Self::Truncate,
+        }
+    }
+}
+
+/// A summary standing in for the start of a branch
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ContextSummary {
+    /// The summary text
+    pub text: String,
+    /// Id of the last message the summary covers
+    pub through: String,
+}
+
+/// Returns the context length of a model in tokens
+pub fn context_length(model: &str) -> usize {
+    let model = model.to_lowercase();
+    // Strip namespaces such as "openai/" or "library/" used by gateways
+    let name = model.rsplit('/').next().unwrap_or(&model);
+    CONTEXT_LENGTHS
+        .iter()
+        .filter(|(prefix, _)| name.starts_with(prefix))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(_, length)| *length)
+        .unwrap_or(DEFAULT_CONTEXT_LENGTH)
+}
+
+/// Estimates the number of tokens in a text
+pub fn estimate_text_tokens(text: &str) -> usize {
+    (text.chars().count() + CHARS_PER_TOKEN - 1) / CHARS_PER_TOKEN
+}
+
+/// Estimates the number of tokens a message takes up in a request
+///
+/// Images count a fixed amount and files count by their size.
+pub fn estimate_tokens(message: &Message) -> usize {
+    let parts: usize = message
+        .content
+        .parts
+        .iter()
+        .map(|part| match part {
+            ContentPart::Text { text } => estimate_text_tokens(text),
+            ContentPart::Image { .. } => IMAGE_TOKENS,
+            ContentPart::File { size, .. } => usize::try_from(*size).unwrap_or(usize::MAX) / CHARS_PER_TOKEN,
+            ContentPart::ToolCall(call) => estimate_text_tokens(&call.name) + estimate_text_tokens(&call.arguments.to_string()),
+            ContentPart::ToolResult(result) => estimate_text_tokens(&result.content),
+        })
+        .sum();
+    parts + MESSAGE_OVERHEAD
+}
+
+/// Messages selected to fit a context window
+#[derive(Debug, Clone, Default)]
+pub struct ContextWindow {
+    /// System messages of the conversation
+    pub system: Vec<Message>,
+    /// Older messages that did not fit, oldest first
+    pub dropped: Vec<Message>,
+    /// Latest messages that fit, oldest first
+    pub kept: Vec<Message>,
+}
+
+impl ContextWindow {
+    /// Assembles the messages to send, with an optional summary of the dropped ones
+    pub fn into_messages(self, summary: Option<&str>) -> Vec<Message> {
+        let mut messages = self.system;
+        if let Some(summary) = summary {
+            messages.push(summary_message(summary));
+        }
+        messages.extend(self.kept);
+        messages
+    }
+}
+
+/// Number of tokens available for the conversation itself
+///
+/// Room is reserved for the reply and the system prompt passed in `params`.
+pub fn budget(params: &ChatCompletionParams) -> usize {
+    let reserved = usize::try_from(params.max_tokens).unwrap_or(0)
+        + params.system_prompt.as_deref().map(estimate_text_tokens).unwrap_or(0);
+    context_length(&params.model).saturating_sub(reserved)
+}
+
+/// Splits a branch into system messages and the latest messages that fit `budget`
+///
+/// The newest message is always kept, even if it does not fit on its own.
+/// Kept messages never start with an assistant reply or tool result whose
+/// prompt was dropped.
+pub fn fit(history: &[Message], budget: usize) -> ContextWindow {
+    let (system, conversation): (Vec<&Message>, Vec<&Message>) =
+        history.iter().partition(|message| message.role == Role::System);
+
+    let mut remaining = budget.saturating_sub(system.iter().map(|m| estimate_tokens(m)).sum());
+    let mut first_kept = conversation.len();
+    for (index, message) in conversation.iter().enumerate().rev() {
+        let tokens = estimate_tokens(message);
+        if tokens > remaining && first_kept < conversation.len() {
+            break;
+        }
+        remaining = remaining.saturating_sub(tokens);
+        first_kept = index;
+    }
+
+    // Start the kept part with a prompt rather than a dangling reply
+    while first_kept + 1 < conversation.len() && conversation[first_kept].role != Role::User {
+        first_kept += 1;
+    }
+
+    ContextWindow {
+        system: system.into_iter().cloned().collect(),
+        dropped: conversation[..first_kept].iter().map(|m| (*m).clone()).collect(),
+        kept: conversation[first_kept..].iter().map(|m| (*m).clone()).collect(),
+    }
+}
+
+/// Builds the system message carrying a summary of dropped messages
+pub fn summary_message(summary: &str) -> Message {
+    Message::new(Role::System, format!("Summary of the earlier conversation:\n{}", summary))
+}
+
+/// Asks a model to fold messages into a running summary
+///
+/// Messages are sent in chunks that fit the summarizing request, so
+/// conversations far longer than the context can still be summarized.
+///
+/// # Errors
+/// Returns an error if a request fails or a reply is empty
+pub async fn summarize(
+    provider: &dyn AIProvider,
+    previous: Option<&str>,
+    messages: &[Message],
+    params: ChatCompletionParams,
+) -> AppResult<String> {
+    let params = ChatCompletionParams {
+        temperature: 0.2,
+        tools: Vec::new(),
+        max_tokens: SUMMARY_MAX_TOKENS,
+        system_prompt: Some(SUMMARY_PROMPT.to_string()),
+        ..params
+    };
+    let limit = budget(&params) / 2;
+
+    let mut summary = previous.map(str::to_string);
+    let mut start = 0;
+    while start < messages.len() {
+        let mut end = start;
+        let mut tokens = 0;
+        while end < messages.len() && (end == start || tokens + estimate_tokens(&messages[end]) <= limit) {
+            tokens += estimate_tokens(&messages[end]);
+            end += 1;
+        }
+        summary = Some(fold(provider, summary.as_deref(), &messages[start..end], params.clone()).await?);
+        start = end;
+    }
+    summary.ok_or_else(|| AppError::invalid_input("Nothing to summarize"))
+}
+
+/// Sends one summarizing request
+async fn fold(
+    provider: &dyn AIProvider,
+    previous: Option<&str>,
+    messages: &[Message],
+    params: ChatCompletionParams,
+) -> AppResult<String> {
+    let mut transcript = String::new();
+    if let Some(previous) = previous {
+        transcript.push_str(&format!("Current summary:\n{}\n\n", previous));
+    }
+    transcript.push_str("New messages:\n");
+    for message in messages {
+        transcript.push_str(&format!("{}: {}\n\n", message.role, message.content));
+    }
+
+    let completion = provider
+        .create_chat_completion(vec![Message::new(Role::User, transcript)], params)
+        .await?;
+
+    let summary = completion.message.content.text().trim().to_string();
+    if summary.is_empty() {
+        return Err(AppError::api("Summary reply was empty"));
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(role: Role, tokens: usize) -> Message {
+        // Each message costs `tokens` including the per-message overhead
+        Message::new(role, "x".repeat((tokens - MESSAGE_OVERHEAD) * CHARS_PER_TOKEN))
+    }
+
+    #[test]
+    fn test_context_length() {
+        assert_eq!(context_length("gpt-4o-mini"), 128_000);
+        assert_eq!(context_length("gpt-4-0613"), 8_192);
+        assert_eq!(context_length("claude-3-5-haiku-latest"), 200_000);
+        assert_eq!(context_length("meta/Llama3.1:8b"), 128_000);
+        assert_eq!(context_length("unknown"), DEFAULT_CONTEXT_LENGTH);
+    }
+
+    #[test]
+    fn test_fit_keeps_system_and_latest_turns() {
+        let history = vec![
+            message(Role::System, 10),
+            message(Role::User, 20),
+            message(Role::Assistant, 20),
+            message(Role::User, 20),
+            message(Role::Assistant, 20),
+            message(Role::User, 20),
+        ];
+
+        let window = fit(&history, 75);
+        assert_eq!(window.system.len(), 1);
+        assert_eq!(window.kept.len(), 3);
+        assert_eq!(window.dropped.len(), 2);
+        assert_eq!(window.kept[0].role, Role::User);
+
+        // A dangling reply at the start is dropped as well
+        let window = fit(&history, 55);
+        assert_eq!(window.kept.len(), 1);
+        assert_eq!(window.dropped.len(), 4);
+
+        // The newest message survives even when nothing fits
+        let window = fit(&history, 0);
+        assert_eq!(window.kept.len(), 1);
+
+        let messages = window.into_messages(Some("Earlier"));
+        assert_eq!(messages.len(), 3);
+        assert!(messages[1].content.text().ends_with("Earlier"));
+    }
+}
@@ -0,0 +1,256 @@
+//! Chat session export
+//!
+//! This module renders chat sessions as Markdown for pasting into documents,
+//! as a self-contained HTML page, and as a lossless JSON document that can
+//! be imported again.
+
+use std::fmt::Write;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::utils::{AppError, AppResult};
+use super::super::ai::{ContentPart, Message};
+use super::ChatSession;
+
+/// Identifies Synapse exports in the JSON format
+pub const JSON_EXPORT_FORMAT: &str = "synapse-chat";
+
+/// Version of the JSON export format
+pub const JSON_EXPORT_VERSION: u32 = 1;
+
+/// Formats a session can be exported to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// Markdown with a header per message
+    Markdown,
+    /// Lossless JSON that can be imported again
+    Json,
+    /// A standalone HTML page
+    Html,
+}
+
+impl ExportFormat {
+    /// File extension conventionally used for the format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Markdown => "md",
+            Self::Json => "json",
+            Self::Html => "html",
+        }
+    }
+}
+
+/// The document written by JSON exports
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonExport {
+    /// Always `synapse-chat`
+    pub format: String,
+    /// Version of the export format
+    pub version: u32,
+    /// When the export was created
+    pub exported_at: DateTime<Utc>,
+    /// The exported session
+    pub session: ChatSession,
+}
+
+/// Renders a session in the given format
+pub fn export_session(session: &ChatSession, format: ExportFormat) -> AppResult<String> {
+    match format {
+        ExportFormat::Markdown => Ok(to_markdown(session)),
+        ExportFormat::Json => to_json(session),
+        ExportFormat::Html => Ok(to_html(session)),
+    }
+}
+
+/// Renders the active branch of a session as Markdown
+pub fn to_markdown(session: &ChatSession) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "# {}\n", session.title);
+    let _ = writeln!(out, "_Created {}_\n", format_time(session.created_at));
+
+    for message in session.active_branch() {
+        let _ = writeln!(out, "## {}\n", message_header(message));
+        let _ = writeln!(out, "{}\n", message.content.text().trim_end());
+        for part in message.content.parts.iter().filter_map(part_label) {
+            let _ = writeln!(out, "_{}_\n", part);
+        }
+    }
+    out
+}
+
+/// Renders a session as lossless JSON
+pub fn to_json(session: &ChatSession) -> AppResult<String> {
+    let export = JsonExport {
+        format: JSON_EXPORT_FORMAT.to_string(),
+        version: JSON_EXPORT_VERSION,
+        exported_at: Utc::now(),
+        session: session.clone(),
+    };
+    Ok(serde_json::to_string_pretty(&export)?)
+}
+
+/// Reads a session back from a JSON export
+///
+/// # Errors
+/// Returns an error if the document is not a Synapse export or was written
+/// by a newer version of the format
+pub fn from_json(json: &str) -> AppResult<ChatSession> {
+    let export: JsonExport = serde_json::from_str(json)
+        .map_err(|e| AppError::invalid_input(format!("Invalid chat export: {}", e)))?;
+
+    if export.format != JSON_EXPORT_FORMAT {
+        return Err(AppError::invalid_input(format!("Unknown export format: {}", export.format)));
+    }
+    if export.version > JSON_EXPORT_VERSION {
+        return Err(AppError::invalid_input(format!(
+            "Chat export version {} is not supported",
+            export.version
+        )));
+    }
+    Ok(export.session)
+}
+
+const HTML_STYLE: &str = "\
+body { font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', sans-serif; \
+max-width: 48rem; margin: 2rem auto; padding: 0 1rem; color: #1f2328; background: #fff; }
+h1 { font-size: 1.6rem; margin-bottom: 0.25rem; }
+.meta { color: #656d76; font-size: 0.85rem; }
+.message { border: 1px solid #d0d7de; border-radius: 8px; padding: 0.75rem 1rem; margin: 1rem 0; }
+.message.user { background: #f6f8fa; }
+.message header { font-weight: 600; margin-bottom: 0.5rem; }
+.message header .meta { font-weight: normal; margin-left: 0.5rem; }
+.content { white-space: pre-wrap; word-wrap: break-word; line-height: 1.5; }
+";
+
+/// Renders the active branch of a session as a standalone HTML page
+pub fn to_html(session: &ChatSession) -> String {
+    let title = escape_html(&session.title);
+    let mut out = String::new();
+    let _ = writeln!(out, "<!DOCTYPE html>");
+    let _ = writeln!(out, "<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">");
+    let _ = writeln!(out, "<title>{}</title>\n<style>\n{}</style>\n</head>\n<body>", title, HTML_STYLE);
+    let _ = writeln!(out, "<h1>{}</h1>", title);
+    let _ = writeln!(out, "<p class=\"meta\">Created {}</p>", format_time(session.created_at));
+
+    for message in session.active_branch() {
+        let _ = writeln!(out, "<section class=\"message {}\">", escape_html(message.role.as_str()));
+        let _ = write!(out, "<header>{}", escape_html(&role_label(message.role.as_str())));
+        let _ = write!(out, "<span class=\"meta\">{}</span>", escape_html(&message_details(message)));
+        let _ = writeln!(out, "</header>");
+        let _ = writeln!(out, "<div class=\"content\">{}</div>", escape_html(&message.content.text()));
+        for part in message.content.parts.iter().filter_map(part_label) {
+            let _ = writeln!(out, "<p class=\"meta\">{}</p>", escape_html(&part));
+        }
+        let _ = writeln!(out, "</section>");
+    }
+
+    let _ = writeln!(out, "</body>\n</html>");
+    out
+}
+
+/// Heading shown above a message, e.g. "Assistant · gpt-4o · 2024-05-01 10:32 UTC"
+fn message_header(message: &Message) -> String {
+    format!("{} · {}", role_label(message.role.as_str()), message_details(message))
+}
+
+/// Model and time a message was written at
+fn message_details(message: &Message) -> String {
+    let time = format_time(message.timestamp);
+    match &message.metadata.model {
+        Some(model) => format!("{} · {}", model, time),
+        None => time,
+    }
+}
+
+/// Line describing a part other than text, e.g. "Attachment: report.pdf (application/pdf, 12 KB)"
+fn part_label(part: &ContentPart) -> Option<String> {
+    match part {
+        ContentPart::Text { .. } => None,
+        ContentPart::File { name, mime_type, size, .. } => Some(format!(
+            "Attachment: {} ({}, {} KB)",
+            name,
+            mime_type,
+            (size + 1023) / 1024
+        )),
+        ContentPart::Image { mime_type, .. } => Some(format!("Image ({})", mime_type)),
+        ContentPart::ToolCall(call) => Some(format!("Called {} with {}", call.name, call.arguments)),
+        ContentPart::ToolResult(result) if result.is_error => Some(format!("Tool failed: {}", result.content)),
+        ContentPart::ToolResult(result) => Some(format!("Tool result: {}", result.content)),
+    }
+}
+
+fn role_label(role: &str) -> String {
+    let mut chars = role.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+fn format_time(time: DateTime<Utc>) -> String {
+    time.format("%Y-%m-%d %H:%M UTC").to_string()
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::ai::Role;
+    use crate::services::chat::{ContextStrategy, SessionSettings};
+
+    fn session() -> ChatSession {
+        let mut reply = Message::new(Role::Assistant, "Use `Vec<T>` & friends.");
+        reply.metadata.provider = Some("openai".to_string());
+        reply.metadata.model = Some("gpt-4o".to_string());
+        let mut session = ChatSession {
+            id: "session-1".to_string(),
+            title: "Collections <in> Rust".to_string(),
+            messages: vec![Message::new(Role::User, "Which collection?"), reply],
+            active_leaf: None,
+            context_strategy: ContextStrategy::default(),
+            context_summary: None,
+            settings: SessionSettings::default(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+        session.link_linear();
+        session
+    }
+
+    #[test]
+    fn test_exports() {
+        let session = session();
+
+        let markdown = to_markdown(&session);
+        assert!(markdown.starts_with("# Collections <in> Rust\n"));
+        assert!(markdown.contains("## User · "));
+        assert!(markdown.contains("## Assistant · gpt-4o · "));
+        assert!(markdown.contains("Use `Vec<T>` & friends."));
+
+        let html = to_html(&session);
+        assert!(html.contains("<title>Collections &lt;in&gt; Rust</title>"));
+        assert!(html.contains("Use `Vec&lt;T&gt;` &amp; friends."));
+        assert!(!html.contains("<link") && !html.contains("<script"));
+
+        let json = to_json(&session).unwrap();
+        let restored = from_json(&json).unwrap();
+        assert_eq!(serde_json::to_value(&restored).unwrap(), serde_json::to_value(&session).unwrap());
+        assert!(from_json(&json.replace(JSON_EXPORT_FORMAT, "other")).is_err());
+    }
+}
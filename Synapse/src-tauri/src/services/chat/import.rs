@@ -0,0 +1,575 @@
+//! Chat history import
+//!
+//! This module reads conversations exported from other assistants into
+//! `ChatSession` values. Supported are the ChatGPT `conversations.json`
+//! export, the Claude data export and Synapse's own JSON export.
+//!
+//! Imported sessions get ids derived from the id of the original
+//! conversation, so importing the same export twice finds the sessions that
+//! already exist instead of creating copies.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::utils::{AppError, AppResult};
+use super::super::ai::{Message, Role};
+use super::{export, ChatSession, ContextStrategy, SessionSettings};
+
+/// Title given to imported conversations without one
+const UNTITLED: &str = "Untitled conversation";
+
+/// Formats conversations can be imported from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportFormat {
+    /// A Synapse JSON export of a single session
+    Synapse,
+    /// The `conversations.json` file of a ChatGPT data export
+    Chatgpt,
+    /// The `conversations.json` file of a Claude data export
+    Claude,
+}
+
+impl ImportFormat {
+    /// Guesses the format of a parsed export
+    pub fn detect(value: &Value) -> Option<Self> {
+        if value.get("format").and_then(Value::as_str) == Some(export::JSON_EXPORT_FORMAT) {
+            return Some(Self::Synapse);
+        }
+
+        let first = value.as_array()?.first()?;
+        if first.get("mapping").is_some() {
+            Some(Self::Chatgpt)
+        } else if first.get("chat_messages").is_some() {
+            Some(Self::Claude)
+        } else {
+            None
+        }
+    }
+
+    /// Prefix of the key imported session ids are derived from
+    fn source(&self) -> &'static str {
+        match self {
+            Self::Synapse => "synapse",
+            Self::Chatgpt => "chatgpt",
+            Self::Claude => "claude",
+        }
+    }
+}
+
+/// A conversation or message that was not imported
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SkippedItem {
+    /// Title of the conversation, if known
+    pub title: Option<String>,
+    /// Id of the conversation in the export, if known
+    pub source_id: Option<String>,
+    /// Why the item was skipped
+    pub reason: String,
+}
+
+/// Summary of an import
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportReport {
+    /// Format the conversations were read from
+    pub format: ImportFormat,
+    /// Number of sessions created
+    pub imported: usize,
+    /// Number of conversations that had been imported before
+    pub duplicates: usize,
+    /// Number of messages in the created sessions
+    pub messages: usize,
+    /// Number of messages left out because they had no text or an unsupported role
+    pub skipped_messages: usize,
+    /// Conversations that could not be imported
+    pub skipped: Vec<SkippedItem>,
+}
+
+/// Conversations read from an export, before they are stored
+#[derive(Debug, Clone)]
+pub struct ParsedImport {
+    /// Format the conversations were read from
+    pub format: ImportFormat,
+    /// Sessions ready to be stored
+    pub sessions: Vec<ChatSession>,
+    /// Number of messages left out of the sessions
+    pub skipped_messages: usize,
+    /// Conversations that could not be converted
+    pub skipped: Vec<SkippedItem>,
+}
+
+impl ParsedImport {
+    fn new(format: ImportFormat) -> Self {
+        Self {
+            format,
+            sessions: Vec::new(),
+            skipped_messages: 0,
+            skipped: Vec::new(),
+        }
+    }
+
+    fn skip(&mut self, title: Option<String>, source_id: Option<String>, reason: impl Into<String>) {
+        self.skipped.push(SkippedItem {
+            title,
+            source_id,
+            reason: reason.into(),
+        });
+    }
+
+    /// Adds a converted conversation, skipping it if it has no messages
+    ///
+    /// Conversations without an active branch are read as a single branch.
+    fn push(&mut self, source_id: String, mut session: ChatSession) {
+        if session.active_leaf.is_none() {
+            session.link_linear();
+        }
+        if session.messages.is_empty() {
+            self.skip(Some(session.title), Some(source_id), "No messages with text");
+        } else {
+            self.sessions.push(session);
+        }
+    }
+}
+
+/// Parses an export, detecting its format unless one is given
+///
+/// Conversations that cannot be read are reported as skipped instead of
+/// failing the whole import.
+///
+/// # Errors
+/// Returns an error if the input is not JSON or its format is unknown
+pub fn parse(json: &str, format: Option<ImportFormat>) -> AppResult<ParsedImport> {
+    let value: Value = serde_json::from_str(json)
+        .map_err(|e| AppError::invalid_input(format!("Invalid export file: {}", e)))?;
+    let format = format
+        .or_else(|| ImportFormat::detect(&value))
+        .ok_or_else(|| AppError::invalid_input("Unrecognized export format"))?;
+
+    match format {
+        ImportFormat::Synapse => {
+            let mut parsed = ParsedImport::new(format);
+            let session = export::from_json(json)?;
+            parsed.push(session.id.clone(), session);
+            Ok(parsed)
+        }
+        ImportFormat::Chatgpt => Ok(parse_conversations(format, value, chatgpt_session)),
+        ImportFormat::Claude => Ok(parse_conversations(format, value, claude_session)),
+    }
+}
+
+/// Converts each entry of a conversation list with `convert`
+fn parse_conversations<T, F>(format: ImportFormat, value: Value, convert: F) -> ParsedImport
+where
+    T: for<'de> Deserialize<'de>,
+    F: Fn(T, &mut ParsedImport),
+{
+    let mut parsed = ParsedImport::new(format);
+    let conversations = match value {
+        Value::Array(conversations) => conversations,
+        _ => {
+            parsed.skip(None, None, "Expected a list of conversations");
+            return parsed;
+        }
+    };
+
+    for conversation in conversations {
+        let title = conversation.get("title")
+            .or_else(|| conversation.get("name"))
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        let source_id = ["conversation_id", "id", "uuid"]
+            .iter()
+            .find_map(|key| conversation.get(*key).and_then(Value::as_str))
+            .map(str::to_string);
+
+        match serde_json::from_value::<T>(conversation) {
+            Ok(conversation) => convert(conversation, &mut parsed),
+            Err(e) => parsed.skip(title, source_id, format!("Unreadable conversation: {}", e)),
+        }
+    }
+    parsed
+}
+
+/// Derives the id of an imported session from its original id
+fn session_id(format: ImportFormat, source_id: &str) -> String {
+    let key = format!("{}:{}", format.source(), source_id);
+    Uuid::new_v5(&Uuid::NAMESPACE_OID, key.as_bytes()).to_string()
+}
+
+fn title_or_default(title: Option<String>) -> String {
+    title
+        .map(|title| title.trim().to_string())
+        .filter(|title| !title.is_empty())
+        .unwrap_or_else(|| UNTITLED.to_string())
+}
+
+/// Converts seconds since the epoch, as used by ChatGPT, to a time
+fn seconds_to_datetime(seconds: f64) -> DateTime<Utc> {
+    DateTime::from_timestamp_millis((seconds * 1000.0).round() as i64).unwrap_or_default()
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatgptConversation {
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    conversation_id: Option<String>,
+    #[serde(default)]
+    title: Option<String>,
+    #[serde(default)]
+    create_time: Option<f64>,
+    #[serde(default)]
+    update_time: Option<f64>,
+    #[serde(default)]
+    current_node: Option<String>,
+    mapping: HashMap<String, ChatgptNode>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatgptNode {
+    #[serde(default)]
+    message: Option<ChatgptMessage>,
+    #[serde(default)]
+    parent: Option<String>,
+    #[serde(default)]
+    children: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatgptMessage {
+    author: ChatgptAuthor,
+    #[serde(default)]
+    create_time: Option<f64>,
+    #[serde(default)]
+    content: Option<ChatgptContent>,
+    #[serde(default)]
+    metadata: HashMap<String, Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatgptAuthor {
+    role: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatgptContent {
+    #[serde(default)]
+    parts: Vec<Value>,
+}
+
+/// Converts a ChatGPT conversation along the branch that was last shown
+fn chatgpt_session(conversation: ChatgptConversation, parsed: &mut ParsedImport) {
+    let source_id = conversation.conversation_id
+        .or(conversation.id)
+        .unwrap_or_else(|| {
+            let title = conversation.title.as_deref().unwrap_or_default();
+            format!("{}@{}", title, conversation.create_time.unwrap_or_default())
+        });
+    let created_at = seconds_to_datetime(conversation.create_time.unwrap_or_default());
+    let mapping = conversation.mapping;
+
+    // Older exports lack `current_node`; fall back to the newest leaf
+    let leaf = conversation.current_node
+        .filter(|id| mapping.contains_key(id))
+        .or_else(|| {
+            mapping.iter()
+                .filter(|(_, node)| node.children.is_empty())
+                .max_by(|(_, a), (_, b)| {
+                    let time = |node: &ChatgptNode| {
+                        node.message.as_ref().and_then(|m| m.create_time).unwrap_or_default()
+                    };
+                    time(a).total_cmp(&time(b))
+                })
+                .map(|(id, _)| id.clone())
+        });
+
+    let mut path = Vec::new();
+    let mut next = leaf;
+    while let Some(id) = next {
+        // Guard against cycles in malformed exports
+        if path.len() > mapping.len() {
+            break;
+        }
+        let Some(node) = mapping.get(&id) else { break };
+        path.push(node);
+        next = node.parent.clone();
+    }
+    path.reverse();
+
+    let mut messages = Vec::new();
+    for message in path.into_iter().filter_map(|node| node.message.as_ref()) {
+        let text: Vec<&str> = message.content
+            .iter()
+            .flat_map(|content| content.parts.iter())
+            .filter_map(Value::as_str)
+            .collect();
+        let text = text.join("\n");
+        let hidden = message.metadata
+            .get("is_visually_hidden_from_conversation")
+            .and_then(Value::as_bool)
+            .unwrap_or(false);
+        if hidden {
+            continue;
+        }
+        if text.trim().is_empty() {
+            // Images and files are not imported, so nothing of these is left
+            let has_other_parts = message.content
+                .iter()
+                .flat_map(|content| content.parts.iter())
+                .any(|part| !part.is_string());
+            if has_other_parts {
+                parsed.skipped_messages += 1;
+            }
+            continue;
+        }
+
+        // Tool calls and their results are not imported
+        let role = match message.author.role.parse() {
+            Ok(role @ (Role::System | Role::User | Role::Assistant)) => role,
+            _ => {
+                parsed.skipped_messages += 1;
+                continue;
+            }
+        };
+
+        let mut imported = Message::new(role, text);
+        imported.timestamp = message.create_time.map(seconds_to_datetime).unwrap_or(created_at);
+        if role == Role::Assistant {
+            imported.metadata.provider = Some("openai".to_string());
+            imported.metadata.model = message.metadata
+                .get("model_slug")
+                .and_then(Value::as_str)
+                .map(str::to_string);
+        }
+        messages.push(imported);
+    }
+
+    let updated_at = conversation.update_time
+        .map(seconds_to_datetime)
+        .or_else(|| messages.last().map(|m| m.timestamp))
+        .unwrap_or(created_at);
+    let session = ChatSession {
+        id: session_id(ImportFormat::Chatgpt, &source_id),
+        title: title_or_default(conversation.title),
+        messages,
+        active_leaf: None,
+        context_strategy: ContextStrategy::default(),
+        context_summary: None,
+        settings: SessionSettings::default(),
+        created_at,
+        updated_at,
+    };
+    parsed.push(source_id, session);
+}
+
+#[derive(Debug, Deserialize)]
+struct ClaudeConversation {
+    uuid: String,
+    #[serde(default)]
+    name: Option<String>,
+    created_at: DateTime<Utc>,
+    #[serde(default)]
+    updated_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    chat_messages: Vec<ClaudeMessage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClaudeMessage {
+    sender: String,
+    #[serde(default)]
+    text: String,
+    #[serde(default)]
+    content: Vec<ClaudeContent>,
+    #[serde(default)]
+    created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClaudeContent {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    text: Option<String>,
+}
+
+/// Converts a Claude conversation
+fn claude_session(conversation: ClaudeConversation, parsed: &mut ParsedImport) {
+    let mut messages = Vec::new();
+    for message in conversation.chat_messages {
+        // Newer exports split messages into content blocks
+        let blocks: Vec<&str> = message.content
+            .iter()
+            .filter(|block| block.kind == "text")
+            .filter_map(|block| block.text.as_deref())
+            .collect();
+        let text = if blocks.is_empty() { message.text } else { blocks.join("\n") };
+        if text.trim().is_empty() {
+            continue;
+        }
+
+        let role = match message.sender.as_str() {
+            "human" => Role::User,
+            "assistant" => Role::Assistant,
+            _ => {
+                parsed.skipped_messages += 1;
+                continue;
+            }
+        };
+
+        let mut imported = Message::new(role, text);
+        imported.timestamp = message.created_at.unwrap_or(conversation.created_at);
+        if role == Role::Assistant {
+            imported.metadata.provider = Some("anthropic".to_string());
+        }
+        messages.push(imported);
+    }
+
+    let session = ChatSession {
+        id: session_id(ImportFormat::Claude, &conversation.uuid),
+        title: title_or_default(conversation.name),
+        messages,
+        active_leaf: None,
+        context_strategy: ContextStrategy::default(),
+        context_summary: None,
+        settings: SessionSettings::default(),
+        created_at: conversation.created_at,
+        updated_at: conversation.updated_at.unwrap_or(conversation.created_at),
+    };
+    parsed.push(conversation.uuid, session);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_chatgpt_export() {
+        let export = json!([{
+            "title": "Sourdough",
+            "create_time": 1700000000.5,
+            "update_time": 1700000100.0,
+            "conversation_id": "c-1",
+            "current_node": "a2",
+            "mapping": {
+                "root": { "message": null, "parent": null, "children": ["sys"] },
+                "sys": {
+                    "message": {
+                        "author": { "role": "system" },
+                        "content": { "content_type": "text", "parts": [""] },
+                        "metadata": { "is_visually_hidden_from_conversation": true }
+                    },
+                    "parent": "root",
+                    "children": ["u1"]
+                },
+                "u1": {
+                    "message": {
+                        "author": { "role": "user" },
+                        "create_time": 1700000001.0,
+                        "content": { "content_type": "text", "parts": ["How long to proof?"] }
+                    },
+                    "parent": "sys",
+                    "children": ["a1", "img"]
+                },
+                "img": {
+                    "message": {
+                        "author": { "role": "user" },
+                        "content": {
+                            "content_type": "multimodal_text",
+                            "parts": [{ "content_type": "image_asset_pointer", "asset_pointer": "file-service://f-1" }]
+                        }
+                    },
+                    "parent": "u1",
+                    "children": ["a2"]
+                },
+                "a1": {
+                    "message": {
+                        "author": { "role": "assistant" },
+                        "create_time": 1700000002.0,
+                        "content": { "content_type": "text", "parts": ["Abandoned branch"] }
+                    },
+                    "parent": "u1",
+                    "children": []
+                },
+                "a2": {
+                    "message": {
+                        "author": { "role": "assistant" },
+                        "create_time": 1700000003.0,
+                        "content": { "content_type": "text", "parts": ["About 4 hours."] },
+                        "metadata": { "model_slug": "gpt-4o" }
+                    },
+                    "parent": "img",
+                    "children": []
+                }
+            }
+        }, {
+            "title": "Broken",
+            "conversation_id": "c-2"
+        }]);
+
+        let parsed = parse(&export.to_string(), None).unwrap();
+        assert_eq!(parsed.format, ImportFormat::Chatgpt);
+        assert_eq!(parsed.sessions.len(), 1);
+        assert_eq!(parsed.skipped.len(), 1);
+        assert_eq!(parsed.skipped[0].source_id.as_deref(), Some("c-2"));
+
+        let session = &parsed.sessions[0];
+        assert_eq!(session.title, "Sourdough");
+        assert_eq!(session.created_at.timestamp_millis(), 1_700_000_000_500);
+        let contents: Vec<_> = session.messages.iter().map(|m| m.content.text()).collect();
+        assert_eq!(contents, vec!["How long to proof?", "About 4 hours."]);
+        // The image-only message cannot be imported and is reported
+        assert_eq!(parsed.skipped_messages, 1);
+        assert_eq!(session.messages[1].timestamp.timestamp_millis(), 1_700_000_003_000);
+        assert_eq!(session.messages[1].metadata.model.as_deref(), Some("gpt-4o"));
+
+        // Ids are stable across imports
+        let again = parse(&export.to_string(), Some(ImportFormat::Chatgpt)).unwrap();
+        assert_eq!(again.sessions[0].id, session.id);
+    }
+
+    #[test]
+    fn test_parse_claude_export() {
+        let export = json!([{
+            "uuid": "conv-1",
+            "name": "",
+            "created_at": "2024-03-05T12:00:00.000000Z",
+            "updated_at": "2024-03-05T12:05:00.000000Z",
+            "chat_messages": [
+                { "sender": "human", "text": "Hi", "created_at": "2024-03-05T12:00:01Z" },
+                {
+                    "sender": "assistant",
+                    "text": "",
+                    "content": [{ "type": "text", "text": "Hello!" }],
+                    "created_at": "2024-03-05T12:00:02Z"
+                },
+                { "sender": "system", "text": "Unknown sender" }
+            ]
+        }, {
+            "uuid": "conv-2",
+            "name": "Empty",
+            "created_at": "2024-03-06T12:00:00Z",
+            "chat_messages": []
+        }]);
+
+        let parsed = parse(&export.to_string(), None).unwrap();
+        assert_eq!(parsed.format, ImportFormat::Claude);
+        assert_eq!(parsed.sessions.len(), 1);
+        assert_eq!(parsed.skipped_messages, 1);
+        assert_eq!(parsed.skipped[0].reason, "No messages with text");
+
+        let session = &parsed.sessions[0];
+        assert_eq!(session.title, UNTITLED);
+        let roles: Vec<_> = session.messages.iter().map(|m| m.role).collect();
+        assert_eq!(roles, vec![Role::User, Role::Assistant]);
+        assert_eq!(session.messages[1].content, "Hello!");
+        assert_eq!(session.messages[1].metadata.provider.as_deref(), Some("anthropic"));
+
+        assert!(parse("{}", None).is_err());
+    }
+}
@@ -3,13 +3,42 @@
 //! This module handles chat session management and interactions.
 //! It provides functionality for creating, managing, and persisting chat sessions.
 
+use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::Instant;
+use tokio::sync::{broadcast, RwLock};
+use log::{error, warn};
 use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
 
-use crate::utils::AppResult;
-use super::ai::{AIProvider, Message};
+use crate::utils::{AppError, AppResult};
+use super::ai::{
+    new_message_id, AIProvider, ChatCompletionParams, ContentPart, Message, MessageContent,
+    MessageMetadata, Role, ToolCall, ToolRegistry, ToolResult,
+};
+use super::documents::Retriever;
+
+pub mod attachments;
+mod branch;
+pub mod context;
+pub mod export;
+pub mod import;
+mod search;
+mod settings;
+mod store;
+pub mod title;
+
+pub use branch::Branch;
+pub use context::{ContextStrategy, ContextSummary};
+pub use export::ExportFormat;
+pub use import::{ImportFormat, ImportReport, SkippedItem};
+pub use search::{SearchQuery, SearchResult, SnippetSegment, DEFAULT_SEARCH_LIMIT};
+pub use settings::SessionSettings;
+pub use store::{ChatStore, SCHEMA_VERSION};
+pub use title::DEFAULT_SESSION_TITLE;
+
+/// Number of events buffered for slow subscribers
+const EVENT_CAPACITY: usize = 64;
 
 /// Represents a chat session
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -18,8 +47,63 @@ pub struct ChatSession {
     pub id: String,
     /// Title of the chat session
     pub title: String,
-    /// Messages in the session
+    /// Messages in the session, in the order they were created
+    ///
+    /// Messages form a tree through their `parent_id`; see `active_branch`
+    /// for the conversation as it is currently shown.
     pub messages: Vec<Message>,
+    /// Id of the last message of the active branch
+    #[serde(default)]
+    pub active_leaf: Option<String>,
+    /// How older messages are handled once the conversation outgrows the
+    /// model's context
+    #[serde(default)]
+    pub context_strategy: ContextStrategy,
+    /// Running summary of messages no longer sent to the model
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_summary: Option<ContextSummary>,
+    /// Provider and parameters overriding the configured defaults
+    #[serde(default)]
+    pub settings: SessionSettings,
+    /// When the session was created
+    pub created_at: DateTime<Utc>,
+    /// When the session was last updated
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Number of model replies generated for one prompt while the model keeps calling tools
+const MAX_TOOL_ROUNDS: usize = 8;
+
+/// Instruction sent to providers that cannot continue an assistant message directly
+const CONTINUE_PROMPT: &str =
+    "Continue your previous response exactly where it stopped, without repeating any of it.";
+
+/// Where a new message is attached in a session's message tree
+#[derive(Debug, Clone)]
+enum Attach {
+    /// After the last message of the active branch
+    ActiveLeaf,
+    /// As a reply to the given message, or as a first message if `None`
+    Parent(Option<String>),
+}
+
+/// Changes to sessions made in the background
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ChatEvent {
+    /// A session was given a new title
+    TitleUpdated { session_id: String, title: String },
+}
+
+/// A chat session without its messages, as shown in session lists
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatSessionSummary {
+    /// Unique identifier for the session
+    pub id: String,
+    /// Title of the chat session
+    pub title: String,
+    /// Number of messages in the session
+    pub message_count: usize,
     /// When the session was created
     pub created_at: DateTime<Utc>,
     /// When the session was last updated
@@ -27,82 +111,1376 @@ pub struct ChatSession {
 }
 
 /// Manages chat sessions and interactions with AI providers
+///
+/// Sessions are persisted in a `ChatStore` and loaded into memory the first
+/// time they are opened. Every change is written to the store before the
+/// in-memory copy is updated.
 #[derive(Debug)]
 pub struct ChatManager {
-    /// The active AI provider
-    provider: Arc<RwLock<Option<Arc<dyn AIProvider>>>>,
-    /// Active chat sessions
-    sessions: Arc<RwLock<Vec<ChatSession>>>,
+    /// Providers available to sessions, keyed by provider id
+    providers: Arc<RwLock<HashMap<String, Arc<dyn AIProvider>>>>,
+    /// Parameters configured for the providers, keyed by provider id
+    provider_params: Arc<RwLock<HashMap<String, ChatCompletionParams>>>,
+    /// Id of the provider used by sessions that do not choose one
+    default_provider: Arc<RwLock<Option<String>>>,
+    /// Sessions loaded from the store so far, keyed by id
+    sessions: Arc<RwLock<HashMap<String, ChatSession>>>,
+    /// Persistent storage for sessions
+    store: ChatStore,
+    /// Model used to title sessions instead of the conversation's model
+    title_model: Arc<RwLock<Option<String>>>,
+    /// Tools models may call while replying
+    tools: ToolRegistry,
+    /// Finds passages of the user's documents for prompts, if enabled
+    retriever: Arc<RwLock<Option<Retriever>>>,
+    /// Publishes background changes to subscribers
+    events: broadcast::Sender<ChatEvent>,
 }
 
 impl ChatManager {
-    /// Creates a new ChatManager instance
-    pub fn new() -> Self {
+    /// Creates a new ChatManager persisting sessions in `store`
+    pub fn new(store: ChatStore) -> Self {
         Self {
-            provider: Arc::new(RwLock::new(None)),
-            sessions: Arc::new(RwLock::new(Vec::new())),
+            providers: Arc::new(RwLock::new(HashMap::new())),
+            provider_params: Arc::new(RwLock::new(HashMap::new())),
+            default_provider: Arc::new(RwLock::new(None)),
+            sessions: Arc::new(RwLock::new(HashMap::new())),
+            store,
+            title_model: Arc::new(RwLock::new(None)),
+            tools: ToolRegistry::new(),
+            retriever: Arc::new(RwLock::new(None)),
+            events: broadcast::channel(EVENT_CAPACITY).0,
         }
     }
 
-    /// Sets the active AI provider
-    pub async fn set_provider(&self, provider: Arc<dyn AIProvider>) {
-        let mut provider_lock = self.provider.write().await;
-        *provider_lock = Some(provider);
+    /// The tools models may call while replying
+    pub fn tools(&self) -> &ToolRegistry {
+        &self.tools
+    }
+
+    /// Subscribes to changes made to sessions in the background
+    pub fn subscribe(&self) -> broadcast::Receiver<ChatEvent> {
+        self.events.subscribe()
+    }
+
+    /// Sets the model used to title sessions, or `None` to use the conversation's model
+    pub async fn set_title_model(&self, model: Option<String>) {
+        *self.title_model.write().await = model.filter(|model| !model.is_empty());
+    }
+
+    /// Sets how passages of the user's documents are found, or `None` to
+    /// answer without them
+    pub async fn set_retriever(&self, retriever: Option<Retriever>) {
+        *self.retriever.write().await = retriever;
+    }
+
+    /// Makes a provider available under its id, replacing any earlier instance
+    pub async fn add_provider(&self, id: impl Into<String>, provider: Arc<dyn AIProvider>) {
+        self.providers.write().await.insert(id.into(), provider);
+    }
+
+    /// Makes a provider available under its id together with the parameters
+    /// configured for it, replacing any earlier instance
+    pub async fn add_configured_provider(
+        &self,
+        id: impl Into<String>,
+        provider: Arc<dyn AIProvider>,
+        params: ChatCompletionParams
+    ) {
+        let id = id.into();
+        self.providers.write().await.insert(id.clone(), provider);
+        self.provider_params.write().await.insert(id, params);
+    }
+
+    /// The parameters configured for a provider added with
+    /// [`Self::add_configured_provider`]
+    pub async fn configured_params(&self, id: &str) -> Option<ChatCompletionParams> {
+        self.provider_params.read().await.get(id).cloned()
+    }
+
+    /// Removes all providers, returning the ids they were available under
+    pub async fn clear_providers(&self) -> Vec<String> {
+        self.provider_params.write().await.clear();
+        self.providers.write().await.drain().map(|(id, _)| id).collect()
+    }
+
+    /// Sets the provider used by sessions that do not choose one
+    pub async fn set_default_provider(&self, id: Option<String>) {
+        *self.default_provider.write().await = id;
     }
 
     /// Creates a new chat session
     pub async fn create_session(&self, title: String) -> AppResult<ChatSession> {
+        let now = Utc::now();
         let session = ChatSession {
             id: uuid::Uuid::new_v4().to_string(),
             title,
             messages: Vec::new(),
-            created_at: Utc::now(),
-            updated_at: Utc::now(),
+            active_leaf: None,
+            context_strategy: ContextStrategy::default(),
+            context_summary: None,
+            settings: SessionSettings::default(),
+            created_at: now,
+            updated_at: now,
         };
 
+        self.store.save_session(&session).await?;
         let mut sessions = self.sessions.write().await;
-        (*sessions).push(session.clone());
+        sessions.insert(session.id.clone(), session.clone());
         Ok(session)
     }
 
-    /// Gets a chat session by ID
+    /// Gets a chat session by ID, loading it from the store if needed
     pub async fn get_session(&self, id: &str) -> AppResult<Option<ChatSession>> {
-        let sessions = self.sessions.read().await;
-        Ok((*sessions).iter().find(|s| s.id == id).cloned())
+        if let Some(session) = self.sessions.read().await.get(id) {
+            return Ok(Some(session.clone()));
+        }
+
+        let loaded = self.store.load_session(id).await?;
+        if let Some(session) = &loaded {
+            let mut sessions = self.sessions.write().await;
+            sessions.entry(session.id.clone()).or_insert_with(|| session.clone());
+        }
+        Ok(loaded)
+    }
+
+    /// Lists all chat sessions, most recently updated first
+    pub async fn list_sessions(&self) -> AppResult<Vec<ChatSessionSummary>> {
+        self.store.list_sessions().await
     }
 
-    /// Lists all chat sessions
-    pub async fn list_sessions(&self) -> AppResult<Vec<ChatSession>> {
-        let sessions = self.sessions.read().await;
-        Ok((*sessions).clone())
+    /// Renders a chat session in the given format
+    ///
+    /// # Errors
+    /// Returns an error if the session does not exist
+    pub async fn export_session(&self, id: &str, format: ExportFormat) -> AppResult<String> {
+        let session = self.get_session(id)
+            .await?
+            .ok_or_else(|| AppError::not_found("Chat session not found"))?;
+        export::export_session(&session, format)
     }
 
-    /// Adds a message to a chat session
+    /// Imports conversations from an export of Synapse or another assistant
+    ///
+    /// Conversations that were imported before are counted as duplicates
+    /// and left untouched.
+    ///
+    /// # Errors
+    /// Returns an error if the export cannot be parsed or storing fails
+    pub async fn import_sessions(
+        &self,
+        json: &str,
+        format: Option<ImportFormat>,
+    ) -> AppResult<ImportReport> {
+        let parsed = import::parse(json, format)?;
+        let mut report = ImportReport {
+            format: parsed.format,
+            imported: 0,
+            duplicates: 0,
+            messages: 0,
+            skipped_messages: parsed.skipped_messages,
+            skipped: parsed.skipped,
+        };
+
+        for session in parsed.sessions {
+            if self.store.insert_session(&session).await? {
+                report.imported += 1;
+                report.messages += session.messages.len();
+            } else {
+                report.duplicates += 1;
+            }
+        }
+        Ok(report)
+    }
+
+    /// Searches message contents and session titles across all sessions
+    ///
+    /// # Errors
+    /// Returns an error if the query contains no words
+    pub async fn search(&self, query: &SearchQuery) -> AppResult<Vec<SearchResult>> {
+        self.store.search(query).await
+    }
+
+    /// Adds a message to the end of a session's active branch
     pub async fn add_message(&self, session_id: &str, message: Message) -> AppResult<()> {
+        self.attach(session_id, Attach::ActiveLeaf, message).await?;
+        Ok(())
+    }
+
+    /// Stores a file with a session so messages can refer to it
+    ///
+    /// # Returns
+    /// The image or file part to include in a message
+    ///
+    /// # Errors
+    /// Returns an error if the session does not exist, or the file is of an
+    /// unsupported type or too large
+    pub async fn add_attachment(&self, session_id: &str, name: &str, data: &[u8]) -> AppResult<ContentPart> {
+        self.get_session(session_id)
+            .await?
+            .ok_or_else(|| AppError::not_found("Chat session not found"))?;
+        self.store.attachments().save(session_id, name, data).await
+    }
+
+    /// Adds a message to the message tree and makes it the active leaf
+    ///
+    /// Inline images and files are moved to the session's attachments.
+    async fn attach(&self, session_id: &str, attach: Attach, mut message: Message) -> AppResult<Message> {
+        // Make sure the session is loaded before appending to it
+        self.get_session(session_id)
+            .await?
+            .ok_or_else(|| AppError::not_found("Chat session not found"))?;
+        self.store.attachments().store_inline(session_id, &mut message.content).await?;
+
         let mut sessions = self.sessions.write().await;
-        if let Some(session) = (*sessions).iter_mut().find(|s| s.id == session_id) {
-            session.messages.push(message);
-            session.updated_at = Utc::now();
-            Ok(())
-        } else {
-            Err(crate::utils::AppError::not_found("Chat session not found"))
+        let session = sessions
+            .get_mut(session_id)
+            .ok_or_else(|| AppError::not_found("Chat session not found"))?;
+
+        message.parent_id = match attach {
+            Attach::ActiveLeaf => session.active_leaf.clone(),
+            Attach::Parent(parent_id) => parent_id,
+        };
+        if let Some(parent_id) = &message.parent_id {
+            if session.message(parent_id).is_none() {
+                return Err(AppError::not_found("Message not found"));
+            }
         }
+
+        let updated_at = Utc::now();
+        self.store
+            .append_message(session_id, session.messages.len(), &message, updated_at)
+            .await?;
+        session.active_leaf = Some(message.id.clone());
+        session.messages.push(message.clone());
+        session.updated_at = updated_at;
+        Ok(message)
     }
 
-    /// Deletes a chat session
-    pub async fn delete_session(&self, id: &str) -> AppResult<()> {
+    /// Returns the provider a session generates with
+    ///
+    /// This is the session's own provider if it chose one, and the default
+    /// provider otherwise.
+    async fn require_provider(&self, session_id: &str) -> AppResult<Arc<dyn AIProvider>> {
+        let session = self.get_session(session_id)
+            .await?
+            .ok_or_else(|| AppError::not_found("Chat session not found"))?;
+        let id = match session.settings.provider_id {
+            Some(id) => id,
+            None => self.default_provider
+                .read()
+                .await
+                .clone()
+                .ok_or_else(|| AppError::invalid_input("No AI provider configured"))?,
+        };
+
+        self.providers
+            .read()
+            .await
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| AppError::invalid_input(format!("AI provider {} is not available", id)))
+    }
+
+    /// Replaces the provider and parameters chosen for a session
+    ///
+    /// Switching the model mid-conversation is allowed; every assistant
+    /// message records the provider and model that generated it.
+    ///
+    /// # Errors
+    /// Returns an error if the settings are invalid or the session does not exist
+    pub async fn update_session_settings(
+        &self,
+        session_id: &str,
+        settings: SessionSettings,
+    ) -> AppResult<ChatSession> {
+        let settings = settings.normalized();
+        settings.validate().map_err(AppError::invalid_input)?;
+        self.get_session(session_id)
+            .await?
+            .ok_or_else(|| AppError::not_found("Chat session not found"))?;
+
+        self.store.set_session_settings(session_id, &settings).await?;
         let mut sessions = self.sessions.write().await;
-        if let Some(pos) = (*sessions).iter().position(|s| s.id == id) {
-            (*sessions).remove(pos);
-            Ok(())
+        let session = sessions
+            .get_mut(session_id)
+            .ok_or_else(|| AppError::not_found("Chat session not found"))?;
+        session.settings = settings;
+        Ok(session.clone())
+    }
+
+    /// Generates a reply to the session's active branch and appends it
+    async fn complete(
+        &self,
+        provider: Arc<dyn AIProvider>,
+        session_id: &str,
+        params: ChatCompletionParams,
+    ) -> AppResult<Message> {
+        let message = self.respond(provider.as_ref(), session_id, Attach::ActiveLeaf, params.clone()).await?;
+        self.title_in_background(session_id, provider, params).await;
+        Ok(message)
+    }
+
+    /// Generates replies, running the tools the model calls, until it answers
+    ///
+    /// Every reply is attached to the message tree. When a reply calls
+    /// tools, their results are attached as a tool message and the model is
+    /// asked again. After `MAX_TOOL_ROUNDS` replies the last one is
+    /// returned; calls it still makes are answered with errors instead of
+    /// being run. Passages of the user's documents that match the latest
+    /// prompt are added to the system prompt.
+    async fn respond(
+        &self,
+        provider: &dyn AIProvider,
+        session_id: &str,
+        mut attach: Attach,
+        mut params: ChatCompletionParams,
+    ) -> AppResult<Message> {
+        let mut request = ChatCompletionParams {
+            tools: self.tools.definitions(),
+            ..params.clone()
+        };
+
+        let mut rounds = 0;
+        loop {
+            rounds += 1;
+            let session = self.get_session(session_id)
+                .await?
+                .ok_or_else(|| AppError::not_found("Chat session not found"))?;
+            let history: Vec<Message> = match &attach {
+                Attach::ActiveLeaf => session.active_branch(),
+                Attach::Parent(Some(parent_id)) => session.branch_to(parent_id),
+                Attach::Parent(None) => Vec::new(),
+            }
+            .into_iter()
+            .cloned()
+            .collect();
+
+            if rounds == 1 {
+                if let Some(system_prompt) = self.retrieve(&history, params.system_prompt.as_deref()).await {
+                    params.system_prompt = Some(system_prompt);
+                    request.system_prompt = params.system_prompt.clone();
+                }
+            }
+            let history = self.fit_context(provider, &session, history, &params).await?;
+            let reply = self.generate_into(provider, session_id, attach, history, request.clone()).await?;
+
+            let calls: Vec<ToolCall> = reply.content.tool_calls().cloned().collect();
+            if calls.is_empty() {
+                return Ok(reply);
+            }
+
+            // Every call needs a result, or providers reject the history
+            let limited = rounds >= MAX_TOOL_ROUNDS;
+            let mut results = Vec::with_capacity(calls.len());
+            for call in &calls {
+                let result = if limited {
+                    ToolResult::error(&call.id, "Tool round limit reached")
+                } else {
+                    self.tools.execute(call).await
+                };
+                results.push(ContentPart::ToolResult(result));
+            }
+            let results = self
+                .attach(session_id, Attach::Parent(Some(reply.id.clone())), Message::new(Role::Tool, results))
+                .await?;
+            if limited {
+                return Ok(reply);
+            }
+            attach = Attach::Parent(Some(results.id));
+        }
+    }
+
+    /// Extends the system prompt with passages matching the latest prompt
+    ///
+    /// Returns `None` if retrieval is off or found nothing. Failures are
+    /// logged rather than failing the reply.
+    async fn retrieve(&self, history: &[Message], system_prompt: Option<&str>) -> Option<String> {
+        let retriever = self.retriever.read().await.clone()?;
+        let question = history
+            .iter()
+            .rev()
+            .find(|message| message.role == Role::User)
+            .map(|message| message.content.text())
+            .filter(|text| !text.trim().is_empty())?;
+
+        match retriever.augment(system_prompt, &question).await {
+            Ok(system_prompt) => system_prompt,
+            Err(e) => {
+                warn!("Failed to look up passages from documents: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Fits a branch into the model's context window
+    ///
+    /// Sessions using `ContextStrategy::Summarize` replace the messages that
+    /// no longer fit with a running summary. The summary is only extended
+    /// once new messages fall out of the window, and then by half a window
+    /// at a time, so most requests reuse the stored summary. If summarizing
+    /// fails, the older messages are dropped instead.
+    async fn fit_context(
+        &self,
+        provider: &dyn AIProvider,
+        session: &ChatSession,
+        history: Vec<Message>,
+        params: &ChatCompletionParams,
+    ) -> AppResult<Vec<Message>> {
+        // Failed replies carry no content worth sending
+        let history: Vec<Message> = history.into_iter().filter(|message| !message.is_failed()).collect();
+        let budget = context::budget(params);
+        let window = context::fit(&history, budget);
+        if window.dropped.is_empty() || session.context_strategy == ContextStrategy::Truncate {
+            return Ok(window.into_messages(None));
+        }
+
+        // Leave out the messages the stored summary covers, if it belongs to this branch
+        let previous = session.context_summary.as_ref().and_then(|summary| {
+            let covered = history.iter().position(|message| message.id == summary.through)?;
+            Some((summary.text.as_str(), covered))
+        });
+        let unsummarized: Vec<Message> = history
+            .iter()
+            .enumerate()
+            .filter(|(index, message)| {
+                previous.map_or(true, |(_, covered)| *index > covered) || message.role == Role::System
+            })
+            .map(|(_, message)| message.clone())
+            .collect();
+        let previous = previous.map(|(text, _)| text);
+
+        if let Some(text) = previous {
+            let window = context::fit(&unsummarized, budget.saturating_sub(context::estimate_text_tokens(text)));
+            if window.dropped.is_empty() {
+                return Ok(window.into_messages(Some(text)));
+            }
+        }
+
+        let window = context::fit(&unsummarized, budget / 2);
+        let Some(through) = window.dropped.last().map(|message| message.id.clone()) else {
+            return Ok(window.into_messages(previous));
+        };
+        match context::summarize(provider, previous, &window.dropped, params.clone()).await {
+            Ok(text) => {
+                let summary = ContextSummary { text, through };
+                self.store.set_context_summary(&session.id, Some(&summary)).await?;
+                if let Some(cached) = self.sessions.write().await.get_mut(&session.id) {
+                    cached.context_summary = Some(summary.clone());
+                }
+                Ok(window.into_messages(Some(&summary.text)))
+            }
+            Err(e) => {
+                warn!("Failed to summarize older messages, dropping them instead: {}", e);
+                Ok(context::fit(&history, budget).into_messages(None))
+            }
+        }
+    }
+
+    /// Sets how a session handles conversations that outgrow the model's context
+    ///
+    /// # Errors
+    /// Returns an error if the session does not exist
+    pub async fn set_context_strategy(
+        &self,
+        session_id: &str,
+        strategy: ContextStrategy,
+    ) -> AppResult<()> {
+        self.store.set_context_strategy(session_id, strategy).await?;
+        if let Some(session) = self.sessions.write().await.get_mut(session_id) {
+            session.context_strategy = strategy;
+        }
+        Ok(())
+    }
+
+    /// Titles a session after its first exchange without blocking the caller
+    ///
+    /// Only sessions still carrying the default title are titled. A
+    /// `ChatEvent::TitleUpdated` is published once the title is stored.
+    async fn title_in_background(
+        &self,
+        session_id: &str,
+        provider: Arc<dyn AIProvider>,
+        mut params: ChatCompletionParams,
+    ) {
+        let (exchange, own_provider): (Vec<Message>, bool) = match self.sessions.read().await.get(session_id) {
+            Some(session) if session.title == DEFAULT_SESSION_TITLE => (
+                // The prompt and the final answer, without the tool rounds in between
+                session.active_branch()
+                    .into_iter()
+                    .filter(|message| !message.is_failed() && message.role != Role::Tool)
+                    .filter(|message| message.content.tool_calls().next().is_none())
+                    .cloned()
+                    .collect(),
+                session.settings.provider_id.is_some(),
+            ),
+            _ => return,
+        };
+        if exchange.iter().filter(|message| message.role == Role::Assistant).count() != 1 {
+            return;
+        }
+
+        // The title model belongs to the default provider
+        if !own_provider {
+            if let Some(model) = self.title_model.read().await.clone() {
+                params.model = model;
+            }
+        }
+        let session_id = session_id.to_string();
+        let store = self.store.clone();
+        let sessions = self.sessions.clone();
+        let events = self.events.clone();
+
+        tokio::spawn(async move {
+            let title = match title::generate(provider.as_ref(), &exchange, params).await {
+                Ok(title) => title,
+                Err(e) => {
+                    warn!("Failed to generate session title, using fallback: {}", e);
+                    title::heuristic(&exchange)
+                }
+            };
+
+            // Leave titles alone that were changed in the meantime
+            let mut sessions = sessions.write().await;
+            let Some(session) = sessions.get_mut(&session_id) else { return };
+            if session.title != DEFAULT_SESSION_TITLE {
+                return;
+            }
+            if let Err(e) = store.rename_session(&session_id, &title).await {
+                error!("Failed to store session title: {}", e);
+                return;
+            }
+            session.title = title.clone();
+            let _ = events.send(ChatEvent::TitleUpdated { session_id, title });
+        });
+    }
+
+    /// Renames a chat session
+    ///
+    /// # Errors
+    /// Returns an error if the session does not exist
+    pub async fn rename_session(&self, id: &str, title: String) -> AppResult<()> {
+        self.store.rename_session(id, &title).await?;
+        if let Some(session) = self.sessions.write().await.get_mut(id) {
+            session.title = title;
+        }
+        Ok(())
+    }
+
+    /// Requests a completion and records how it was generated
+    async fn generate(
+        provider: &dyn AIProvider,
+        history: Vec<Message>,
+        params: ChatCompletionParams,
+    ) -> AppResult<Message> {
+        let model = params.model.clone();
+        let started = Instant::now();
+        let completion = provider.create_chat_completion(history, params).await?;
+
+        let mut message = completion.message;
+        message.metadata = MessageMetadata {
+            provider: Some(provider.name().to_string()),
+            model: Some(model),
+            finish_reason: completion.finish_reason,
+            usage: Some(completion.usage),
+            latency_ms: Some(u64::try_from(started.elapsed().as_millis()).unwrap_or(u64::MAX)),
+            error: None,
+        };
+        Ok(message)
+    }
+
+    /// Generates a reply and attaches it to the message tree
+    ///
+    /// If the request fails, an empty assistant message carrying the error
+    /// is attached instead, so the failure stays visible and can be
+    /// regenerated, and the error is returned.
+    async fn generate_into(
+        &self,
+        provider: &dyn AIProvider,
+        session_id: &str,
+        attach: Attach,
+        history: Vec<Message>,
+        params: ChatCompletionParams,
+    ) -> AppResult<Message> {
+        let model = params.model.clone();
+        let history = self.resolve_attachments(session_id, history).await?;
+        match Self::generate(provider, history, params).await {
+            Ok(message) => self.attach(session_id, attach, message).await,
+            Err(e) => {
+                let mut failed = Message::new(Role::Assistant, "");
+                failed.metadata.provider = Some(provider.name().to_string());
+                failed.metadata.model = Some(model);
+                failed.metadata.error = Some(e.to_string());
+                self.attach(session_id, attach, failed).await?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Replaces attachment references in messages with their data
+    async fn resolve_attachments(&self, session_id: &str, mut messages: Vec<Message>) -> AppResult<Vec<Message>> {
+        for message in &mut messages {
+            self.store.attachments().resolve(session_id, &mut message.content).await?;
+        }
+        Ok(messages)
+    }
+
+    /// Finds an assistant message, defaulting to the end of the active branch
+    async fn assistant_message(
+        &self,
+        session_id: &str,
+        message_id: Option<&str>,
+    ) -> AppResult<(ChatSession, Message)> {
+        let session = self.get_session(session_id)
+            .await?
+            .ok_or_else(|| AppError::not_found("Chat session not found"))?;
+
+        let message = message_id
+            .or(session.active_leaf.as_deref())
+            .and_then(|id| session.message(id))
+            .cloned()
+            .ok_or_else(|| AppError::not_found("Message not found"))?;
+        if message.role != Role::Assistant {
+            return Err(AppError::invalid_input("Not an assistant message"));
+        }
+        Ok((session, message))
+    }
+
+    /// Generates a new version of an assistant message
+    ///
+    /// The new version is added as a sibling of the old one, which stays
+    /// available through `list_branches`. `params` may differ from the ones
+    /// the original was generated with.
+    ///
+    /// # Arguments
+    /// * `message_id` - The message to regenerate; defaults to the last
+    ///   message of the active branch
+    ///
+    /// # Errors
+    /// Returns an error if no provider is set, the message does not exist or
+    /// is not an assistant message, or the provider request fails
+    pub async fn regenerate(
+        &self,
+        session_id: &str,
+        message_id: Option<&str>,
+        params: ChatCompletionParams,
+    ) -> AppResult<Message> {
+        let provider = self.require_provider(session_id).await?;
+        let (_, original) = self.assistant_message(session_id, message_id).await?;
+        self.respond(provider.as_ref(), session_id, Attach::Parent(original.parent_id), params)
+            .await
+    }
+
+    /// Continues an assistant message that was cut off by the token limit
+    ///
+    /// Providers that support it continue the message directly; others are
+    /// asked to pick up where it stopped. The continuation is appended to
+    /// the message in place.
+    ///
+    /// # Arguments
+    /// * `message_id` - The message to continue; defaults to the last
+    ///   message of the active branch
+    ///
+    /// # Errors
+    /// Returns an error if no provider is set, the message does not exist or
+    /// was not truncated, or the provider request fails
+    pub async fn continue_message(
+        &self,
+        session_id: &str,
+        message_id: Option<&str>,
+        params: ChatCompletionParams,
+    ) -> AppResult<Message> {
+        let provider = self.require_provider(session_id).await?;
+        let (session, mut message) = self.assistant_message(session_id, message_id).await?;
+        if !message.is_truncated() {
+            return Err(AppError::invalid_input("Message was not cut off by the token limit"));
+        }
+
+        let mut history: Vec<Message> = session.branch_to(&message.id).into_iter().cloned().collect();
+        if provider.supports_prefill() {
+            // Prefilled assistant messages may not end in whitespace
+            message.content.trim_end();
+            if let Some(last) = history.last_mut() {
+                last.content = message.content.clone();
+            }
         } else {
-            Err(crate::utils::AppError::not_found("Chat session not found"))
+            history.push(Message::new(Role::User, CONTINUE_PROMPT));
+        }
+        let history = self.fit_context(provider.as_ref(), &session, history, &params).await?;
+        let history = self.resolve_attachments(session_id, history).await?;
+
+        let continuation = Self::generate(provider.as_ref(), history, params).await?;
+        message.content.push_text(&continuation.content.text());
+
+        // Usage and latency cover both requests
+        let previous = message.metadata;
+        message.metadata = continuation.metadata;
+        if let (Some(usage), Some(before)) = (message.metadata.usage.as_mut(), previous.usage) {
+            usage.prompt_tokens += before.prompt_tokens;
+            usage.completion_tokens += before.completion_tokens;
+            usage.total_tokens += before.total_tokens;
+        }
+        if let (Some(latency), Some(before)) = (message.metadata.latency_ms.as_mut(), previous.latency_ms) {
+            *latency += before;
+        }
+
+        self.replace_message(session_id, message).await
+    }
+
+    /// Replaces a stored message with a new version under the same id
+    async fn replace_message(&self, session_id: &str, message: Message) -> AppResult<Message> {
+        let mut sessions = self.sessions.write().await;
+        let session = sessions
+            .get_mut(session_id)
+            .ok_or_else(|| AppError::not_found("Chat session not found"))?;
+        let stored = session.messages
+            .iter_mut()
+            .find(|stored| stored.id == message.id)
+            .ok_or_else(|| AppError::not_found("Message not found"))?;
+
+        let updated_at = Utc::now();
+        self.store.update_message(session_id, &message, updated_at).await?;
+        *stored = message.clone();
+        session.updated_at = updated_at;
+        Ok(message)
+    }
+
+    /// Sends a user message and returns the assistant's reply
+    ///
+    /// The user message is appended to the active branch, the branch is
+    /// sent to the active provider, and the reply is appended as well.
+    /// Images and files in the message may be inline or previously added
+    /// with `add_attachment`.
+    ///
+    /// # Errors
+    /// Returns an error if no provider is set, the session does not exist,
+    /// or the provider request fails. The user message is kept in the
+    /// session even if the request fails.
+    pub async fn send_message(
+        &self,
+        session_id: &str,
+        content: impl Into<MessageContent>,
+        params: ChatCompletionParams,
+    ) -> AppResult<Message> {
+        let provider = self.require_provider(session_id).await?;
+        self.add_message(session_id, Message::new(Role::User, content)).await?;
+        self.complete(provider, session_id, params).await
+    }
+
+    /// Edits a message by adding the new version as a sibling branch
+    ///
+    /// The original message and everything after it are kept on their own
+    /// branch. Editing a user message also generates a new reply to it,
+    /// which is returned; otherwise the edited message is returned. Images
+    /// and files of the original message are kept. `params` are only
+    /// needed for the reply, so edits of other messages may omit them.
+    ///
+    /// # Errors
+    /// Returns an error if the session or message does not exist, or a
+    /// reply is needed and no parameters are given or the provider request
+    /// fails
+    pub async fn edit_message(
+        &self,
+        session_id: &str,
+        message_id: &str,
+        content: String,
+        params: Option<ChatCompletionParams>,
+    ) -> AppResult<Message> {
+        let original = self.get_session(session_id)
+            .await?
+            .ok_or_else(|| AppError::not_found("Chat session not found"))?
+            .message(message_id)
+            .cloned()
+            .ok_or_else(|| AppError::not_found("Message not found"))?;
+
+        let mut edited = Message::new(original.role, original.content.with_text(content));
+        edited.metadata.provider = original.metadata.provider;
+        edited.metadata.model = original.metadata.model;
+
+        if original.role != Role::User {
+            return self.attach(session_id, Attach::Parent(original.parent_id), edited).await;
         }
+
+        let params = params
+            .ok_or_else(|| AppError::invalid_input("Parameters are needed to reply to the edited message"))?;
+        let provider = self.require_provider(session_id).await?;
+        self.attach(session_id, Attach::Parent(original.parent_id), edited).await?;
+        self.complete(provider, session_id, params).await
+    }
+
+    /// Lists the alternative branches at a message, including the message itself
+    ///
+    /// # Errors
+    /// Returns an error if the session or message does not exist
+    pub async fn list_branches(&self, session_id: &str, message_id: &str) -> AppResult<Vec<Branch>> {
+        self.get_session(session_id)
+            .await?
+            .ok_or_else(|| AppError::not_found("Chat session not found"))?
+            .branches_at(message_id)
+            .ok_or_else(|| AppError::not_found("Message not found"))
+    }
+
+    /// Makes the branch containing a message active
+    ///
+    /// The conversation continues from the most recent message below
+    /// `message_id`.
+    ///
+    /// # Errors
+    /// Returns an error if the session or message does not exist
+    pub async fn switch_branch(&self, session_id: &str, message_id: &str) -> AppResult<ChatSession> {
+        self.get_session(session_id)
+            .await?
+            .ok_or_else(|| AppError::not_found("Chat session not found"))?;
+
+        let mut sessions = self.sessions.write().await;
+        let session = sessions
+            .get_mut(session_id)
+            .ok_or_else(|| AppError::not_found("Chat session not found"))?;
+        if session.message(message_id).is_none() {
+            return Err(AppError::not_found("Message not found"));
+        }
+
+        let leaf = session.latest_leaf(message_id);
+        self.store.set_active_leaf(session_id, &leaf).await?;
+        session.active_leaf = Some(leaf);
+        Ok(session.clone())
+    }
+
+    /// Copies a branch into a new standalone session
+    ///
+    /// The new session contains the messages from the start of the
+    /// conversation up to `message_id`, or the whole active branch if no
+    /// message is given.
+    ///
+    /// # Errors
+    /// Returns an error if the session or message does not exist
+    pub async fn fork_session(&self, session_id: &str, message_id: Option<&str>) -> AppResult<ChatSession> {
+        let source = self.get_session(session_id)
+            .await?
+            .ok_or_else(|| AppError::not_found("Chat session not found"))?;
+
+        let branch = match message_id {
+            Some(id) if source.message(id).is_none() => {
+                return Err(AppError::not_found("Message not found"));
+            }
+            Some(id) => source.branch_to(id),
+            None => source.active_branch(),
+        };
+
+        let now = Utc::now();
+        let mut fork = ChatSession {
+            id: uuid::Uuid::new_v4().to_string(),
+            title: format!("{} (fork)", source.title),
+            messages: branch
+                .into_iter()
+                .map(|message| Message {
+                    id: new_message_id(),
+                    ..message.clone()
+                })
+                .collect(),
+            active_leaf: None,
+            context_strategy: source.context_strategy,
+            context_summary: None,
+            settings: source.settings.clone(),
+            created_at: now,
+            updated_at: now,
+        };
+        fork.link_linear();
+
+        for message in &fork.messages {
+            self.store.attachments().copy(&source.id, &fork.id, &message.content).await?;
+        }
+        self.store.save_session(&fork).await?;
+        self.sessions.write().await.insert(fork.id.clone(), fork.clone());
+        Ok(fork)
+    }
+
+    /// Deletes a chat session
+    pub async fn delete_session(&self, id: &str) -> AppResult<()> {
+        self.store.delete_session(id).await?;
+        self.sessions.write().await.remove(id);
+        Ok(())
     }
 }
 
-impl Default for ChatManager {
-    fn default() -> Self {
-        Self::new()
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use std::sync::Mutex;
+    use crate::services::ai::{ChatCompletion, CompletionUsage, Tool};
+
+    /// Provider replying with the number of messages it received
+    ///
+    /// When tools are offered, it first calls the first of them with that
+    /// number before replying.
+    #[derive(Debug)]
+    struct CountingProvider;
+
+    #[async_trait]
+    impl AIProvider for CountingProvider {
+        fn name(&self) -> &str {
+            "counting"
+        }
+
+        fn available_models(&self) -> Vec<String> {
+            vec!["counter".to_string()]
+        }
+
+        async fn create_chat_completion(
+            &self,
+            messages: Vec<Message>,
+            params: ChatCompletionParams
+        ) -> AppResult<ChatCompletion> {
+            if params.model == "unavailable" {
+                return Err(AppError::api("Model unavailable"));
+            }
+            if let Some(tool) = params.tools.first() {
+                if messages.last().map(|message| message.role) == Some(Role::User) {
+                    let call = ToolCall {
+                        id: "call-1".to_string(),
+                        name: tool.name.clone(),
+                        arguments: serde_json::json!({ "messages": messages.len() }),
+                    };
+                    return Ok(ChatCompletion {
+                        message: Message::new(Role::Assistant, MessageContent::reply(String::new(), vec![call])),
+                        usage: CompletionUsage::default(),
+                        finish_reason: Some("tool_calls".to_string()),
+                    });
+                }
+            }
+            // A limit of a single token simulates a truncated reply
+            let finish_reason = if params.max_tokens == 1 { "length" } else { "stop" };
+            Ok(ChatCompletion {
+                message: Message::new(Role::Assistant, format!("{} messages", messages.len())),
+                usage: CompletionUsage::default(),
+                finish_reason: Some(finish_reason.to_string()),
+            })
+        }
+
+        async fn validate_api_key(&self, _api_key: &str) -> AppResult<bool> {
+            Ok(true)
+        }
     }
-} 
\ No newline at end of file
+
+    /// Provider calling the first tool offered on every request
+    ///
+    /// It records the messages of every request it receives.
+    #[derive(Debug, Default)]
+    struct LoopingProvider {
+        requests: Mutex<Vec<Vec<Message>>>,
+    }
+
+    #[async_trait]
+    impl AIProvider for LoopingProvider {
+        fn name(&self) -> &str {
+            "looping"
+        }
+
+        fn available_models(&self) -> Vec<String> {
+            vec!["counter".to_string()]
+        }
+
+        async fn create_chat_completion(
+            &self,
+            messages: Vec<Message>,
+            params: ChatCompletionParams
+        ) -> AppResult<ChatCompletion> {
+            let mut requests = self.requests.lock().unwrap();
+            requests.push(messages);
+            let calls = params.tools
+                .iter()
+                .take(1)
+                .map(|tool| ToolCall {
+                    id: format!("call-{}", requests.len()),
+                    name: tool.name.clone(),
+                    arguments: serde_json::json!({}),
+                })
+                .collect();
+            Ok(ChatCompletion {
+                message: Message::new(Role::Assistant, MessageContent::reply(String::new(), calls)),
+                usage: CompletionUsage::default(),
+                finish_reason: Some("tool_calls".to_string()),
+            })
+        }
+
+        async fn validate_api_key(&self, _api_key: &str) -> AppResult<bool> {
+            Ok(true)
+        }
+    }
+
+    /// Tool returning its arguments
+    #[derive(Debug)]
+    struct EchoTool;
+
+    #[async_trait]
+    impl Tool for EchoTool {
+        fn name(&self) -> &str {
+            "echo"
+        }
+
+        fn description(&self) -> &str {
+            "Returns its arguments"
+        }
+
+        fn parameters(&self) -> serde_json::Value {
+            serde_json::json!({ "type": "object" })
+        }
+
+        async fn execute(&self, arguments: serde_json::Value) -> AppResult<String> {
+            Ok(arguments.to_string())
+        }
+    }
+
+    async fn use_counting_provider(manager: &ChatManager) {
+        manager.add_provider("counting", Arc::new(CountingProvider)).await;
+        manager.set_default_provider(Some("counting".to_string())).await;
+    }
+
+    fn params() -> ChatCompletionParams {
+        ChatCompletionParams {
+            model: "counter".to_string(),
+            temperature: 0.0,
+            max_tokens: 0,
+            system_prompt: None,
+            tools: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_send_message() {
+        let manager = ChatManager::new(ChatStore::open_in_memory().unwrap());
+        let session = manager.create_session("Test".to_string()).await.unwrap();
+
+        // Without a provider the request is rejected before touching the session
+        assert!(manager.send_message(&session.id, "Hello".to_string(), params()).await.is_err());
+
+        use_counting_provider(&manager).await;
+        let first = manager.send_message(&session.id, "Hello".to_string(), params()).await.unwrap();
+        let second = manager.send_message(&session.id, "Again".to_string(), params()).await.unwrap();
+
+        // The provider sees the whole history, including the new message
+        assert_eq!(first.content, "1 messages");
+        assert_eq!(second.content, "3 messages");
+        let session = manager.get_session(&session.id).await.unwrap().unwrap();
+        let roles: Vec<_> = session.messages.iter().map(|m| m.role).collect();
+        assert_eq!(roles, vec![Role::User, Role::Assistant, Role::User, Role::Assistant]);
+        assert_eq!(second.metadata.model.as_deref(), Some("counter"));
+        assert!(second.metadata.usage.is_some() && second.metadata.latency_ms.is_some());
+
+        // Failed requests are recorded on the branch but not sent again
+        let unavailable = ChatCompletionParams { model: "unavailable".to_string(), ..params() };
+        assert!(manager.send_message(&session.id, "Third".to_string(), unavailable).await.is_err());
+        let failed = manager.get_session(&session.id).await.unwrap().unwrap().messages.pop().unwrap();
+        assert_eq!(failed.metadata.error.as_deref(), Some("API error: Model unavailable"));
+        let retried = manager.regenerate(&session.id, None, params()).await.unwrap();
+        assert_eq!(retried.content, "5 messages");
+
+        assert!(manager.send_message("missing", "Hello".to_string(), params()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sessions_load_lazily() {
+        let store = ChatStore::open_in_memory().unwrap();
+        let session = {
+            let manager = ChatManager::new(store.clone());
+            let session = manager.create_session("Persisted".to_string()).await.unwrap();
+            use_counting_provider(&manager).await;
+            manager.send_message(&session.id, "Hello".to_string(), params()).await.unwrap();
+            session
+        };
+
+        // A fresh manager lists sessions without loading their messages
+        let manager = ChatManager::new(store);
+        assert!(manager.sessions.read().await.is_empty());
+        let summaries = manager.list_sessions().await.unwrap();
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].message_count, 2);
+        assert!(manager.sessions.read().await.is_empty());
+
+        // Opening the session loads its history
+        let loaded = manager.get_session(&session.id).await.unwrap().unwrap();
+        assert_eq!(loaded.messages.len(), 2);
+        manager.add_message(&session.id, Message::new(Role::User, "Later")).await.unwrap();
+
+        manager.delete_session(&session.id).await.unwrap();
+        assert!(manager.get_session(&session.id).await.unwrap().is_none());
+        assert!(manager.list_sessions().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_edit_and_fork_branches() {
+        let store = ChatStore::open_in_memory().unwrap();
+        let manager = ChatManager::new(store.clone());
+        use_counting_provider(&manager).await;
+        let session = manager.create_session("Branches".to_string()).await.unwrap();
+
+        manager.send_message(&session.id, "First".to_string(), params()).await.unwrap();
+        manager.send_message(&session.id, "Second".to_string(), params()).await.unwrap();
+        let original = manager.get_session(&session.id).await.unwrap().unwrap();
+        let second = original.active_branch()[2].clone();
+
+        // Editing adds a sibling and answers it from the shortened history
+        let reply = manager
+            .edit_message(&session.id, &second.id, "Second, edited".to_string(), Some(params()))
+            .await
+            .unwrap();
+        assert_eq!(reply.content, "3 messages");
+
+        let edited = manager.get_session(&session.id).await.unwrap().unwrap();
+        assert_eq!(edited.messages.len(), 6);
+        let contents: Vec<_> = edited.active_branch().iter().map(|m| m.content.text()).collect();
+        assert_eq!(contents, vec!["First", "1 messages", "Second, edited", "3 messages"]);
+
+        let branches = manager.list_branches(&session.id, &second.id).await.unwrap();
+        assert_eq!(branches.len(), 2);
+        assert!(!branches[0].active && branches[1].active);
+
+        // Switching back restores the original conversation, also after reloading
+        manager.switch_branch(&session.id, &second.id).await.unwrap();
+        let reloaded = ChatManager::new(store).get_session(&session.id).await.unwrap().unwrap();
+        let contents: Vec<_> = reloaded.active_branch().iter().map(|m| m.content.text()).collect();
+        assert_eq!(contents, vec!["First", "1 messages", "Second", "3 messages"]);
+
+        // Forking copies a branch into a linear session
+        let fork = manager.fork_session(&session.id, Some(&second.id)).await.unwrap();
+        assert_eq!(fork.messages.len(), 3);
+        assert_eq!(fork.active_branch().len(), 3);
+        assert_ne!(fork.messages[2].id, second.id);
+        assert_eq!(manager.list_sessions().await.unwrap().len(), 2);
+        assert!(manager.fork_session(&session.id, Some("missing")).await.is_err());
+
+        // Edited replies are not answered, so they need no parameters
+        let reply = original.active_branch()[1].clone();
+        let edited = manager
+            .edit_message(&session.id, &reply.id, "One message".to_string(), None)
+            .await
+            .unwrap();
+        assert_eq!(edited.role, Role::Assistant);
+        assert!(manager.edit_message(&session.id, &second.id, "Again".to_string(), None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_regenerate_and_continue() {
+        let manager = ChatManager::new(ChatStore::open_in_memory().unwrap());
+        use_counting_provider(&manager).await;
+        let session = manager.create_session("Variants".to_string()).await.unwrap();
+
+        let truncated = ChatCompletionParams { max_tokens: 1, ..params() };
+        let first = manager.send_message(&session.id, "Hi".to_string(), truncated.clone()).await.unwrap();
+        assert!(first.is_truncated());
+
+        // Regenerating keeps the earlier variant as a sibling
+        let second = manager.regenerate(&session.id, None, truncated).await.unwrap();
+        assert_eq!(second.parent_id, first.parent_id);
+        let branches = manager.list_branches(&session.id, &first.id).await.unwrap();
+        assert_eq!(branches.len(), 2);
+        assert!(branches[1].active);
+
+        // Continuing appends to the truncated message in place
+        let continued = manager.continue_message(&session.id, None, params()).await.unwrap();
+        assert_eq!(continued.id, second.id);
+        assert_eq!(continued.content, "1 messages3 messages");
+        assert!(!continued.is_truncated());
+
+        let stored = manager.get_session(&session.id).await.unwrap().unwrap();
+        assert_eq!(stored.message(&second.id).unwrap().content, continued.content);
+        assert!(manager.continue_message(&session.id, None, params()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sessions_are_titled_after_first_exchange() {
+        let manager = ChatManager::new(ChatStore::open_in_memory().unwrap());
+        use_counting_provider(&manager).await;
+        let mut events = manager.subscribe();
+
+        let session = manager.create_session(DEFAULT_SESSION_TITLE.to_string()).await.unwrap();
+        manager.send_message(&session.id, "Hello".to_string(), params()).await.unwrap();
+
+        let event = tokio::time::timeout(std::time::Duration::from_secs(5), events.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        let ChatEvent::TitleUpdated { session_id, title } = event;
+        assert_eq!(session_id, session.id);
+        assert_eq!(title, "2 messages");
+        assert_eq!(manager.list_sessions().await.unwrap()[0].title, "2 messages");
+
+        // Later exchanges and custom titles are left alone
+        manager.send_message(&session.id, "Again".to_string(), params()).await.unwrap();
+        let named = manager.create_session("Named".to_string()).await.unwrap();
+        manager.send_message(&named.id, "Hello".to_string(), params()).await.unwrap();
+        tokio::task::yield_now().await;
+        assert!(events.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sessions_choose_their_provider() {
+        let manager = ChatManager::new(ChatStore::open_in_memory().unwrap());
+        use_counting_provider(&manager).await;
+        let session = manager.create_session("Test".to_string()).await.unwrap();
+
+        let invalid = SessionSettings { temperature: Some(1.5), ..Default::default() };
+        assert!(manager.update_session_settings(&session.id, invalid).await.is_err());
+
+        // Sessions choosing a provider that is not available cannot generate
+        let settings = SessionSettings {
+            provider_id: Some("other".to_string()),
+            model: Some(" ".to_string()),
+            system_prompt: Some("Be brief.".to_string()),
+            ..Default::default()
+        };
+        let updated = manager.update_session_settings(&session.id, settings).await.unwrap();
+        assert_eq!(updated.settings.model, None);
+        assert!(manager.send_message(&session.id, "Hello".to_string(), params()).await.is_err());
+
+        manager.add_provider("other", Arc::new(CountingProvider)).await;
+        manager.set_default_provider(None).await;
+        manager.send_message(&session.id, "Hello".to_string(), params()).await.unwrap();
+
+        // Switching models mid-conversation is recorded per reply
+        let params = updated.settings.apply(params());
+        assert_eq!(params.system_prompt.as_deref(), Some("Be brief."));
+        let switched = ChatCompletionParams { model: "other-model".to_string(), ..params };
+        let reply = manager.send_message(&session.id, "Again".to_string(), switched).await.unwrap();
+        assert_eq!(reply.metadata.model.as_deref(), Some("other-model"));
+
+        let reloaded = ChatManager::new(manager.store.clone())
+            .get_session(&session.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(reloaded.settings, updated.settings);
+        let models: Vec<_> = reloaded.active_branch()
+            .iter()
+            .filter_map(|message| message.metadata.model.as_deref())
+            .collect();
+        assert_eq!(models, vec!["counter", "other-model"]);
+    }
+
+    #[tokio::test]
+    async fn test_configured_providers_are_cached_until_cleared() {
+        let manager = ChatManager::new(ChatStore::open_in_memory().unwrap());
+        manager.add_configured_provider("counting", Arc::new(CountingProvider), params()).await;
+        manager.set_default_provider(Some("counting".to_string())).await;
+        assert_eq!(manager.configured_params("counting").await.unwrap().model, "counter");
+
+        let session = manager.create_session("Test".to_string()).await.unwrap();
+        manager.send_message(&session.id, "Hello".to_string(), params()).await.unwrap();
+
+        assert_eq!(manager.clear_providers().await, vec!["counting".to_string()]);
+        assert!(manager.configured_params("counting").await.is_none());
+        assert!(manager.send_message(&session.id, "Again".to_string(), params()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_long_sessions_fit_the_context() {
+        let manager = ChatManager::new(ChatStore::open_in_memory().unwrap());
+        use_counting_provider(&manager).await;
+        let session = manager.create_session("Test".to_string()).await.unwrap();
+
+        // Leave room for three messages of 30 tokens and short replies
+        let params = ChatCompletionParams {
+            max_tokens: (context::DEFAULT_CONTEXT_LENGTH - 100) as i32,
+            ..params()
+        };
+        let long = "x".repeat(104);
+        for _ in 0..3 {
+            manager.send_message(&session.id, long.clone(), params.clone()).await.unwrap();
+        }
+        let session = manager.get_session(&session.id).await.unwrap().unwrap();
+        assert_eq!(session.active_branch().last().unwrap().content, "3 messages");
+
+        // Summarizing replaces the older turns with a single system message
+        manager.set_context_strategy(&session.id, ContextStrategy::Summarize).await.unwrap();
+        let reply = manager.send_message(&session.id, long.clone(), params.clone()).await.unwrap();
+        assert_eq!(reply.content, "2 messages");
+        let summary = manager.get_session(&session.id).await.unwrap().unwrap().context_summary.unwrap();
+        assert_eq!(summary.text, "1 messages");
+
+        // The stored summary is reused while the newer turns still fit
+        let reply = manager.send_message(&session.id, long.clone(), params.clone()).await.unwrap();
+        assert_eq!(reply.content, "4 messages");
+        let session = manager.get_session(&session.id).await.unwrap().unwrap();
+        assert_eq!(session.context_summary, Some(summary));
+        assert_eq!(session.context_strategy, ContextStrategy::Summarize);
+    }
+
+    #[tokio::test]
+    async fn test_import_skips_duplicates() {
+        let manager = ChatManager::new(ChatStore::open_in_memory().unwrap());
+        let original = manager.create_session("Exported".to_string()).await.unwrap();
+        manager.add_message(&original.id, Message::new(Role::User, "Keep me")).await.unwrap();
+        let json = manager.export_session(&original.id, ExportFormat::Json).await.unwrap();
+
+        // Re-importing into the same history finds the existing session
+        let report = manager.import_sessions(&json, None).await.unwrap();
+        assert_eq!(report.format, ImportFormat::Synapse);
+        assert_eq!((report.imported, report.duplicates), (0, 1));
+
+        manager.delete_session(&original.id).await.unwrap();
+        let report = manager.import_sessions(&json, None).await.unwrap();
+        assert_eq!((report.imported, report.duplicates, report.messages), (1, 0, 1));
+
+        let restored = manager.get_session(&original.id).await.unwrap().unwrap();
+        assert_eq!(restored.title, "Exported");
+        assert_eq!(restored.messages[0].content, "Keep me");
+    }
+
+    #[tokio::test]
+    async fn test_tool_calls_are_run() {
+        let manager = ChatManager::new(ChatStore::open_in_memory().unwrap());
+        use_counting_provider(&manager).await;
+        manager.tools().register(Arc::new(EchoTool));
+        let session = manager.create_session("Test".to_string()).await.unwrap();
+
+        // The model calls the tool, then answers with its result in view
+        let reply = manager.send_message(&session.id, "Hello".to_string(), params()).await.unwrap();
+        assert_eq!(reply.content, "3 messages");
+
+        let session = manager.get_session(&session.id).await.unwrap().unwrap();
+        let branch = session.active_branch();
+        let roles: Vec<_> = branch.iter().map(|m| m.role).collect();
+        assert_eq!(roles, vec![Role::User, Role::Assistant, Role::Tool, Role::Assistant]);
+        assert_eq!(branch[1].content.tool_calls().next().unwrap().name, "echo");
+        let results: Vec<_> = branch[2].content.tool_results().collect();
+        assert_eq!(results, vec![&ToolResult::success("call-1", r#"{"messages":1}"#)]);
+
+        // Calls to unknown tools are reported back to the model
+        let call = ToolCall { id: "call-2".to_string(), name: "missing".to_string(), arguments: serde_json::json!({}) };
+        assert!(manager.tools().execute(&call).await.is_error);
+    }
+
+    #[tokio::test]
+    async fn test_tool_rounds_are_limited() {
+        let manager = ChatManager::new(ChatStore::open_in_memory().unwrap());
+        let provider = Arc::new(LoopingProvider::default());
+        manager.add_provider("looping", provider.clone()).await;
+        manager.set_default_provider(Some("looping".to_string())).await;
+        manager.tools().register(Arc::new(EchoTool));
+        let session = manager.create_session("Test".to_string()).await.unwrap();
+
+        // The last round's calls are answered with errors instead of being run
+        manager.send_message(&session.id, "Hello".to_string(), params()).await.unwrap();
+        assert_eq!(provider.requests.lock().unwrap().len(), MAX_TOOL_ROUNDS);
+        let session = manager.get_session(&session.id).await.unwrap().unwrap();
+        let last = session.active_branch().last().cloned().unwrap();
+        assert_eq!(last.role, Role::Tool);
+        let results: Vec<_> = last.content.tool_results().cloned().collect();
+        let expected = ToolResult::error(&format!("call-{}", MAX_TOOL_ROUNDS), "Tool round limit reached");
+        assert_eq!(results, vec![expected]);
+
+        // The next request pairs every call with its result
+        manager.send_message(&session.id, "Again".to_string(), params()).await.unwrap();
+        let history = provider.requests.lock().unwrap()[MAX_TOOL_ROUNDS].clone();
+        assert_eq!(history.last().map(|message| message.role), Some(Role::User));
+        for (index, message) in history.iter().enumerate() {
+            for call in message.content.tool_calls() {
+                let answered = history
+                    .get(index + 1)
+                    .is_some_and(|next| next.content.tool_results().any(|result| result.call_id == call.id));
+                assert!(answered, "{} has no result", call.id);
+            }
+        }
+    }
+}
@@ -0,0 +1,152 @@
+//! Chat history search
+//!
+//! This module defines the queries and results of full-text search over
+//! stored chat sessions. The search itself runs against the SQLite FTS5
+//! index maintained by the `ChatStore`.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::super::ai::Role;
+
+/// Number of results returned when a query does not set a limit
+pub const DEFAULT_SEARCH_LIMIT: usize = 50;
+
+/// Marks the start of a highlighted match in raw FTS5 snippets
+pub(crate) const HIGHLIGHT_START: char = '\u{E000}';
+/// Marks the end of a highlighted match in raw FTS5 snippets
+pub(crate) const HIGHLIGHT_END: char = '\u{E001}';
+
+/// A full-text search over chat history
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchQuery {
+    /// Words to search for; every word must match, the last one as a prefix
+    pub text: String,
+    /// Only match messages sent at or after this time
+    #[serde(default)]
+    pub from: Option<DateTime<Utc>>,
+    /// Only match messages sent at or before this time
+    #[serde(default)]
+    pub to: Option<DateTime<Utc>>,
+    /// Only match messages with this role
+    #[serde(default)]
+    pub role: Option<Role>,
+    /// Only match messages generated by this provider
+    #[serde(default)]
+    pub provider: Option<String>,
+    /// Only match messages generated by this model
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Maximum number of results
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+impl SearchQuery {
+    /// Whether the query filters on message attributes a session title lacks
+    pub(crate) fn filters_messages(&self) -> bool {
+        self.role.is_some() || self.provider.is_some() || self.model.is_some()
+    }
+
+    /// Translates the search text into an FTS5 match expression
+    ///
+    /// Every word is quoted so user input can never be parsed as FTS5 query
+    /// syntax. Returns `None` if the text contains no words.
+    pub(crate) fn match_expression(&self) -> Option<String> {
+        let words: Vec<_> = self.text.split_whitespace().collect();
+        let (last, rest) = words.split_last()?;
+
+        let mut terms: Vec<String> = rest.iter().map(|word| quote(word)).collect();
+        terms.push(format!("{}*", quote(last)));
+        Some(terms.join(" "))
+    }
+}
+
+fn quote(word: &str) -> String {
+    format!("\"{}\"", word.replace('"', "\"\""))
+}
+
+/// A piece of a result snippet
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnippetSegment {
+    /// Text of the segment
+    pub text: String,
+    /// Whether the segment matched the query
+    pub highlighted: bool,
+}
+
+/// Splits a raw FTS5 snippet into plain and highlighted segments
+pub(crate) fn parse_snippet(raw: &str) -> Vec<SnippetSegment> {
+    let mut segments = Vec::new();
+    let mut text = String::new();
+    let mut highlighted = false;
+
+    for c in raw.chars() {
+        let toggles = (c == HIGHLIGHT_START && !highlighted) || (c == HIGHLIGHT_END && highlighted);
+        if !toggles {
+            text.push(c);
+            continue;
+        }
+        if !text.is_empty() {
+            segments.push(SnippetSegment {
+                text: std::mem::take(&mut text),
+                highlighted,
+            });
+        }
+        highlighted = !highlighted;
+    }
+
+    if !text.is_empty() {
+        segments.push(SnippetSegment { text, highlighted });
+    }
+    segments
+}
+
+/// A single search hit
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchResult {
+    /// The session containing the match
+    pub session_id: String,
+    /// Title of the session
+    pub session_title: String,
+    /// Index of the matching message in `ChatSession::messages`, or `None`
+    /// if the title matched
+    pub message_index: Option<usize>,
+    /// Id of the matching message
+    pub message_id: Option<String>,
+    /// Role of the matching message
+    pub role: Option<Role>,
+    /// Provider that generated the matching message
+    pub provider: Option<String>,
+    /// Model that generated the matching message
+    pub model: Option<String>,
+    /// When the message was sent, or the session last updated
+    pub timestamp: i64,
+    /// Excerpt around the match with the matching words highlighted
+    pub snippet: Vec<SnippetSegment>,
+    /// Relevance of the match; higher is better
+    pub score: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_match_expression_and_snippets() {
+        let query = SearchQuery {
+            text: "  borrow \"checker  ".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(query.match_expression().unwrap(), "\"borrow\" \"\"\"checker\"*");
+        assert!(SearchQuery::default().match_expression().is_none());
+
+        let raw = format!("the {}borrow{} {}checker{} says", HIGHLIGHT_START, HIGHLIGHT_END, HIGHLIGHT_START, HIGHLIGHT_END);
+        let segments = parse_snippet(&raw);
+        let texts: Vec<_> = segments.iter().map(|s| (s.text.as_str(), s.highlighted)).collect();
+        assert_eq!(
+            texts,
+            vec![("the ", false), ("borrow", true), (" ", false), ("checker", true), (" says", false)]
+        );
+    }
+}
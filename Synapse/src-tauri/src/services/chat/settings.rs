@@ -0,0 +1,73 @@
+//! Per-session settings
+//!
+//! This module defines the provider and completion parameters a session can
+//! choose for itself. Anything a session leaves unset falls back to the
+//! provider configuration in the application settings.
+
+use serde::{Deserialize, Serialize};
+
+use super::super::ai::ChatCompletionParams;
+
+/// Provider and parameters chosen for a single session
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SessionSettings {
+    /// Id of the configured provider to use instead of the active one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider_id: Option<String>,
+    /// Model to generate with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Temperature for response generation (0.0 to 1.0)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    /// Maximum tokens to generate
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    /// System prompt sent with every request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_prompt: Option<String>,
+}
+
+impl SessionSettings {
+    /// Drops empty strings so they fall back to the defaults
+    pub fn normalized(self) -> Self {
+        let non_empty = |value: Option<String>| value.filter(|value| !value.trim().is_empty());
+        Self {
+            provider_id: non_empty(self.provider_id),
+            model: non_empty(self.model),
+            temperature: self.temperature,
+            max_tokens: self.max_tokens,
+            system_prompt: non_empty(self.system_prompt),
+        }
+    }
+
+    /// Validates the parameters
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(temperature) = self.temperature {
+            if !(0.0..=1.0).contains(&temperature) {
+                return Err("Temperature must be between 0 and 1".to_string());
+            }
+        }
+        if self.max_tokens == Some(0) {
+            return Err("Max tokens must be greater than 0".to_string());
+        }
+        Ok(())
+    }
+
+    /// Applies the settings on top of a provider's default parameters
+    pub fn apply(&self, mut params: ChatCompletionParams) -> ChatCompletionParams {
+        if let Some(model) = &self.model {
+            params.model = model.clone();
+        }
+        if let Some(temperature) = self.temperature {
+            params.temperature = temperature;
+        }
+        if let Some(max_tokens) = self.max_tokens {
+            params.max_tokens = i32::try_from(max_tokens).unwrap_or(i32::MAX);
+        }
+        if let Some(system_prompt) = &self.system_prompt {
+            params.system_prompt = Some(system_prompt.clone());
+        }
+        params
+    }
+}
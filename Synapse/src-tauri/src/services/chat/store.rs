@@ -0,0 +1,959 @@
+//! Chat history storage
+//!
+//! This module persists chat sessions and their messages in an embedded
+//! SQLite database. Every write runs in a transaction on a database in WAL
+//! mode, so a crash leaves either the old or the new state on disk, never a
+//! partial one. Sessions are listed without their messages; messages are
+//! only read when a session is opened. Attachments are kept as files in a
+//! directory next to the database.
+
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSqlOutput, ValueRef};
+use rusqlite::{params, Connection, OptionalExtension, Row, ToSql};
+
+use crate::utils::{AppError, AppResult};
+use super::super::ai::{CompletionUsage, ContentPart, Message, MessageContent, MessageMetadata, Role};
+use super::attachments::AttachmentStore;
+use super::search::{self, SearchQuery, SearchResult, DEFAULT_SEARCH_LIMIT};
+use super::{ChatSession, ChatSessionSummary, ContextStrategy, ContextSummary, SessionSettings};
+
+/// Schema migrations, applied in order
+///
+/// The schema version stored in the database is the number of migrations
+/// that have been applied. New migrations must only ever be appended.
+const MIGRATIONS: &[&str] = &[
+    // 1: sessions and their messages
+    "CREATE TABLE sessions (
+        id TEXT PRIMARY KEY,
+        title TEXT NOT NULL,
+        created_at INTEGER NOT NULL,
+        updated_at INTEGER NOT NULL
+    );
+    CREATE TABLE messages (
+        session_id TEXT NOT NULL REFERENCES sessions(id) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        role TEXT NOT NULL,
+        content TEXT NOT NULL,
+        timestamp INTEGER NOT NULL,
+        PRIMARY KEY (session_id, position)
+    );",
+    // 2: provider and model of generated messages, and full-text search
+    "ALTER TABLE messages ADD COLUMN provider TEXT;
+    ALTER TABLE messages ADD COLUMN model TEXT;
+    CREATE VIRTUAL TABLE messages_fts USING fts5(
+        content, session_id UNINDEXED, position UNINDEXED
+    );
+    CREATE VIRTUAL TABLE sessions_fts USING fts5(title, session_id UNINDEXED);
+    INSERT INTO messages_fts (content, session_id, position)
+        SELECT content, session_id, position FROM messages;
+    INSERT INTO sessions_fts (title, session_id) SELECT title, id FROM sessions;
+    CREATE TRIGGER messages_fts_insert AFTER INSERT ON messages BEGIN
+        INSERT INTO messages_fts (content, session_id, position)
+            VALUES (new.content, new.session_id, new.position);
+    END;
+    CREATE TRIGGER messages_fts_update AFTER UPDATE OF content ON messages BEGIN
+        UPDATE messages_fts SET content = new.content
+            WHERE session_id = old.session_id AND position = old.position;
+    END;
+    CREATE TRIGGER messages_fts_delete AFTER DELETE ON messages BEGIN
+        DELETE FROM messages_fts WHERE session_id = old.session_id AND position = old.position;
+    END;
+    CREATE TRIGGER sessions_fts_insert AFTER INSERT ON sessions BEGIN
+        INSERT INTO sessions_fts (title, session_id) VALUES (new.title, new.id);
+    END;
+    CREATE TRIGGER sessions_fts_update AFTER UPDATE OF title ON sessions BEGIN
+        UPDATE sessions_fts SET title = new.title WHERE session_id = old.id;
+    END;
+    CREATE TRIGGER sessions_fts_delete AFTER DELETE ON sessions BEGIN
+        DELETE FROM sessions_fts WHERE session_id = old.id;
+    END;",
+    // 3: message trees; existing sessions become a single branch
+    "ALTER TABLE messages ADD COLUMN id TEXT;
+    ALTER TABLE messages ADD COLUMN parent_id TEXT;
+    ALTER TABLE sessions ADD COLUMN active_leaf TEXT;
+    UPDATE messages SET id = lower(hex(randomblob(16)));
+    UPDATE messages SET parent_id = (
+        SELECT p.id FROM messages p
+        WHERE p.session_id = messages.session_id AND p.position = messages.position - 1
+    );
+    UPDATE sessions SET active_leaf = (
+        SELECT m.id FROM messages m WHERE m.session_id = sessions.id
+        ORDER BY m.position DESC LIMIT 1
+    );
+    CREATE UNIQUE INDEX messages_by_id ON messages (session_id, id);",
+    // 4: why generation of a message stopped
+    "ALTER TABLE messages ADD COLUMN finish_reason TEXT;",
+    // 5: context window strategy and running summary per session
+    "ALTER TABLE sessions ADD COLUMN context_strategy TEXT NOT NULL DEFAULT 'truncate';
+    ALTER TABLE sessions ADD COLUMN context_summary TEXT;
+    ALTER TABLE sessions ADD COLUMN context_summary_through TEXT;",
+    // 6: provider and parameters chosen per session
+    "ALTER TABLE sessions ADD COLUMN provider_id TEXT;
+    ALTER TABLE sessions ADD COLUMN model TEXT;
+    ALTER TABLE sessions ADD COLUMN temperature REAL;
+    ALTER TABLE sessions ADD COLUMN max_tokens INTEGER;
+    ALTER TABLE sessions ADD COLUMN system_prompt TEXT;",
+    // 7: token usage, latency and errors of generated messages
+    "ALTER TABLE messages ADD COLUMN prompt_tokens INTEGER;
+    ALTER TABLE messages ADD COLUMN completion_tokens INTEGER;
+    ALTER TABLE messages ADD COLUMN total_tokens INTEGER;
+    ALTER TABLE messages ADD COLUMN latency_ms INTEGER;
+    ALTER TABLE messages ADD COLUMN error TEXT;",
+    // 8: images and files; `content` keeps the text for search
+    "ALTER TABLE messages ADD COLUMN parts TEXT;",
+];
+
+/// The schema version this build of Synapse writes
+pub const SCHEMA_VERSION: usize = MIGRATIONS.len();
+
+/// Persistent store for chat sessions
+///
+/// The connection is shared behind a mutex and only used from blocking
+/// tasks, so callers never block the async runtime on disk I/O.
+#[derive(Debug, Clone)]
+pub struct ChatStore {
+    /// Connection to the database
+    conn: Arc<Mutex<Connection>>,
+    /// Images and files attached to messages
+    attachments: AttachmentStore,
+}
+
+impl ChatStore {
+    /// Opens the database at `path`, creating and migrating it as needed
+    ///
+    /// # Errors
+    /// Returns an error if the database cannot be opened or was written by
+    /// a newer version of Synapse
+    pub fn open(path: &Path) -> AppResult<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let conn = Connection::open(path)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "synchronous", "FULL")?;
+        Self::from_connection(conn, AttachmentStore::new(path.with_file_name("attachments")))
+    }
+
+    /// Opens a database that only lives in memory
+    ///
+    /// Attachments are written to a fresh directory under the system's
+    /// temporary directory.
+    pub fn open_in_memory() -> AppResult<Self> {
+        let root = std::env::temp_dir().join(format!("synapse-attachments-{}", uuid::Uuid::new_v4()));
+        Self::from_connection(Connection::open_in_memory()?, AttachmentStore::new(root))
+    }
+
+    fn from_connection(mut conn: Connection, attachments: AttachmentStore) -> AppResult<Self> {
+        conn.pragma_update(None, "foreign_keys", "ON")?;
+        migrate(&mut conn)?;
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+            attachments,
+        })
+    }
+
+    /// The store holding the attachments of messages
+    pub fn attachments(&self) -> &AttachmentStore {
+        &self.attachments
+    }
+
+    /// Runs `f` with the connection on a blocking thread
+    async fn with_conn<T, F>(&self, f: F) -> AppResult<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> AppResult<T> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = conn
+                .lock()
+                .map_err(|_| AppError::internal("Chat store lock poisoned"))?;
+            f(&mut conn)
+        })
+        .await
+        .map_err(|e| AppError::internal(e.to_string()))?
+    }
+
+    /// Lists all sessions, most recently updated first, without their messages
+    pub async fn list_sessions(&self) -> AppResult<Vec<ChatSessionSummary>> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT s.id, s.title, s.created_at, s.updated_at,
+                        (SELECT COUNT(*) FROM messages m WHERE m.session_id = s.id)
+                 FROM sessions s
+                 ORDER BY s.updated_at DESC",
+            )?;
+            let sessions = stmt
+                .query_map([], |row| {
+                    Ok(ChatSessionSummary {
+                        id: row.get(0)?,
+                        title: row.get(1)?,
+                        created_at: datetime(row, 2)?,
+                        updated_at: datetime(row, 3)?,
+                        message_count: row.get(4)?,
+                    })
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(sessions)
+        })
+        .await
+    }
+
+    /// Loads a session together with its messages
+    pub async fn load_session(&self, id: &str) -> AppResult<Option<ChatSession>> {
+        let id = id.to_string();
+        self.with_conn(move |conn| {
+            let session = conn
+                .query_row(
+                    "SELECT id, title, created_at, updated_at, active_leaf,
+                            context_strategy, context_summary, context_summary_through,
+                            provider_id, model, temperature, max_tokens, system_prompt
+                     FROM sessions WHERE id = ?1",
+                    [&id],
+                    |row| {
+                        let strategy: String = row.get(5)?;
+                        let summary: Option<String> = row.get(6)?;
+                        let through: Option<String> = row.get(7)?;
+                        Ok(ChatSession {
+                            id: row.get(0)?,
+                            title: row.get(1)?,
+                            messages: Vec::new(),
+                            active_leaf: row.get(4)?,
+                            context_strategy: ContextStrategy::parse(&strategy),
+                            context_summary: summary
+                                .zip(through)
+                                .map(|(text, through)| ContextSummary { text, through }),
+                            settings: SessionSettings {
+                                provider_id: row.get(8)?,
+                                model: row.get(9)?,
+                                temperature: row.get(10)?,
+                                max_tokens: row.get(11)?,
+                                system_prompt: row.get(12)?,
+                            },
+                            created_at: datetime(row, 2)?,
+                            updated_at: datetime(row, 3)?,
+                        })
+                    },
+                )
+                .optional()?;
+
+            let Some(mut session) = session else {
+                return Ok(None);
+            };
+
+            let mut stmt = conn.prepare(
+                "SELECT id, parent_id, role, content, timestamp, provider, model, finish_reason,
+                        prompt_tokens, completion_tokens, total_tokens, latency_ms, error, parts
+                 FROM messages WHERE session_id = ?1 ORDER BY position",
+            )?;
+            session.messages = stmt
+                .query_map([&id], |row| {
+                    let prompt_tokens: Option<i32> = row.get(8)?;
+                    let usage = match prompt_tokens {
+                        Some(prompt_tokens) => Some(CompletionUsage {
+                            prompt_tokens,
+                            completion_tokens: row.get(9)?,
+                            total_tokens: row.get(10)?,
+                        }),
+                        None => None,
+                    };
+                    Ok(Message {
+                        id: row.get(0)?,
+                        parent_id: row.get(1)?,
+                        role: row.get(2)?,
+                        content: content(row, 3, 13)?,
+                        timestamp: datetime(row, 4)?,
+                        metadata: MessageMetadata {
+                            provider: row.get(5)?,
+                            model: row.get(6)?,
+                            finish_reason: row.get(7)?,
+                            usage,
+                            latency_ms: row.get(11)?,
+                            error: row.get(12)?,
+                        },
+                    })
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(Some(session))
+        })
+        .await
+    }
+
+    /// Stores a session and all of its messages, replacing any existing copy
+    pub async fn save_session(&self, session: &ChatSession) -> AppResult<()> {
+        let session = session.clone();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            write_session(&tx, &session)?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Stores a session unless one with the same id exists
+    ///
+    /// # Returns
+    /// Whether the session was stored
+    pub async fn insert_session(&self, session: &ChatSession) -> AppResult<bool> {
+        let session = session.clone();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            let exists = tx
+                .query_row("SELECT 1 FROM sessions WHERE id = ?1", [&session.id], |_| Ok(()))
+                .optional()?
+                .is_some();
+            if exists {
+                return Ok(false);
+            }
+            write_session(&tx, &session)?;
+            tx.commit()?;
+            Ok(true)
+        })
+        .await
+    }
+
+    /// Appends a message at `position` and makes it the session's active leaf
+    ///
+    /// # Errors
+    /// Returns an error if the session does not exist or the position is
+    /// already taken
+    pub async fn append_message(
+        &self,
+        session_id: &str,
+        position: usize,
+        message: &Message,
+        updated_at: DateTime<Utc>,
+    ) -> AppResult<()> {
+        let session_id = session_id.to_string();
+        let message = message.clone();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            let updated = tx.execute(
+                "UPDATE sessions SET updated_at = ?2, active_leaf = ?3 WHERE id = ?1",
+                params![session_id, updated_at.timestamp_millis(), message.id],
+            )?;
+            if updated == 0 {
+                return Err(AppError::not_found("Chat session not found"));
+            }
+            insert_message(&tx, &session_id, position, &message)?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Replaces the content of a stored message
+    ///
+    /// # Errors
+    /// Returns an error if the message does not exist
+    pub async fn update_message(
+        &self,
+        session_id: &str,
+        message: &Message,
+        updated_at: DateTime<Utc>,
+    ) -> AppResult<()> {
+        let session_id = session_id.to_string();
+        let message = message.clone();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            let updated = tx.execute(
+                "UPDATE messages
+                 SET content = ?3, provider = ?4, model = ?5, finish_reason = ?6,
+                     prompt_tokens = ?7, completion_tokens = ?8, total_tokens = ?9,
+                     latency_ms = ?10, error = ?11, parts = ?12
+                 WHERE session_id = ?1 AND id = ?2",
+                params![
+                    session_id,
+                    message.id,
+                    message.content.text(),
+                    message.metadata.provider,
+                    message.metadata.model,
+                    message.metadata.finish_reason,
+                    message.metadata.usage.as_ref().map(|usage| usage.prompt_tokens),
+                    message.metadata.usage.as_ref().map(|usage| usage.completion_tokens),
+                    message.metadata.usage.as_ref().map(|usage| usage.total_tokens),
+                    message.metadata.latency_ms,
+                    message.metadata.error,
+                    parts_json(&message.content)?,
+                ],
+            )?;
+            if updated == 0 {
+                return Err(AppError::not_found("Message not found"));
+            }
+            tx.execute(
+                "UPDATE sessions SET updated_at = ?2 WHERE id = ?1",
+                params![session_id, updated_at.timestamp_millis()],
+            )?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Changes the title of a session
+    ///
+    /// # Errors
+    /// Returns an error if the session does not exist
+    pub async fn rename_session(&self, id: &str, title: &str) -> AppResult<()> {
+        let id = id.to_string();
+        let title = title.to_string();
+        self.with_conn(move |conn| {
+            let updated = conn.execute(
+                "UPDATE sessions SET title = ?2 WHERE id = ?1",
+                params![id, title],
+            )?;
+            if updated == 0 {
+                return Err(AppError::not_found("Chat session not found"));
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Selects the last message of a session's active branch
+    ///
+    /// # Errors
+    /// Returns an error if the session does not exist
+    pub async fn set_active_leaf(&self, session_id: &str, leaf_id: &str) -> AppResult<()> {
+        let session_id = session_id.to_string();
+        let leaf_id = leaf_id.to_string();
+        self.with_conn(move |conn| {
+            let updated = conn.execute(
+                "UPDATE sessions SET active_leaf = ?2 WHERE id = ?1",
+                params![session_id, leaf_id],
+            )?;
+            if updated == 0 {
+                return Err(AppError::not_found("Chat session not found"));
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Sets how a session handles conversations that outgrow the context
+    ///
+    /// # Errors
+    /// Returns an error if the session does not exist
+    pub async fn set_context_strategy(
+        &self,
+        session_id: &str,
+        strategy: ContextStrategy,
+    ) -> AppResult<()> {
+        let session_id = session_id.to_string();
+        self.with_conn(move |conn| {
+            let updated = conn.execute(
+                "UPDATE sessions SET context_strategy = ?2 WHERE id = ?1",
+                params![session_id, strategy.as_str()],
+            )?;
+            if updated == 0 {
+                return Err(AppError::not_found("Chat session not found"));
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Stores the running summary of a session's older messages
+    ///
+    /// # Errors
+    /// Returns an error if the session does not exist
+    pub async fn set_context_summary(
+        &self,
+        session_id: &str,
+        summary: Option<&ContextSummary>,
+    ) -> AppResult<()> {
+        let session_id = session_id.to_string();
+        let summary = summary.cloned();
+        self.with_conn(move |conn| {
+            let updated = conn.execute(
+                "UPDATE sessions SET context_summary = ?2, context_summary_through = ?3
+                 WHERE id = ?1",
+                params![
+                    session_id,
+                    summary.as_ref().map(|summary| &summary.text),
+                    summary.as_ref().map(|summary| &summary.through),
+                ],
+            )?;
+            if updated == 0 {
+                return Err(AppError::not_found("Chat session not found"));
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Replaces the provider and parameters chosen for a session
+    ///
+    /// # Errors
+    /// Returns an error if the session does not exist
+    pub async fn set_session_settings(
+        &self,
+        session_id: &str,
+        settings: &SessionSettings,
+    ) -> AppResult<()> {
+        let session_id = session_id.to_string();
+        let settings = settings.clone();
+        self.with_conn(move |conn| {
+            let updated = conn.execute(
+                "UPDATE sessions
+                 SET provider_id = ?2, model = ?3, temperature = ?4, max_tokens = ?5,
+                     system_prompt = ?6
+                 WHERE id = ?1",
+                params![
+                    session_id,
+                    settings.provider_id,
+                    settings.model,
+                    settings.temperature,
+                    settings.max_tokens,
+                    settings.system_prompt,
+                ],
+            )?;
+            if updated == 0 {
+                return Err(AppError::not_found("Chat session not found"));
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Deletes a session, its messages and its attachments
+    ///
+    /// # Errors
+    /// Returns an error if the session does not exist
+    pub async fn delete_session(&self, id: &str) -> AppResult<()> {
+        let session_id = id.to_string();
+        self.with_conn(move |conn| {
+            let deleted = conn.execute("DELETE FROM sessions WHERE id = ?1", [&session_id])?;
+            if deleted == 0 {
+                return Err(AppError::not_found("Chat session not found"));
+            }
+            Ok(())
+        })
+        .await?;
+        self.attachments.delete_session(id).await
+    }
+}
+
+impl ChatStore {
+    /// Searches message contents and session titles
+    ///
+    /// Message matches honour all filters. Title matches are only included
+    /// when the query does not filter on role, provider or model, and are
+    /// filtered by the session's last update instead of a message time.
+    /// Results are ordered by relevance.
+    ///
+    /// # Errors
+    /// Returns an error if the query contains no words
+    pub async fn search(&self, query: &SearchQuery) -> AppResult<Vec<SearchResult>> {
+        let expression = query
+            .match_expression()
+            .ok_or_else(|| AppError::invalid_input("Search query must not be empty"))?;
+        let query = query.clone();
+        let limit = query.limit.unwrap_or(DEFAULT_SEARCH_LIMIT);
+        let from = query.from.map(|from| from.timestamp_millis());
+        let to = query.to.map(|to| to.timestamp_millis());
+        let open = search::HIGHLIGHT_START.to_string();
+        let close = search::HIGHLIGHT_END.to_string();
+
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT messages_fts.session_id, messages_fts.position, s.title,
+                        m.role, m.provider, m.model, m.timestamp,
+                        snippet(messages_fts, 0, ?2, ?3, '…', 16),
+                        bm25(messages_fts), m.id
+                 FROM messages_fts
+                 JOIN messages m
+                     ON m.session_id = messages_fts.session_id
+                     AND m.position = messages_fts.position
+                 JOIN sessions s ON s.id = messages_fts.session_id
+                 WHERE messages_fts MATCH ?1
+                     AND (?4 IS NULL OR m.timestamp >= ?4)
+                     AND (?5 IS NULL OR m.timestamp <= ?5)
+                     AND (?6 IS NULL OR m.role = ?6)
+                     AND (?7 IS NULL OR m.provider = ?7)
+                     AND (?8 IS NULL OR m.model = ?8)
+                 ORDER BY bm25(messages_fts)
+                 LIMIT ?9",
+            )?;
+            let mut results = stmt
+                .query_map(
+                    params![
+                        expression, open, close, from, to,
+                        query.role, query.provider, query.model, limit,
+                    ],
+                    |row| {
+                        let snippet: String = row.get(7)?;
+                        let rank: f64 = row.get(8)?;
+                        Ok(SearchResult {
+                            session_id: row.get(0)?,
+                            message_index: Some(row.get(1)?),
+                            message_id: row.get(9)?,
+                            session_title: row.get(2)?,
+                            role: row.get(3)?,
+                            provider: row.get(4)?,
+                            model: row.get(5)?,
+                            timestamp: row.get(6)?,
+                            snippet: search::parse_snippet(&snippet),
+                            score: -rank,
+                        })
+                    },
+                )?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            if !query.filters_messages() {
+                let mut stmt = conn.prepare(
+                    "SELECT s.id, s.title, s.updated_at,
+                            highlight(sessions_fts, 0, ?2, ?3),
+                            bm25(sessions_fts)
+                     FROM sessions_fts
+                     JOIN sessions s ON s.id = sessions_fts.session_id
+                     WHERE sessions_fts MATCH ?1
+                         AND (?4 IS NULL OR s.updated_at >= ?4)
+                         AND (?5 IS NULL OR s.updated_at <= ?5)
+                     ORDER BY bm25(sessions_fts)
+                     LIMIT ?6",
+                )?;
+                let titles = stmt.query_map(
+                    params![expression, open, close, from, to, limit],
+                    |row| {
+                        let snippet: String = row.get(3)?;
+                        let rank: f64 = row.get(4)?;
+                        Ok(SearchResult {
+                            session_id: row.get(0)?,
+                            session_title: row.get(1)?,
+                            message_index: None,
+                            message_id: None,
+                            role: None,
+                            provider: None,
+                            model: None,
+                            timestamp: row.get(2)?,
+                            snippet: search::parse_snippet(&snippet),
+                            score: -rank,
+                        })
+                    },
+                )?;
+                for title in titles {
+                    results.push(title?);
+                }
+                results.sort_by(|a, b| b.score.total_cmp(&a.score));
+                results.truncate(limit);
+            }
+            Ok(results)
+        })
+        .await
+    }
+}
+
+/// Brings the schema up to date, one transaction per migration
+fn migrate(conn: &mut Connection) -> AppResult<()> {
+    let version: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    if version > SCHEMA_VERSION {
+        return Err(AppError::internal(format!(
+            "Chat history schema version {} is newer than supported version {}",
+            version, SCHEMA_VERSION
+        )));
+    }
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", index + 1)?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+/// Writes a session and replaces its messages
+fn write_session(conn: &Connection, session: &ChatSession) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO sessions
+             (id, title, created_at, updated_at, active_leaf,
+              context_strategy, context_summary, context_summary_through,
+              provider_id, model, temperature, max_tokens, system_prompt)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)
+         ON CONFLICT (id) DO UPDATE SET
+             title = excluded.title,
+             updated_at = excluded.updated_at,
+             active_leaf = excluded.active_leaf,
+             context_strategy = excluded.context_strategy,
+             context_summary = excluded.context_summary,
+             context_summary_through = excluded.context_summary_through,
+             provider_id = excluded.provider_id,
+             model = excluded.model,
+             temperature = excluded.temperature,
+             max_tokens = excluded.max_tokens,
+             system_prompt = excluded.system_prompt",
+        params![
+            session.id,
+            session.title,
+            session.created_at.timestamp_millis(),
+            session.updated_at.timestamp_millis(),
+            session.active_leaf,
+            session.context_strategy.as_str(),
+            session.context_summary.as_ref().map(|summary| &summary.text),
+            session.context_summary.as_ref().map(|summary| &summary.through),
+            session.settings.provider_id,
+            session.settings.model,
+            session.settings.temperature,
+            session.settings.max_tokens,
+            session.settings.system_prompt,
+        ],
+    )?;
+    conn.execute("DELETE FROM messages WHERE session_id = ?1", [&session.id])?;
+    for (position, message) in session.messages.iter().enumerate() {
+        insert_message(conn, &session.id, position, message)?;
+    }
+    Ok(())
+}
+
+fn insert_message(
+    conn: &Connection,
+    session_id: &str,
+    position: usize,
+    message: &Message,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO messages
+             (session_id, position, id, parent_id, role, content, timestamp, provider, model,
+              finish_reason, prompt_tokens, completion_tokens, total_tokens, latency_ms, error,
+              parts)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)",
+        params![
+            session_id,
+            position,
+            message.id,
+            message.parent_id,
+            message.role,
+            message.content.text(),
+            message.timestamp.timestamp_millis(),
+            message.metadata.provider,
+            message.metadata.model,
+            message.metadata.finish_reason,
+            message.metadata.usage.as_ref().map(|usage| usage.prompt_tokens),
+            message.metadata.usage.as_ref().map(|usage| usage.completion_tokens),
+            message.metadata.usage.as_ref().map(|usage| usage.total_tokens),
+            message.metadata.latency_ms,
+            message.metadata.error,
+            parts_json(&message.content)?,
+        ],
+    )?;
+    Ok(())
+}
+
+/// Serializes the parts of content that is not plain text
+fn parts_json(content: &MessageContent) -> rusqlite::Result<Option<String>> {
+    if content.is_text_only() {
+        return Ok(None);
+    }
+    serde_json::to_string(&content.parts)
+        .map(Some)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
+}
+
+/// Reads message content from its text column, or its parts column if set
+fn content(row: &Row<'_>, text: usize, parts: usize) -> rusqlite::Result<MessageContent> {
+    let json: Option<String> = row.get(parts)?;
+    match json {
+        Some(json) => serde_json::from_str::<Vec<ContentPart>>(&json)
+            .map(MessageContent::from)
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(parts, rusqlite::types::Type::Text, Box::new(e))),
+        None => row.get::<_, String>(text).map(MessageContent::from),
+    }
+}
+
+impl ToSql for Role {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for Role {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        value
+            .as_str()?
+            .parse()
+            .map_err(|e: AppError| FromSqlError::Other(Box::new(e)))
+    }
+}
+
+/// Reads a millisecond timestamp column
+fn datetime(row: &Row<'_>, index: usize) -> rusqlite::Result<DateTime<Utc>> {
+    let millis: i64 = row.get(index)?;
+    DateTime::from_timestamp_millis(millis).ok_or_else(|| {
+        rusqlite::Error::IntegralValueOutOfRange(index, millis)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::ai::PartSource;
+    use tempfile::tempdir;
+
+    fn message(role: Role, content: &str) -> Message {
+        Message::new(role, content)
+    }
+
+    #[tokio::test]
+    async fn test_sessions_survive_reopen() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("chats.db");
+
+        let session = ChatSession {
+            id: "session-1".to_string(),
+            title: "Rust questions".to_string(),
+            messages: vec![Message::new(
+                Role::User,
+                vec![
+                    ContentPart::text("What is a trait?"),
+                    ContentPart::File {
+                        name: "traits.md".to_string(),
+                        mime_type: "text/markdown".to_string(),
+                        size: 12,
+                        source: PartSource::Attachment { id: "attachment-1".to_string() },
+                    },
+                ],
+            )],
+            active_leaf: None,
+            context_strategy: ContextStrategy::default(),
+            context_summary: None,
+            settings: SessionSettings::default(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+
+        {
+            let store = ChatStore::open(&path).unwrap();
+            store.save_session(&session).await.unwrap();
+            store
+                .append_message(&session.id, 1, &message(Role::Assistant, "An interface."), Utc::now())
+                .await
+                .unwrap();
+
+            // Positions are unique within a session
+            assert!(store
+                .append_message(&session.id, 1, &message(Role::User, "Again"), Utc::now())
+                .await
+                .is_err());
+
+            let summary = ContextSummary {
+                text: "Asked about traits.".to_string(),
+                through: session.messages[0].id.clone(),
+            };
+            store.set_context_strategy(&session.id, ContextStrategy::Summarize).await.unwrap();
+            store.set_context_summary(&session.id, Some(&summary)).await.unwrap();
+        }
+
+        let store = ChatStore::open(&path).unwrap();
+        let summaries = store.list_sessions().await.unwrap();
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].title, "Rust questions");
+        assert_eq!(summaries[0].message_count, 2);
+
+        let loaded = store.load_session(&session.id).await.unwrap().unwrap();
+        let contents: Vec<_> = loaded.messages.iter().map(|m| m.content.text()).collect();
+        assert_eq!(contents, vec!["What is a trait?", "An interface."]);
+        assert_eq!(loaded.messages[0].content, session.messages[0].content);
+        assert_eq!(loaded.created_at.timestamp_millis(), session.created_at.timestamp_millis());
+        assert_eq!(loaded.context_strategy, ContextStrategy::Summarize);
+        assert_eq!(loaded.context_summary.unwrap().text, "Asked about traits.");
+
+        store.delete_session(&session.id).await.unwrap();
+        assert!(store.load_session(&session.id).await.unwrap().is_none());
+        assert!(store.delete_session(&session.id).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_search() {
+        let store = ChatStore::open_in_memory().unwrap();
+        let mut reply = message(Role::Assistant, "The borrow checker enforces ownership rules.");
+        reply.metadata.provider = Some("openai".to_string());
+        reply.metadata.model = Some("gpt-4o".to_string());
+
+        let session = ChatSession {
+            id: "session-1".to_string(),
+            title: "Ownership in Rust".to_string(),
+            messages: vec![message(Role::User, "Why does the borrow checker complain?"), reply],
+            active_leaf: None,
+            context_strategy: ContextStrategy::default(),
+            context_summary: None,
+            settings: SessionSettings::default(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+        store.save_session(&session).await.unwrap();
+
+        // Words are matched as prefixes and highlighted in the snippet
+        let results = store
+            .search(&SearchQuery { text: "borrow check".to_string(), ..Default::default() })
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|r| r.session_id == "session-1"));
+        let highlighted: Vec<_> = results[0]
+            .snippet
+            .iter()
+            .filter(|segment| segment.highlighted)
+            .map(|segment| segment.text.as_str())
+            .collect();
+        assert_eq!(highlighted, vec!["borrow", "checker"]);
+
+        // Filters narrow down message matches
+        let results = store
+            .search(&SearchQuery {
+                text: "borrow".to_string(),
+                model: Some("gpt-4o".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].message_index, Some(1));
+        assert_eq!(results[0].role, Some(Role::Assistant));
+
+        let results = store
+            .search(&SearchQuery {
+                text: "borrow".to_string(),
+                from: Some(Utc::now() + chrono::Duration::days(1)),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert!(results.is_empty());
+
+        // Session titles are searched too
+        let results = store
+            .search(&SearchQuery { text: "ownership".to_string(), ..Default::default() })
+            .await
+            .unwrap();
+        let indices: Vec<_> = results.iter().map(|r| r.message_index).collect();
+        assert!(indices.contains(&None));
+        assert!(indices.contains(&Some(1)));
+
+        // Deleted sessions disappear from the index
+        store.delete_session(&session.id).await.unwrap();
+        let results = store
+            .search(&SearchQuery { text: "ownership".to_string(), ..Default::default() })
+            .await
+            .unwrap();
+        assert!(results.is_empty());
+        assert!(store.search(&SearchQuery::default()).await.is_err());
+    }
+
+    #[test]
+    fn test_rejects_newer_schema() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("chats.db");
+
+        let conn = Connection::open(&path).unwrap();
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION + 1).unwrap();
+        drop(conn);
+
+        assert!(ChatStore::open(&path).is_err());
+    }
+}
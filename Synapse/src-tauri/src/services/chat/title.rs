@@ -0,0 +1,112 @@
+//! Session titles
+//!
+//! This module generates short titles for chat sessions from their first
+//! exchange. Titles are requested from a model, with a heuristic based on
+//! the first user message as fallback.
+
+use crate::utils::{AppError, AppResult};
+use super::super::ai::{AIProvider, ChatCompletionParams, Message, Role};
+
+/// Title given to sessions created without one
+pub const DEFAULT_SESSION_TITLE: &str = "New chat";
+
+/// Maximum number of characters in a title
+const MAX_TITLE_CHARS: usize = 60;
+
+/// Maximum number of words in a heuristic title
+const MAX_HEURISTIC_WORDS: usize = 8;
+
+/// Maximum number of characters of each message shown to the model
+const MAX_EXCERPT_CHARS: usize = 1000;
+
+/// Tokens a generated title may use
+const TITLE_MAX_TOKENS: i32 = 24;
+
+const TITLE_PROMPT: &str = "You write titles for chat conversations. \
+Reply with a title of at most six words that summarizes the conversation. \
+Reply with the title only, without quotes or punctuation at the end.";
+
+/// Asks a model for a title summarizing the first exchange
+///
+/// # Errors
+/// Returns an error if the request fails or the reply contains no title
+pub async fn generate(
+    provider: &dyn AIProvider,
+    exchange: &[Message],
+    params: ChatCompletionParams,
+) -> AppResult<String> {
+    let messages = exchange
+        .iter()
+        .map(|message| Message::new(message.role, excerpt(&message.content.text())))
+        .collect();
+    let params = ChatCompletionParams {
+        temperature: 0.2,
+        tools: Vec::new(),
+        max_tokens: TITLE_MAX_TOKENS,
+        system_prompt: Some(TITLE_PROMPT.to_string()),
+        ..params
+    };
+
+    let completion = provider.create_chat_completion(messages, params).await?;
+    clean(&completion.message.content.text())
+        .ok_or_else(|| AppError::api("Title reply was empty"))
+}
+
+/// Builds a title from the first user message
+pub fn heuristic(exchange: &[Message]) -> String {
+    exchange
+        .iter()
+        .find(|message| message.role == Role::User)
+        .and_then(|message| {
+            let text = message.content.text();
+            let line = text.lines().find(|line| !line.trim().is_empty())?;
+            let words: Vec<_> = line.split_whitespace().take(MAX_HEURISTIC_WORDS).collect();
+            clean(&words.join(" "))
+        })
+        .unwrap_or_else(|| DEFAULT_SESSION_TITLE.to_string())
+}
+
+/// Normalizes a title to a single short line without surrounding quotes
+fn clean(raw: &str) -> Option<String> {
+    let line = raw.lines().find(|line| !line.trim().is_empty())?;
+    let line = line.trim();
+    let line = line.strip_prefix("Title:").unwrap_or(line);
+    let title = line
+        .trim()
+        .trim_matches(['"', '\'', '*', '#', '`'])
+        .trim_end_matches(['.', ':', ';', ','])
+        .trim();
+    if title.is_empty() {
+        return None;
+    }
+
+    if title.chars().count() <= MAX_TITLE_CHARS {
+        return Some(title.to_string());
+    }
+    let truncated: String = title.chars().take(MAX_TITLE_CHARS - 1).collect();
+    Some(format!("{}…", truncated.trim_end()))
+}
+
+fn excerpt(content: &str) -> String {
+    content.chars().take(MAX_EXCERPT_CHARS).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clean_and_heuristic() {
+        assert_eq!(clean("\"Sourdough Proofing Times.\"\n").as_deref(), Some("Sourdough Proofing Times"));
+        assert_eq!(clean("Title: **Rust traits**").as_deref(), Some("Rust traits"));
+        assert!(clean("  \n\"\"").is_none());
+        assert_eq!(clean(&"a".repeat(100)).unwrap().chars().count(), MAX_TITLE_CHARS);
+
+        let exchange = vec![
+            Message::new(Role::User, "\nhow do I split a string by whitespace in rust and collect it?"),
+            Message::new(Role::Assistant, "Use split_whitespace."),
+        ];
+        assert_eq!(heuristic(&exchange), "how do I split a string by whitespace");
+        assert_eq!(heuristic(&[]), DEFAULT_SESSION_TITLE);
+    }
+}
@@ -0,0 +1,220 @@
+//! Document chunking
+//!
+//! This module splits documents into passages small enough to embed and to
+//! quote in a prompt. Chunks are cut at paragraph breaks where possible;
+//! Markdown documents also start a new chunk at every heading, which the
+//! chunks under it remember. Every chunk records the lines it spans so
+//! answers can cite them.
+
+use std::path::Path;
+
+/// Longest chunk, in bytes
+pub const MAX_CHUNK_BYTES: usize = 1_500;
+
+/// Extensions of Markdown documents
+const MARKDOWN_EXTENSIONS: &[&str] = &["md", "markdown", "mdx"];
+
+/// Extensions of plain text documents
+const TEXT_EXTENSIONS: &[&str] = &["txt", "text", "rst", "adoc", "org", "tex"];
+
+/// Extensions of source and configuration files
+const SOURCE_EXTENSIONS: &[&str] = &[
+    "rs", "py", "js", "jsx", "ts", "tsx", "mjs", "cjs", "go", "java", "kt", "kts", "scala", "c", "h", "cc",
+    "cpp", "cxx", "hpp", "cs", "fs", "rb", "php", "swift", "m", "dart", "lua", "pl", "r", "jl", "ex", "exs",
+    "erl", "hs", "ml", "clj", "zig", "nim", "sh", "bash", "zsh", "fish", "ps1", "sql", "html", "css", "scss",
+    "vue", "svelte", "json", "yaml", "yml", "toml", "ini", "xml", "proto", "graphql",
+];
+
+/// How a document is split
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocumentKind {
+    /// Prose split at paragraphs
+    Text,
+    /// Prose split at headings and paragraphs
+    Markdown,
+    /// Code split at blank lines between blocks
+    Source,
+}
+
+impl DocumentKind {
+    /// Determines how a file is split from its extension
+    ///
+    /// # Returns
+    /// `None` for files that are not indexed
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        if MARKDOWN_EXTENSIONS.contains(&extension.as_str()) {
+            Some(Self::Markdown)
+        } else if TEXT_EXTENSIONS.contains(&extension.as_str()) {
+            Some(Self::Text)
+        } else if SOURCE_EXTENSIONS.contains(&extension.as_str()) {
+            Some(Self::Source)
+        } else {
+            None
+        }
+    }
+}
+
+/// A passage of a document
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    /// Text of the passage
+    pub text: String,
+    /// Heading of the Markdown section the passage belongs to
+    pub heading: Option<String>,
+    /// First line of the passage, counting from 1
+    pub start_line: usize,
+    /// Last line of the passage
+    pub end_line: usize,
+}
+
+/// Splits a document into chunks of at most `MAX_CHUNK_BYTES`
+pub fn chunk(text: &str, kind: DocumentKind) -> Vec<Chunk> {
+    let mut chunker = Chunker::default();
+    let mut in_fence = false;
+
+    for (index, line) in text.lines().enumerate() {
+        if kind == DocumentKind::Markdown {
+            let trimmed = line.trim_start();
+            if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+                in_fence = !in_fence;
+            } else if !in_fence && is_heading(trimmed) {
+                chunker.flush();
+                let heading = trimmed.trim_start_matches('#').trim();
+                chunker.heading = Some(heading.to_string()).filter(|heading| !heading.is_empty());
+            }
+        }
+        chunker.push(index + 1, line);
+    }
+    chunker.flush();
+    chunker.chunks
+}
+
+/// Whether a Markdown line is an ATX heading such as `## Usage`
+fn is_heading(line: &str) -> bool {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    (1..=6).contains(&level) && line[level..].chars().next().map_or(true, char::is_whitespace)
+}
+
+/// Collects lines into chunks
+#[derive(Debug, Default)]
+struct Chunker {
+    /// Chunks completed so far
+    chunks: Vec<Chunk>,
+    /// Heading of the current Markdown section
+    heading: Option<String>,
+    /// Lines of the chunk being built, with their numbers
+    lines: Vec<(usize, String)>,
+    /// Bytes in `lines`, counting line breaks
+    len: usize,
+    /// Index into `lines` of the last paragraph break, where a split is preferred
+    boundary: Option<usize>,
+}
+
+impl Chunker {
+    fn push(&mut self, number: usize, line: &str) {
+        if line.len() > MAX_CHUNK_BYTES {
+            self.flush();
+            for piece in split_long_line(line) {
+                self.chunks.push(Chunk {
+                    text: piece.to_string(),
+                    heading: self.heading.clone(),
+                    start_line: number,
+                    end_line: number,
+                });
+            }
+            return;
+        }
+
+        while !self.lines.is_empty() && self.len + line.len() + 1 > MAX_CHUNK_BYTES {
+            let split = self.boundary.unwrap_or(self.lines.len());
+            let rest = self.lines.split_off(split);
+            self.flush();
+            for (number, line) in rest {
+                self.append(number, line);
+            }
+        }
+        self.append(number, line.to_string());
+    }
+
+    fn append(&mut self, number: usize, line: String) {
+        if line.trim().is_empty() {
+            // Leading blank lines carry nothing
+            if self.lines.is_empty() {
+                return;
+            }
+            self.boundary = Some(self.lines.len());
+        }
+        self.len += line.len() + 1;
+        self.lines.push((number, line));
+    }
+
+    fn flush(&mut self) {
+        while self.lines.last().is_some_and(|(_, line)| line.trim().is_empty()) {
+            self.lines.pop();
+        }
+        if let (Some((start_line, _)), Some((end_line, _))) = (self.lines.first(), self.lines.last()) {
+            let text: Vec<&str> = self.lines.iter().map(|(_, line)| line.as_str()).collect();
+            self.chunks.push(Chunk {
+                text: text.join("\n"),
+                heading: self.heading.clone(),
+                start_line: *start_line,
+                end_line: *end_line,
+            });
+        }
+        self.lines.clear();
+        self.len = 0;
+        self.boundary = None;
+    }
+}
+
+/// Splits a line longer than a chunk at character boundaries
+fn split_long_line(line: &str) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    let mut rest = line;
+    while !rest.is_empty() {
+        let mut end = rest.len().min(MAX_CHUNK_BYTES);
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        let (piece, tail) = rest.split_at(end);
+        pieces.push(piece);
+        rest = tail;
+    }
+    pieces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunks_follow_structure() {
+        let markdown = "# Setup\n\nInstall it.\n\n```sh\n# not a heading\ncargo build\n```\n\n## Usage\nRun it.\n";
+        let chunks = chunk(markdown, DocumentKind::Markdown);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].heading.as_deref(), Some("Setup"));
+        assert_eq!((chunks[0].start_line, chunks[0].end_line), (1, 8));
+        assert!(chunks[0].text.contains("# not a heading"));
+        assert_eq!(chunks[1].heading.as_deref(), Some("Usage"));
+        assert_eq!(chunks[1].text, "## Usage\nRun it.");
+
+        // Long documents are cut at the last paragraph break before the limit
+        let paragraph = "word ".repeat(100);
+        let text = format!("{}\n\n{}\n\n{}", paragraph, paragraph, paragraph);
+        let chunks = chunk(&text, DocumentKind::Text);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!((chunks[0].start_line, chunks[0].end_line), (1, 3));
+        assert_eq!((chunks[1].start_line, chunks[1].end_line), (5, 5));
+        assert!(chunks.iter().all(|chunk| chunk.text.len() <= MAX_CHUNK_BYTES));
+
+        let long_line = "é".repeat(MAX_CHUNK_BYTES);
+        let chunks = chunk(&long_line, DocumentKind::Source);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks.iter().map(|chunk| chunk.text.len()).sum::<usize>(), long_line.len());
+
+        assert_eq!(DocumentKind::from_path(Path::new("docs/README.MD")), Some(DocumentKind::Markdown));
+        assert_eq!(DocumentKind::from_path(Path::new("src/main.rs")), Some(DocumentKind::Source));
+        assert_eq!(DocumentKind::from_path(Path::new("logo.png")), None);
+    }
+}
@@ -0,0 +1,610 @@
+//! Document index
+//!
+//! This module lets chats draw on the user's own documents. Text, Markdown
+//! and source files in the configured folders are split into chunks, which
+//! are embedded by a provider and stored in a persistent index. Before a
+//! model answers, the chunks closest to the question are looked up and
+//! added to the system prompt as numbered excerpts the model can cite.
+//! The `watcher` keeps the index up to date as files change.
+
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+use ignore::WalkBuilder;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::utils::{AppError, AppResult};
+use super::ai::AIProvider;
+
+pub mod chunk;
+mod store;
+pub mod watcher;
+
+pub use chunk::{Chunk, DocumentKind};
+pub use store::{DocumentStore, IndexStats, Passage};
+use store::{EmbeddedChunk, StoredDocument};
+pub use watcher::{DocumentWatcher, IndexEvent, IndexStatus};
+
+/// Files larger than this are not indexed
+const MAX_FILE_BYTES: u64 = 1024 * 1024;
+
+/// Texts sent to the provider in one embeddings request
+const EMBEDDING_BATCH: usize = 64;
+
+/// Folders that hold dependencies or build output rather than documents
+const SKIPPED_DIRS: &[&str] = &["node_modules", "target", "dist", "build", "vendor", "__pycache__", "venv"];
+
+/// Instruction preceding the excerpts added to a prompt
+const CONTEXT_PROMPT: &str = "The following excerpts from the user's documents may help with their \
+question. Use them where they are relevant and cite them by their number, like [1]. If they do not \
+contain the answer, say so before answering from your own knowledge.";
+
+/// Embeds texts with one provider and model
+#[derive(Debug, Clone)]
+pub struct Embedder {
+    /// Id of the provider in the settings
+    provider_id: String,
+    /// The provider computing the embeddings
+    provider: Arc<dyn AIProvider>,
+    /// Embedding model
+    model: String,
+}
+
+impl Embedder {
+    /// Creates an embedder
+    pub fn new(provider_id: impl Into<String>, provider: Arc<dyn AIProvider>, model: impl Into<String>) -> Self {
+        Self {
+            provider_id: provider_id.into(),
+            provider,
+            model: model.into(),
+        }
+    }
+
+    /// Identifies the vectors this embedder produces
+    ///
+    /// Chunks embedded under a different key are embedded again when
+    /// indexed and ignored when searching.
+    pub fn key(&self) -> String {
+        format!("{}/{}", self.provider_id, self.model)
+    }
+
+    /// Embeds texts, in batches of `EMBEDDING_BATCH`
+    ///
+    /// # Errors
+    /// Returns an error if the provider cannot embed them
+    pub async fn embed(&self, texts: Vec<String>) -> AppResult<Vec<Vec<f32>>> {
+        let mut embeddings = Vec::with_capacity(texts.len());
+        for batch in texts.chunks(EMBEDDING_BATCH) {
+            embeddings.extend(self.provider.embed(batch.to_vec(), &self.model).await?);
+        }
+        Ok(embeddings)
+    }
+}
+
+/// A file that could not be indexed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SkippedFile {
+    /// Path of the file or folder
+    pub path: String,
+    /// Why it was skipped
+    pub reason: String,
+}
+
+/// Summary of an indexing run
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct IndexReport {
+    /// Number of files embedded because they were new or changed
+    pub indexed: usize,
+    /// Number of files left alone because they had not changed
+    pub unchanged: usize,
+    /// Number of files removed from the index
+    pub removed: usize,
+    /// Number of chunks embedded
+    pub chunks: usize,
+    /// Number of chunks of changed files that kept their embeddings
+    pub reused_chunks: usize,
+    /// Files and folders that could not be indexed
+    pub skipped: Vec<SkippedFile>,
+}
+
+/// How far an indexing run has come
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexProgress {
+    /// Folder being indexed
+    pub folder: String,
+    /// Files of the folder looked at so far
+    pub processed: usize,
+    /// Files in the folder
+    pub total: usize,
+    /// File looked at last
+    pub path: String,
+}
+
+/// A file found in an indexed folder
+#[derive(Debug, Clone)]
+struct ListedFile {
+    /// Path of the file
+    path: PathBuf,
+    /// Modification time in milliseconds since the epoch, if known
+    modified: Option<i64>,
+    /// Size in bytes
+    size: u64,
+}
+
+/// The index of the configured folders
+#[derive(Debug, Clone)]
+pub struct DocumentIndex {
+    /// Persistent storage for documents and embeddings
+    store: DocumentStore,
+}
+
+impl DocumentIndex {
+    /// Creates an index persisted in `store`
+    pub fn new(store: DocumentStore) -> Self {
+        Self { store }
+    }
+
+    /// Brings the index in line with the configured folders
+    ///
+    /// Folders no longer configured are dropped from the index. In the
+    /// others, files whose modification time or size changed are read
+    /// again and only the chunks whose text changed are embedded; files that
+    /// no longer exist are removed. Folders inside another configured
+    /// folder are indexed as part of it. `progress` is called after every
+    /// file.
+    ///
+    /// # Errors
+    /// Returns an error if the provider fails to embed or the index cannot
+    /// be written; files that cannot be read are skipped instead
+    pub async fn sync(
+        &self,
+        folders: &[PathBuf],
+        embedder: &Embedder,
+        progress: &(dyn Fn(IndexProgress) + Send + Sync),
+    ) -> AppResult<IndexReport> {
+        let folders = outermost_folders(folders);
+        let roots: Vec<String> = folders.iter().map(|folder| root_key(folder)).collect();
+        let mut report = IndexReport {
+            removed: self.store.retain_roots(roots).await?,
+            ..IndexReport::default()
+        };
+        for folder in &folders {
+            self.index_folder(folder, embedder, progress, &mut report).await?;
+        }
+        Ok(report)
+    }
+
+    async fn index_folder(
+        &self,
+        folder: &Path,
+        embedder: &Embedder,
+        progress: &(dyn Fn(IndexProgress) + Send + Sync),
+        report: &mut IndexReport,
+    ) -> AppResult<()> {
+        let root = root_key(folder);
+        let walk_root = folder.to_path_buf();
+        let files = tokio::task::spawn_blocking(move || list_files(&walk_root))
+            .await
+            .map_err(|e| AppError::internal(e.to_string()))?;
+        let (files, skipped) = match files {
+            Ok(listed) => listed,
+            Err(e) => {
+                // An unmounted folder should not wipe its index
+                report.skipped.push(SkippedFile { path: root, reason: e.to_string() });
+                return Ok(());
+            }
+        };
+        report.skipped.extend(skipped);
+
+        let mut indexed: HashMap<String, StoredDocument> = self.store
+            .documents(&root)
+            .await?
+            .into_iter()
+            .map(|document| (document.path.clone(), document))
+            .collect();
+        let total = files.len();
+        for (processed, file) in files.iter().enumerate() {
+            let path = file.path.to_string_lossy().to_string();
+            let stored = indexed.remove(&path);
+            self.index_file(&root, file, stored, embedder, report).await?;
+            progress(IndexProgress {
+                folder: root.clone(),
+                processed: processed + 1,
+                total,
+                path,
+            });
+        }
+
+        // What is left was not found on disk anymore
+        let gone: Vec<String> = indexed.into_keys().collect();
+        report.removed += gone.len();
+        self.store.remove_documents(gone).await
+    }
+
+    /// Indexes one file, reusing what is stored for it where possible
+    async fn index_file(
+        &self,
+        root: &str,
+        file: &ListedFile,
+        stored: Option<StoredDocument>,
+        embedder: &Embedder,
+        report: &mut IndexReport,
+    ) -> AppResult<()> {
+        let path = file.path.to_string_lossy().to_string();
+        let key = embedder.key();
+        let stored = stored.filter(|document| document.embedder == key);
+        let untouched = stored.as_ref().is_some_and(|document| {
+            document.modified.is_some() && document.modified == file.modified && document.size == Some(file.size)
+        });
+        if untouched {
+            report.unchanged += 1;
+            return Ok(());
+        }
+
+        let bytes = match tokio::fs::read(&file.path).await {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                report.skipped.push(SkippedFile { path, reason: e.to_string() });
+                return Ok(());
+            }
+        };
+        let hash = format!("{:x}", Sha256::digest(&bytes));
+        if stored.as_ref().is_some_and(|document| document.hash == hash) {
+            // Saved again without changes
+            self.store.touch_document(&path, file.modified, Some(file.size)).await?;
+            report.unchanged += 1;
+            return Ok(());
+        }
+
+        let Ok(text) = String::from_utf8(bytes) else {
+            report.skipped.push(SkippedFile { path, reason: "Not UTF-8 text".to_string() });
+            return Ok(());
+        };
+        let Some(kind) = DocumentKind::from_path(&file.path) else { return Ok(()) };
+        let chunks = chunk::chunk(&text, kind);
+
+        // Chunks whose text is unchanged keep their embeddings
+        let mut embeddings = match &stored {
+            Some(_) => self.store.chunk_embeddings(&path, &key).await?,
+            None => HashMap::new(),
+        };
+        let inputs: Vec<String> = chunks.iter().map(|chunk| embedding_input(&file.path, chunk)).collect();
+        let hashes: Vec<String> = inputs
+            .iter()
+            .map(|input| format!("{:x}", Sha256::digest(input.as_bytes())))
+            .collect();
+        let mut missing: Vec<(String, String)> = Vec::new();
+        for (input, hash) in inputs.into_iter().zip(&hashes) {
+            if !embeddings.contains_key(hash) && !missing.iter().any(|(_, missing)| missing == hash) {
+                missing.push((input, hash.clone()));
+            }
+        }
+        let (missing_inputs, missing_hashes): (Vec<String>, Vec<String>) = missing.into_iter().unzip();
+        report.chunks += missing_inputs.len();
+        report.reused_chunks += hashes.iter().filter(|hash| !missing_hashes.contains(hash)).count();
+        let fresh = embedder.embed(missing_inputs).await?;
+        embeddings.extend(missing_hashes.into_iter().zip(fresh));
+
+        let chunks = chunks
+            .into_iter()
+            .zip(hashes)
+            .map(|(chunk, hash)| {
+                let embedding = embeddings
+                    .get(&hash)
+                    .cloned()
+                    .ok_or_else(|| AppError::api("The provider returned too few embeddings"))?;
+                Ok(EmbeddedChunk { chunk, hash, embedding })
+            })
+            .collect::<AppResult<Vec<_>>>()?;
+        let document = StoredDocument {
+            path,
+            root: root.to_string(),
+            hash,
+            embedder: key,
+            modified: file.modified,
+            size: Some(file.size),
+        };
+        self.store.replace_document(document, chunks).await?;
+        report.indexed += 1;
+        Ok(())
+    }
+
+    /// Finds the passages most relevant to a question
+    ///
+    /// # Errors
+    /// Returns an error if the question cannot be embedded or the index
+    /// cannot be read
+    pub async fn search(&self, embedder: &Embedder, query: &str, limit: usize) -> AppResult<Vec<Passage>> {
+        let query = embedder
+            .embed(vec![query.to_string()])
+            .await?
+            .pop()
+            .ok_or_else(|| AppError::api("The provider returned no embedding"))?;
+        self.store.search(&embedder.key(), query, limit).await
+    }
+
+    /// Counts the indexed documents and chunks
+    pub async fn stats(&self) -> AppResult<IndexStats> {
+        self.store.stats().await
+    }
+}
+
+/// Looks up passages for the questions asked in chats
+#[derive(Debug, Clone)]
+pub struct Retriever {
+    /// The index searched
+    index: DocumentIndex,
+    /// Embeds the questions, the same way the documents were embedded
+    embedder: Embedder,
+    /// Passages added to a prompt
+    limit: usize,
+}
+
+impl Retriever {
+    /// Creates a retriever adding up to `limit` passages to a prompt
+    pub fn new(index: DocumentIndex, embedder: Embedder, limit: usize) -> Self {
+        Self { index, embedder, limit }
+    }
+
+    /// Extends a system prompt with the passages relevant to a question
+    ///
+    /// # Returns
+    /// The extended prompt, or `None` if no passages were found
+    ///
+    /// # Errors
+    /// Returns an error if the index cannot be searched
+    pub async fn augment(&self, system_prompt: Option<&str>, question: &str) -> AppResult<Option<String>> {
+        let passages = self.index.search(&self.embedder, question, self.limit).await?;
+        if passages.is_empty() {
+            return Ok(None);
+        }
+        let context = format_context(&passages);
+        Ok(Some(match system_prompt {
+            Some(prompt) if !prompt.trim().is_empty() => format!("{}\n\n{}", prompt, context),
+            _ => context,
+        }))
+    }
+}
+
+/// Formats passages as numbered excerpts with their sources
+pub fn format_context(passages: &[Passage]) -> String {
+    let mut context = CONTEXT_PROMPT.to_string();
+    for (index, passage) in passages.iter().enumerate() {
+        context.push_str(&format!("\n\n[{}] {}", index + 1, citation(passage)));
+        context.push_str(&format!("\n{}", passage.text));
+    }
+    context
+}
+
+/// Where a passage comes from, such as `/docs/setup.md:4-20 (Install)`
+pub fn citation(passage: &Passage) -> String {
+    let lines = if passage.start_line == passage.end_line {
+        passage.start_line.to_string()
+    } else {
+        format!("{}-{}", passage.start_line, passage.end_line)
+    };
+    match &passage.heading {
+        Some(heading) => format!("{}:{} ({})", passage.path, lines, heading),
+        None => format!("{}:{}", passage.path, lines),
+    }
+}
+
+/// The folders that are not inside another of them, each once
+///
+/// Documents are stored by path, so a file must belong to a single root.
+fn outermost_folders(folders: &[PathBuf]) -> Vec<PathBuf> {
+    let mut outermost: Vec<PathBuf> = Vec::new();
+    for folder in folders {
+        let nested = folders.iter().any(|other| other != folder && folder.starts_with(other));
+        if !nested && !outermost.contains(folder) {
+            outermost.push(folder.clone());
+        }
+    }
+    outermost
+}
+
+/// Key under which a folder's documents are stored
+fn root_key(folder: &Path) -> String {
+    folder.to_string_lossy().to_string()
+}
+
+/// Text embedded for a chunk, naming its file and section for context
+fn embedding_input(path: &Path, chunk: &Chunk) -> String {
+    let name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+    match &chunk.heading {
+        Some(heading) => format!("{} > {}\n{}", name, heading, chunk.text),
+        None => format!("{}\n{}", name, chunk.text),
+    }
+}
+
+/// Lists the indexable files in a folder and its subfolders
+///
+/// Files matched by `.gitignore` and `.ignore` files, hidden entries and
+/// folders of dependencies or build output are left out, as are files too
+/// large to index.
+fn list_files(folder: &Path) -> std::io::Result<(Vec<ListedFile>, Vec<SkippedFile>)> {
+    if !std::fs::metadata(folder)?.is_dir() {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Not a folder"));
+    }
+
+    let mut files = Vec::new();
+    let mut skipped = Vec::new();
+    let walker = WalkBuilder::new(folder)
+        .git_global(false)
+        .require_git(false)
+        .filter_entry(|entry| {
+            !(entry.file_type().is_some_and(|kind| kind.is_dir()) && is_skipped_dir(entry.file_name()))
+        })
+        .build();
+    for entry in walker {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                skipped.push(SkippedFile { path: folder.to_string_lossy().to_string(), reason: e.to_string() });
+                continue;
+            }
+        };
+        if !entry.file_type().is_some_and(|kind| kind.is_file()) || DocumentKind::from_path(entry.path()).is_none() {
+            continue;
+        }
+        let Ok(metadata) = entry.metadata() else { continue };
+        if metadata.len() > MAX_FILE_BYTES {
+            skipped.push(SkippedFile {
+                path: entry.path().to_string_lossy().to_string(),
+                reason: "File is too large".to_string(),
+            });
+            continue;
+        }
+        files.push(ListedFile {
+            path: entry.into_path(),
+            modified: metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .and_then(|since| i64::try_from(since.as_millis()).ok()),
+            size: metadata.len(),
+        });
+    }
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok((files, skipped))
+}
+
+/// Whether a folder holds dependencies or build output
+fn is_skipped_dir(name: &OsStr) -> bool {
+    name.to_str().is_some_and(|name| SKIPPED_DIRS.contains(&name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tempfile::tempdir;
+    use crate::services::ai::{ChatCompletion, ChatCompletionParams, Message};
+
+    /// Embeds texts by counting a few keywords
+    #[derive(Debug, Default)]
+    struct KeywordProvider {
+        embedded: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl AIProvider for KeywordProvider {
+        fn name(&self) -> &str {
+            "keywords"
+        }
+
+        fn available_models(&self) -> Vec<String> {
+            Vec::new()
+        }
+
+        async fn create_chat_completion(
+            &self,
+            _messages: Vec<Message>,
+            _params: ChatCompletionParams
+        ) -> AppResult<ChatCompletion> {
+            Err(AppError::internal("Not a chat model"))
+        }
+
+        async fn embed(&self, texts: Vec<String>, _model: &str) -> AppResult<Vec<Vec<f32>>> {
+            self.embedded.fetch_add(texts.len(), Ordering::SeqCst);
+            Ok(texts
+                .iter()
+                .map(|text| {
+                    let text = text.to_lowercase();
+                    ["install", "release", "borrow"].iter().map(|word| text.matches(word).count() as f32).collect()
+                })
+                .collect())
+        }
+
+        async fn validate_api_key(&self, _api_key: &str) -> AppResult<bool> {
+            Ok(true)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_folders_are_indexed_and_searched() {
+        let dir = tempdir().unwrap();
+        let docs = dir.path().join("docs");
+        std::fs::create_dir_all(docs.join("node_modules")).unwrap();
+        std::fs::write(docs.join("setup.md"), "# Install\n\nRun the installer to install Synapse.\n").unwrap();
+        std::fs::write(docs.join("release.txt"), "Each release is tagged.\n").unwrap();
+        std::fs::write(docs.join("node_modules").join("dep.md"), "install install install").unwrap();
+        std::fs::write(docs.join("logo.png"), [0u8, 1, 2]).unwrap();
+        std::fs::write(docs.join(".gitignore"), "drafts/\n").unwrap();
+        std::fs::create_dir_all(docs.join("drafts")).unwrap();
+        std::fs::write(docs.join("drafts").join("todo.md"), "install later").unwrap();
+
+        let provider = Arc::new(KeywordProvider::default());
+        let embedder = Embedder::new("keywords", provider.clone(), "counts");
+        let index = DocumentIndex::new(DocumentStore::open(&dir.path().join("documents.db")).unwrap());
+
+        let folders = vec![docs.clone()];
+        let progress = std::sync::Mutex::new(Vec::new());
+        let report = index
+            .sync(&folders, &embedder, &|update| progress.lock().unwrap().push(update))
+            .await
+            .unwrap();
+        assert_eq!((report.indexed, report.unchanged, report.chunks), (2, 0, 2));
+        assert_eq!(provider.embedded.load(Ordering::SeqCst), 2);
+        let progress = progress.into_inner().unwrap();
+        assert_eq!(progress.iter().map(|update| (update.processed, update.total)).collect::<Vec<_>>(), [(1, 2), (2, 2)]);
+
+        let passages = index.search(&embedder, "How do I install it?", 1).await.unwrap();
+        assert_eq!(passages.len(), 1);
+        assert!(passages[0].path.ends_with("setup.md"));
+        assert_eq!(citation(&passages[0]), format!("{}:1-3 (Install)", passages[0].path));
+
+        // Unchanged files are not embedded again, deleted ones are dropped
+        let embedded = provider.embedded.load(Ordering::SeqCst);
+        std::fs::remove_file(docs.join("release.txt")).unwrap();
+        let report = index.sync(&folders, &embedder, &|_| {}).await.unwrap();
+        assert_eq!((report.indexed, report.unchanged, report.removed), (0, 1, 1));
+        assert_eq!(provider.embedded.load(Ordering::SeqCst), embedded);
+
+        // Only the chunks of a file that changed are embedded again
+        std::fs::write(
+            docs.join("setup.md"),
+            "# Install\n\nRun the installer to install Synapse.\n\n# Release\n\nTag a release.\n",
+        )
+        .unwrap();
+        let report = index.sync(&folders, &embedder, &|_| {}).await.unwrap();
+        assert_eq!((report.indexed, report.chunks, report.reused_chunks), (1, 1, 1));
+        assert_eq!(provider.embedded.load(Ordering::SeqCst), embedded + 1);
+
+        let retriever = Retriever::new(index.clone(), embedder, 2);
+        let prompt = retriever.augment(Some("Be brief."), "install").await.unwrap().unwrap();
+        assert!(prompt.starts_with("Be brief.\n\n"));
+        assert!(prompt.contains("[1] ") && prompt.contains("Run the installer"));
+
+        // Folders removed from the settings are dropped from the index
+        let report = index.sync(&[], &Embedder::new("keywords", provider, "counts"), &|_| {}).await.unwrap();
+        assert_eq!(report.removed, 1);
+        assert_eq!(index.stats().await.unwrap().documents, 0);
+    }
+    #[tokio::test]
+    async fn test_nested_folders_are_indexed_once() {
+        let dir = tempdir().unwrap();
+        let docs = dir.path().join("docs");
+        std::fs::create_dir_all(docs.join("guides")).unwrap();
+        std::fs::write(docs.join("readme.md"), "Read me first.\n").unwrap();
+        std::fs::write(docs.join("guides").join("install.md"), "How to install.\n").unwrap();
+
+        let provider = Arc::new(KeywordProvider::default());
+        let embedder = Embedder::new("keywords", provider.clone(), "counts");
+        let index = DocumentIndex::new(DocumentStore::open(&dir.path().join("documents.db")).unwrap());
+
+        let folders = vec![docs.join("guides"), docs.clone(), docs.clone()];
+        let report = index.sync(&folders, &embedder, &|_| {}).await.unwrap();
+        assert_eq!((report.indexed, report.chunks), (2, 2));
+        assert_eq!(index.stats().await.unwrap().documents, 2);
+
+        let report = index.sync(&folders, &embedder, &|_| {}).await.unwrap();
+        assert_eq!((report.indexed, report.unchanged, report.removed, report.chunks), (0, 2, 0, 0));
+        assert_eq!(provider.embedded.load(Ordering::SeqCst), 2);
+    }
+}
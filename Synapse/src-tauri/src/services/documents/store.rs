@@ -0,0 +1,399 @@
+//! Document index storage
+//!
+//! This module persists indexed documents and the embeddings of their
+//! chunks in an embedded SQLite database. Embeddings are stored as little
+//! endian `f32` blobs and compared by cosine similarity when searching,
+//! which stays fast enough for the folders of a single user. Every chunk
+//! keeps a hash of its text, so unchanged chunks of an edited file keep
+//! their embeddings.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+
+use crate::utils::{AppError, AppResult};
+use super::chunk::Chunk;
+
+/// Schema migrations, applied in order
+///
+/// The schema version stored in the database is the number of migrations
+/// that have been applied. New migrations must only ever be appended.
+const MIGRATIONS: &[&str] = &[
+    // 1: documents and the embeddings of their chunks
+    "CREATE TABLE documents (
+        path TEXT PRIMARY KEY,
+        root TEXT NOT NULL,
+        hash TEXT NOT NULL,
+        embedder TEXT NOT NULL,
+        indexed_at INTEGER NOT NULL
+    );
+    CREATE INDEX documents_by_root ON documents (root);
+    CREATE TABLE chunks (
+        path TEXT NOT NULL REFERENCES documents(path) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        heading TEXT,
+        start_line INTEGER NOT NULL,
+        end_line INTEGER NOT NULL,
+        text TEXT NOT NULL,
+        embedding BLOB NOT NULL,
+        PRIMARY KEY (path, position)
+    );",
+    // 2: modification times and sizes of documents, and hashes of chunks
+    "ALTER TABLE documents ADD COLUMN modified INTEGER;
+    ALTER TABLE documents ADD COLUMN size INTEGER;
+    ALTER TABLE chunks ADD COLUMN hash TEXT;",
+];
+
+/// The schema version this build of Synapse writes
+const SCHEMA_VERSION: usize = MIGRATIONS.len();
+
+/// A document as recorded in the index
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredDocument {
+    /// Path of the file
+    pub path: String,
+    /// Indexed folder the file belongs to
+    pub root: String,
+    /// SHA-256 of the file's contents when it was indexed
+    pub hash: String,
+    /// Provider and model that embedded the chunks, see `Embedder::key`
+    pub embedder: String,
+    /// Modification time of the file in milliseconds since the epoch
+    pub modified: Option<i64>,
+    /// Size of the file in bytes
+    pub size: Option<u64>,
+}
+
+/// A chunk with its embedding, ready to be stored
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmbeddedChunk {
+    /// The chunk
+    pub chunk: Chunk,
+    /// SHA-256 of the text that was embedded
+    pub hash: String,
+    /// The embedding
+    pub embedding: Vec<f32>,
+}
+
+/// A chunk found by a search
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Passage {
+    /// Path of the file the chunk is from
+    pub path: String,
+    /// Heading of the Markdown section the chunk belongs to
+    pub heading: Option<String>,
+    /// First line of the chunk
+    pub start_line: usize,
+    /// Last line of the chunk
+    pub end_line: usize,
+    /// Text of the chunk
+    pub text: String,
+    /// Cosine similarity to the query, between -1 and 1
+    pub score: f32,
+}
+
+/// Size of the index
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct IndexStats {
+    /// Number of indexed files
+    pub documents: usize,
+    /// Number of embedded chunks
+    pub chunks: usize,
+    /// When a file was last indexed
+    pub last_indexed: Option<DateTime<Utc>>,
+}
+
+/// Persistent store for the document index
+///
+/// The connection is shared behind a mutex and only used from blocking
+/// tasks, so callers never block the async runtime on disk I/O.
+#[derive(Debug, Clone)]
+pub struct DocumentStore {
+    /// Connection to the database
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl DocumentStore {
+    /// Opens the database at `path`, creating and migrating it as needed
+    ///
+    /// # Errors
+    /// Returns an error if the database cannot be opened or was written by
+    /// a newer version of Synapse
+    pub fn open(path: &Path) -> AppResult<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let conn = Connection::open(path)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        Self::from_connection(conn)
+    }
+
+    /// Opens a database that only lives in memory
+    pub fn open_in_memory() -> AppResult<Self> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(mut conn: Connection) -> AppResult<Self> {
+        conn.pragma_update(None, "foreign_keys", "ON")?;
+        migrate(&mut conn)?;
+        Ok(Self { conn: Arc::new(Mutex::new(conn)) })
+    }
+
+    /// Runs `f` with the connection on a blocking thread
+    async fn with_conn<T, F>(&self, f: F) -> AppResult<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> AppResult<T> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = conn
+                .lock()
+                .map_err(|_| AppError::internal("Document index lock poisoned"))?;
+            f(&mut conn)
+        })
+        .await
+        .map_err(|e| AppError::internal(e.to_string()))?
+    }
+
+    /// Lists the documents indexed under a folder
+    pub async fn documents(&self, root: &str) -> AppResult<Vec<StoredDocument>> {
+        let root = root.to_string();
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT path, root, hash, embedder, modified, size FROM documents WHERE root = ?1",
+            )?;
+            let documents = stmt
+                .query_map([root], |row| {
+                    Ok(StoredDocument {
+                        path: row.get(0)?,
+                        root: row.get(1)?,
+                        hash: row.get(2)?,
+                        embedder: row.get(3)?,
+                        modified: row.get(4)?,
+                        size: row.get(5)?,
+                    })
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(documents)
+        })
+        .await
+    }
+
+    /// Returns the embeddings stored for a document's chunks, keyed by chunk hash
+    ///
+    /// Only embeddings made by `embedder` are returned.
+    pub async fn chunk_embeddings(&self, path: &str, embedder: &str) -> AppResult<HashMap<String, Vec<f32>>> {
+        let (path, embedder) = (path.to_string(), embedder.to_string());
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT c.hash, c.embedding FROM chunks c JOIN documents d ON d.path = c.path
+                 WHERE c.path = ?1 AND d.embedder = ?2 AND c.hash IS NOT NULL",
+            )?;
+            let embeddings = stmt
+                .query_map([path, embedder], |row| {
+                    Ok((row.get::<_, String>(0)?, decode_embedding(&row.get::<_, Vec<u8>>(1)?)))
+                })?
+                .collect::<Result<HashMap<_, _>, _>>()?;
+            Ok(embeddings)
+        })
+        .await
+    }
+
+    /// Stores a document with its chunks, replacing any earlier version
+    pub async fn replace_document(&self, document: StoredDocument, chunks: Vec<EmbeddedChunk>) -> AppResult<()> {
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            tx.execute("DELETE FROM documents WHERE path = ?1", [&document.path])?;
+            tx.execute(
+                "INSERT INTO documents (path, root, hash, embedder, indexed_at, modified, size)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    document.path,
+                    document.root,
+                    document.hash,
+                    document.embedder,
+                    Utc::now().timestamp_millis(),
+                    document.modified,
+                    document.size,
+                ],
+            )?;
+            {
+                let mut stmt = tx.prepare(
+                    "INSERT INTO chunks (path, position, heading, start_line, end_line, text, embedding, hash)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                )?;
+                for (position, embedded) in chunks.iter().enumerate() {
+                    stmt.execute(params![
+                        document.path,
+                        position,
+                        embedded.chunk.heading,
+                        embedded.chunk.start_line,
+                        embedded.chunk.end_line,
+                        embedded.chunk.text,
+                        encode_embedding(&embedded.embedding),
+                        embedded.hash,
+                    ])?;
+                }
+            }
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Records a new modification time and size for a document whose
+    /// contents did not change
+    pub async fn touch_document(&self, path: &str, modified: Option<i64>, size: Option<u64>) -> AppResult<()> {
+        let path = path.to_string();
+        self.with_conn(move |conn| {
+            conn.execute(
+                "UPDATE documents SET modified = ?2, size = ?3 WHERE path = ?1",
+                params![path, modified, size],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Removes documents and their chunks
+    pub async fn remove_documents(&self, paths: Vec<String>) -> AppResult<()> {
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            for path in &paths {
+                tx.execute("DELETE FROM documents WHERE path = ?1", [path])?;
+            }
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Removes every document of the folders not in `roots`
+    ///
+    /// # Returns
+    /// The number of documents removed
+    pub async fn retain_roots(&self, roots: Vec<String>) -> AppResult<usize> {
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            let stale: Vec<String> = {
+                let mut stmt = tx.prepare("SELECT DISTINCT root FROM documents")?;
+                let indexed = stmt.query_map([], |row| row.get::<_, String>(0))?.collect::<Result<Vec<_>, _>>()?;
+                indexed.into_iter().filter(|root| !roots.contains(root)).collect()
+            };
+            let mut removed = 0;
+            for root in &stale {
+                removed += tx.execute("DELETE FROM documents WHERE root = ?1", [root])?;
+            }
+            tx.commit()?;
+            Ok(removed)
+        })
+        .await
+    }
+
+    /// Finds the chunks most similar to a query embedding
+    ///
+    /// Only chunks embedded by `embedder` are compared, since vectors of
+    /// different models are not comparable.
+    pub async fn search(&self, embedder: &str, query: Vec<f32>, limit: usize) -> AppResult<Vec<Passage>> {
+        let embedder = embedder.to_string();
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT c.path, c.heading, c.start_line, c.end_line, c.text, c.embedding
+                 FROM chunks c JOIN documents d ON d.path = c.path
+                 WHERE d.embedder = ?1",
+            )?;
+            let mut rows = stmt.query([embedder])?;
+            let mut passages: Vec<Passage> = Vec::new();
+            while let Some(row) = rows.next()? {
+                let embedding = decode_embedding(&row.get::<_, Vec<u8>>(5)?);
+                let score = cosine_similarity(&query, &embedding);
+                if passages.len() == limit && passages.last().map_or(true, |last| last.score >= score) {
+                    continue;
+                }
+                passages.push(Passage {
+                    path: row.get(0)?,
+                    heading: row.get(1)?,
+                    start_line: row.get(2)?,
+                    end_line: row.get(3)?,
+                    text: row.get(4)?,
+                    score,
+                });
+                passages.sort_by(|a, b| b.score.total_cmp(&a.score));
+                passages.truncate(limit);
+            }
+            Ok(passages)
+        })
+        .await
+    }
+
+    /// Counts the indexed documents and chunks
+    pub async fn stats(&self) -> AppResult<IndexStats> {
+        self.with_conn(|conn| {
+            let (documents, last_indexed): (usize, Option<i64>) = conn.query_row(
+                "SELECT COUNT(*), MAX(indexed_at) FROM documents",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )?;
+            let chunks: usize = conn.query_row("SELECT COUNT(*) FROM chunks", [], |row| row.get(0))?;
+            Ok(IndexStats {
+                documents,
+                chunks,
+                last_indexed: last_indexed.and_then(DateTime::from_timestamp_millis),
+            })
+        })
+        .await
+    }
+}
+
+/// Brings the schema up to date
+fn migrate(conn: &mut Connection) -> AppResult<()> {
+    let version: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    if version > SCHEMA_VERSION {
+        return Err(AppError::internal(format!(
+            "Document index schema version {} is newer than supported version {}",
+            version, SCHEMA_VERSION
+        )));
+    }
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", index + 1)?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+fn encode_embedding(embedding: &[f32]) -> Vec<u8> {
+    embedding.iter().flat_map(|value| value.to_le_bytes()).collect()
+}
+
+fn decode_embedding(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .collect()
+}
+
+/// Cosine similarity of two vectors, 0 if they differ in length or are zero
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let (mut dot, mut norm_a, mut norm_b) = (0.0f32, 0.0f32, 0.0f32);
+    for (x, y) in a.iter().zip(b) {
+        dot += x * y;
+        norm_a += x * x;
+        norm_b += y * y;
+    }
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a.sqrt() * norm_b.sqrt())
+}
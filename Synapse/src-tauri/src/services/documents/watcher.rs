@@ -0,0 +1,419 @@
+//! Document index watcher
+//!
+//! This module keeps the document index in step with the configured folders.
+//! Changes reported by the file system are collected until they settle and
+//! then handled by an incremental sync, which only reads files whose
+//! modification time or size changed and only embeds the chunks whose text
+//! changed. Every run publishes its progress and outcome as `IndexEvent`s.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+
+use ignore::gitignore::Gitignore;
+use log::{info, warn};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinHandle;
+
+use crate::utils::{AppError, AppResult};
+use super::{is_skipped_dir, DocumentIndex, DocumentKind, Embedder, IndexProgress, IndexReport, IndexStats};
+
+/// How long the folders must be quiet before changes are indexed
+const DEBOUNCE: Duration = Duration::from_secs(1);
+
+/// Events kept for subscribers that fall behind
+const EVENT_CAPACITY: usize = 256;
+
+/// Progress and outcome of indexing runs
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum IndexEvent {
+    /// A run started
+    Started,
+    /// A file was looked at
+    Progress(IndexProgress),
+    /// A run completed
+    Finished { report: IndexReport },
+    /// A run stopped with an error
+    Failed { error: String },
+}
+
+/// State of the document index, as shown in the settings
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct IndexStatus {
+    /// Folders being indexed
+    pub folders: Vec<String>,
+    /// Whether the folders are watched for changes
+    pub watching: bool,
+    /// Whether a run is in progress
+    pub indexing: bool,
+    /// Progress of the current run
+    pub progress: Option<IndexProgress>,
+    /// Summary of the last completed run
+    pub last_report: Option<IndexReport>,
+    /// Error of the last run, if it failed
+    pub last_error: Option<String>,
+    /// Size of the index
+    pub stats: IndexStats,
+}
+
+/// Keeps the document index up to date as files change
+#[derive(Debug, Clone)]
+pub struct DocumentWatcher {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    /// The index being kept up to date
+    index: DocumentIndex,
+    /// What is indexed and how
+    config: Mutex<Config>,
+    /// Progress and outcome of runs
+    state: Mutex<RunState>,
+    /// Held while a run is in progress, so runs never overlap
+    running: tokio::sync::Mutex<()>,
+    /// Publishes progress to subscribers
+    events: broadcast::Sender<IndexEvent>,
+}
+
+#[derive(Debug, Default)]
+struct Config {
+    /// Folders to index
+    folders: Vec<PathBuf>,
+    /// Embedder for the chunks, if one is available
+    embedder: Option<Embedder>,
+    /// Watches the folders, while there is an embedder
+    watcher: Option<RecommendedWatcher>,
+    /// Indexes the changes the watcher reports
+    task: Option<JoinHandle<()>>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct RunState {
+    /// Whether a run is in progress
+    indexing: bool,
+    /// Progress of the current run
+    progress: Option<IndexProgress>,
+    /// Summary of the last completed run
+    last_report: Option<IndexReport>,
+    /// Error of the last run, if it failed
+    last_error: Option<String>,
+}
+
+impl DocumentWatcher {
+    /// Creates a watcher for `index` that watches nothing until configured
+    pub fn new(index: DocumentIndex) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                index,
+                config: Mutex::new(Config::default()),
+                state: Mutex::new(RunState::default()),
+                running: tokio::sync::Mutex::new(()),
+                events: broadcast::channel(EVENT_CAPACITY).0,
+            }),
+        }
+    }
+
+    /// The index being kept up to date
+    pub fn index(&self) -> &DocumentIndex {
+        &self.inner.index
+    }
+
+    /// Subscribes to the progress and outcome of indexing runs
+    pub fn subscribe(&self) -> broadcast::Receiver<IndexEvent> {
+        self.inner.events.subscribe()
+    }
+
+    /// Sets the folders to index and the embedder to index them with
+    ///
+    /// The folders are watched while there is an embedder; without one,
+    /// nothing is indexed. Folders that cannot be watched, such as ones
+    /// that do not exist yet, are still indexed on every sync.
+    pub fn configure(&self, folders: Vec<PathBuf>, embedder: Option<Embedder>) {
+        let Ok(mut config) = self.inner.config.lock() else {
+            return;
+        };
+        if let Some(task) = config.task.take() {
+            task.abort();
+        }
+        config.watcher = None;
+        config.folders = folders.clone();
+        config.embedder = embedder;
+        if config.embedder.is_none() || folders.is_empty() {
+            return;
+        }
+        let folders = canonical_folders(&folders);
+
+        let (sender, changes) = mpsc::unbounded_channel();
+        let watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+            Ok(event) => {
+                let _ = sender.send(event);
+            }
+            Err(e) => warn!("Watching documents failed: {}", e),
+        });
+        let mut watcher = match watcher {
+            Ok(watcher) => watcher,
+            Err(e) => {
+                warn!("Failed to watch documents: {}", e);
+                return;
+            }
+        };
+        for folder in &folders {
+            if let Err(e) = watcher.watch(folder, RecursiveMode::Recursive) {
+                warn!("Failed to watch {}: {}", folder.display(), e);
+            }
+        }
+        config.watcher = Some(watcher);
+        config.task = Some(tokio::spawn(index_changes(Arc::downgrade(&self.inner), folders, changes)));
+    }
+
+    /// Indexes the configured folders, waiting for a run in progress first
+    ///
+    /// # Errors
+    /// Returns an error if no embedder is configured, the provider fails to
+    /// embed or the index cannot be written
+    pub async fn sync(&self) -> AppResult<IndexReport> {
+        let _running = self.inner.running.lock().await;
+        let (folders, embedder) = match self.inner.config.lock() {
+            Ok(config) => (config.folders.clone(), config.embedder.clone()),
+            Err(_) => return Err(AppError::internal("Document index configuration is unavailable")),
+        };
+        let embedder = embedder.ok_or_else(|| AppError::invalid_input("No embedding provider is available"))?;
+
+        self.update_state(|state| {
+            state.indexing = true;
+            state.progress = None;
+        });
+        let _ = self.inner.events.send(IndexEvent::Started);
+        let inner = self.inner.clone();
+        let result = self.inner.index
+            .sync(&folders, &embedder, &|progress| {
+                if let Ok(mut state) = inner.state.lock() {
+                    state.progress = Some(progress.clone());
+                }
+                let _ = inner.events.send(IndexEvent::Progress(progress));
+            })
+            .await;
+
+        self.update_state(|state| {
+            state.indexing = false;
+            state.progress = None;
+            match &result {
+                Ok(report) => {
+                    state.last_report = Some(report.clone());
+                    state.last_error = None;
+                }
+                Err(e) => state.last_error = Some(e.to_string()),
+            }
+        });
+        let _ = self.inner.events.send(match &result {
+            Ok(report) => IndexEvent::Finished { report: report.clone() },
+            Err(e) => IndexEvent::Failed { error: e.to_string() },
+        });
+        result
+    }
+
+    /// Indexes the configured folders without waiting for the outcome
+    ///
+    /// Nothing happens if no embedder is configured.
+    pub fn sync_in_background(&self) {
+        let configured = self.inner.config
+            .lock()
+            .is_ok_and(|config| config.embedder.is_some());
+        if !configured {
+            return;
+        }
+        let watcher = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) = watcher.sync().await {
+                warn!("Indexing documents failed: {}", e);
+            }
+        });
+    }
+
+    /// Reports what is indexed and how far the current run has come
+    ///
+    /// # Errors
+    /// Returns an error if the index cannot be read
+    pub async fn status(&self) -> AppResult<IndexStatus> {
+        let stats = self.inner.index.stats().await?;
+        let (folders, watching) = self.inner.config
+            .lock()
+            .map(|config| {
+                let folders = config.folders.iter().map(|folder| folder.to_string_lossy().to_string()).collect();
+                (folders, config.watcher.is_some())
+            })
+            .unwrap_or_default();
+        let state = self.inner.state.lock().map(|state| state.clone()).unwrap_or_default();
+        Ok(IndexStatus {
+            folders,
+            watching,
+            indexing: state.indexing,
+            progress: state.progress,
+            last_report: state.last_report,
+            last_error: state.last_error,
+            stats,
+        })
+    }
+
+    fn update_state(&self, update: impl FnOnce(&mut RunState)) {
+        if let Ok(mut state) = self.inner.state.lock() {
+            update(&mut state);
+        }
+    }
+}
+
+/// Indexes the folders whenever the changes reported for them settle
+async fn index_changes(
+    inner: Weak<Inner>,
+    folders: Vec<PathBuf>,
+    mut changes: mpsc::UnboundedReceiver<notify::Event>,
+) {
+    let mut ignores = load_ignores(&folders);
+    while let Some(event) = changes.recv().await {
+        if touches_gitignore(&event) {
+            ignores = load_ignores(&folders);
+        }
+        if !is_relevant(&event, &folders, &ignores) {
+            continue;
+        }
+        // Wait for bursts of changes, such as a checkout, to settle
+        loop {
+            match tokio::time::timeout(DEBOUNCE, changes.recv()).await {
+                Ok(Some(event)) => {
+                    if touches_gitignore(&event) {
+                        ignores = load_ignores(&folders);
+                    }
+                }
+                Ok(None) => return,
+                Err(_) => break,
+            }
+        }
+        let Some(inner) = inner.upgrade() else {
+            return;
+        };
+        info!("Documents changed, updating the index");
+        DocumentWatcher { inner }.sync_in_background();
+    }
+}
+
+/// Resolves the folders as the file system reports changes to them
+///
+/// Folders reached through symbolic links, such as `/var` on macOS, are
+/// reported under their real path. Folders that do not exist are kept as
+/// configured.
+fn canonical_folders(folders: &[PathBuf]) -> Vec<PathBuf> {
+    folders
+        .iter()
+        .map(|folder| folder.canonicalize().unwrap_or_else(|_| folder.clone()))
+        .collect()
+}
+
+/// Reads the `.gitignore` at the top of each folder
+fn load_ignores(folders: &[PathBuf]) -> Vec<Gitignore> {
+    folders
+        .iter()
+        .map(|folder| Gitignore::new(folder.join(".gitignore")).0)
+        .collect()
+}
+
+/// Whether a change concerns a `.gitignore`, which changes what is relevant
+fn touches_gitignore(event: &notify::Event) -> bool {
+    event.paths.iter().any(|path| path.file_name().is_some_and(|name| name == ".gitignore"))
+}
+
+/// Whether a change could affect the index
+///
+/// Changes to hidden entries, folders of dependencies or build output,
+/// files ignored by a folder's `.gitignore` and files that are never
+/// indexed are left alone. Paths without an extension may be folders and
+/// count, as do changes to the `.gitignore` itself.
+fn is_relevant(event: &notify::Event, folders: &[PathBuf], ignores: &[Gitignore]) -> bool {
+    if event.kind.is_access() {
+        return false;
+    }
+    event.paths.iter().any(|path| {
+        folders.iter().zip(ignores).any(|(folder, ignore)| {
+            let Ok(relative) = path.strip_prefix(folder) else {
+                return false;
+            };
+            if relative == Path::new(".gitignore") {
+                return true;
+            }
+            let hidden = relative.components().any(|component| {
+                let name = component.as_os_str();
+                name.to_str().is_some_and(|name| name.starts_with('.')) || is_skipped_dir(name)
+            });
+            !hidden
+                && !ignore.matched_path_or_any_parents(path, false).is_ignore()
+                && (path.extension().is_none() || DocumentKind::from_path(path).is_some())
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{AccessKind, CreateKind, EventKind};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_only_relevant_changes_are_indexed() {
+        let dir = tempdir().unwrap();
+        let folder = dir.path().to_path_buf();
+        std::fs::write(folder.join(".gitignore"), "drafts/\n*.log.md\n").unwrap();
+        let folders = vec![folder.clone()];
+        let ignores = vec![Gitignore::new(folder.join(".gitignore")).0];
+        let relevant = |path: PathBuf| {
+            let event = notify::Event::new(EventKind::Create(CreateKind::File)).add_path(path);
+            is_relevant(&event, &folders, &ignores)
+        };
+
+        assert!(relevant(folder.join("notes").join("setup.md")));
+        assert!(relevant(folder.join("notes")));
+        assert!(relevant(folder.join(".gitignore")));
+        assert!(!relevant(folder.join("drafts").join("todo.md")));
+        assert!(!relevant(folder.join("build.log.md")));
+        assert!(!relevant(folder.join(".git").join("index")));
+        assert!(!relevant(folder.join("node_modules").join("dep").join("README.md")));
+        assert!(!relevant(folder.join("logo.png")));
+        assert!(!relevant(dir.path().parent().unwrap().join("elsewhere.md")));
+
+        let read = notify::Event::new(EventKind::Access(AccessKind::Any)).add_path(folder.join("setup.md"));
+        assert!(!is_relevant(&read, &folders, &ignores));
+
+        // Changed ignore rules apply once the `.gitignore` is read again
+        std::fs::write(folder.join(".gitignore"), "notes/\n").unwrap();
+        let saved = notify::Event::new(EventKind::Create(CreateKind::File)).add_path(folder.join(".gitignore"));
+        assert!(touches_gitignore(&saved));
+        let ignores = load_ignores(&folders);
+        let event = notify::Event::new(EventKind::Create(CreateKind::File)).add_path(folder.join("drafts").join("todo.md"));
+        assert!(is_relevant(&event, &folders, &ignores));
+        let event = notify::Event::new(EventKind::Create(CreateKind::File)).add_path(folder.join("notes").join("setup.md"));
+        assert!(!is_relevant(&event, &folders, &ignores));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_folders_are_matched_by_their_real_path() {
+        let dir = tempdir().unwrap();
+        let real = dir.path().join("real");
+        std::fs::create_dir(&real).unwrap();
+        let link = dir.path().join("link");
+        std::os::unix::fs::symlink(&real, &link).unwrap();
+
+        // Changes are reported under the real path of a linked folder
+        let folders = canonical_folders(&[link]);
+        assert_eq!(folders, vec![real.canonicalize().unwrap()]);
+        let ignores = load_ignores(&folders);
+        let event = notify::Event::new(EventKind::Create(CreateKind::File))
+            .add_path(real.canonicalize().unwrap().join("notes.md"));
+        assert!(is_relevant(&event, &folders, &ignores));
+
+        let missing = vec![dir.path().join("missing")];
+        assert_eq!(canonical_folders(&missing), missing);
+    }
+}
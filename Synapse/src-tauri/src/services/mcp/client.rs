@@ -0,0 +1,332 @@
+//! MCP stdio client
+//!
+//! This module starts an MCP server as a child process and talks to it over
+//! its standard input and output. Responses are matched to requests by id
+//! on a background task, which also answers pings from the server and
+//! notices when the process exits. Whatever the server writes to its
+//! standard error is logged.
+
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use log::{info, warn};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::sync::{oneshot, watch};
+use tokio::task::JoinHandle;
+
+use crate::settings::McpServerConfig;
+use crate::utils::{AppError, AppResult};
+use super::protocol::{
+    CallToolResult, Implementation, InitializeResult, McpPrompt, McpResource, McpTool, RpcError, RpcMessage,
+    METHOD_NOT_FOUND, PROTOCOL_VERSION,
+};
+
+/// How long a request may take before it is given up
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// How long a server may take to answer `initialize`
+const INITIALIZE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Pages fetched when listing tools, resources or prompts
+const MAX_PAGES: usize = 20;
+
+/// Requests waiting for their response, by id
+type PendingMap = HashMap<u64, oneshot::Sender<Result<Value, RpcError>>>;
+
+/// Pending requests, shared with the reader
+type Pending = Arc<Mutex<PendingMap>>;
+
+/// Stdin of the server, shared with the task answering its requests
+type Writer = Arc<tokio::sync::Mutex<ChildStdin>>;
+
+/// A connection to a running MCP server
+///
+/// Dropping the client stops the server.
+#[derive(Debug)]
+pub struct McpClient {
+    /// Id of the server in the settings
+    server_id: String,
+    /// What the server reported when initialized
+    initialize: InitializeResult,
+    /// Writes messages to the server
+    writer: Writer,
+    /// Requests waiting for their response
+    pending: Pending,
+    /// Id of the next request
+    next_id: AtomicU64,
+    /// Why the connection closed, once it has
+    closed: watch::Receiver<Option<String>>,
+    /// Reads the server's messages and owns the process
+    reader: JoinHandle<()>,
+}
+
+impl McpClient {
+    /// Starts a server and performs the initialize handshake
+    ///
+    /// # Errors
+    /// Returns an error if the program cannot be started or the handshake
+    /// fails
+    pub async fn connect(config: &McpServerConfig) -> AppResult<Self> {
+        let mut child = Command::new(&config.command)
+            .args(&config.args)
+            .envs(&config.env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| AppError::internal(format!("Failed to start {}: {}", config.command, e)))?;
+
+        let stdin = child.stdin.take().ok_or_else(|| AppError::internal("Server has no stdin"))?;
+        let stdout = child.stdout.take().ok_or_else(|| AppError::internal("Server has no stdout"))?;
+        if let Some(stderr) = child.stderr.take() {
+            let server_id = config.id.clone();
+            tokio::spawn(async move {
+                let mut lines = BufReader::new(stderr).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    info!("[mcp {}] {}", server_id, line);
+                }
+            });
+        }
+
+        let writer: Writer = Arc::new(tokio::sync::Mutex::new(stdin));
+        let pending: Pending = Arc::default();
+        let (closed_sender, closed) = watch::channel(None);
+        let reader = tokio::spawn(read_messages(
+            config.id.clone(),
+            child,
+            stdout,
+            writer.clone(),
+            pending.clone(),
+            closed_sender,
+        ));
+
+        let mut client = Self {
+            server_id: config.id.clone(),
+            initialize: InitializeResult {
+                protocol_version: PROTOCOL_VERSION.to_string(),
+                capabilities: serde_json::Map::new(),
+                server_info: Implementation {
+                    name: config.id.clone(),
+                    version: String::new(),
+                },
+                instructions: None,
+            },
+            writer,
+            pending,
+            next_id: AtomicU64::new(1),
+            closed,
+            reader,
+        };
+
+        let params = json!({
+            "protocolVersion": PROTOCOL_VERSION,
+            "capabilities": {},
+            "clientInfo": Implementation::synapse(),
+        });
+        let result = client.request_with_timeout("initialize", params, INITIALIZE_TIMEOUT).await?;
+        client.initialize = serde_json::from_value(result)
+            .map_err(|e| AppError::api(format!("Invalid initialize result: {}", e)))?;
+        client.send(&RpcMessage::notification("notifications/initialized", None)).await?;
+        Ok(client)
+    }
+
+    /// What the server reported when initialized
+    pub fn server_info(&self) -> &InitializeResult {
+        &self.initialize
+    }
+
+    /// Whether the server supports a feature such as `tools` or `prompts`
+    pub fn supports(&self, capability: &str) -> bool {
+        self.initialize.capabilities.contains_key(capability)
+    }
+
+    /// Waits until the connection closes and returns why
+    pub async fn closed(&self) -> String {
+        let mut closed = self.closed.clone();
+        loop {
+            if let Some(reason) = closed.borrow().clone() {
+                return reason;
+            }
+            if closed.changed().await.is_err() {
+                return "Connection closed".to_string();
+            }
+        }
+    }
+
+    /// Sends a request and waits for its result
+    ///
+    /// # Errors
+    /// Returns an error if the server answers with an error, closes the
+    /// connection or does not answer in time
+    pub async fn request(&self, method: &str, params: Value) -> AppResult<Value> {
+        self.request_with_timeout(method, params, REQUEST_TIMEOUT).await
+    }
+
+    async fn request_with_timeout(&self, method: &str, params: Value, timeout: Duration) -> AppResult<Value> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = oneshot::channel();
+        self.pending_requests()?.insert(id, sender);
+
+        // The reader fails pending requests once it has marked the
+        // connection closed, so a request added later has to check itself
+        let closed = self.closed.borrow().clone();
+        let sent = match closed {
+            Some(reason) => Err(AppError::api(reason)),
+            None => self.send(&RpcMessage::request(id.into(), method, params)).await,
+        };
+        if let Err(e) = sent {
+            self.pending_requests()?.remove(&id);
+            return Err(e);
+        }
+
+        match tokio::time::timeout(timeout, receiver).await {
+            Ok(Ok(Ok(result))) => Ok(result),
+            Ok(Ok(Err(error))) => Err(AppError::api(format!("{} failed: {}", method, error.message))),
+            Ok(Err(_)) => Err(AppError::api(format!("{} closed the connection", self.server_id))),
+            Err(_) => {
+                self.pending_requests()?.remove(&id);
+                Err(AppError::api(format!("{} did not answer {} in time", self.server_id, method)))
+            }
+        }
+    }
+
+    /// Lists the server's tools
+    pub async fn list_tools(&self) -> AppResult<Vec<McpTool>> {
+        self.list("tools/list", "tools").await
+    }
+
+    /// Lists the server's resources
+    pub async fn list_resources(&self) -> AppResult<Vec<McpResource>> {
+        self.list("resources/list", "resources").await
+    }
+
+    /// Lists the server's prompts
+    pub async fn list_prompts(&self) -> AppResult<Vec<McpPrompt>> {
+        self.list("prompts/list", "prompts").await
+    }
+
+    /// Calls a tool of the server
+    pub async fn call_tool(&self, name: &str, arguments: Value) -> AppResult<CallToolResult> {
+        let result = self.request("tools/call", json!({ "name": name, "arguments": arguments })).await?;
+        serde_json::from_value(result).map_err(|e| AppError::api(format!("Invalid tool result: {}", e)))
+    }
+
+    /// Fetches all pages of a list
+    async fn list<T: DeserializeOwned>(&self, method: &str, field: &str) -> AppResult<Vec<T>> {
+        let mut items = Vec::new();
+        let mut cursor: Option<Value> = None;
+        for _ in 0..MAX_PAGES {
+            let params = match cursor.take() {
+                Some(cursor) => json!({ "cursor": cursor }),
+                None => json!({}),
+            };
+            let mut result = self.request(method, params).await?;
+            let page = result.get_mut(field).map(Value::take).unwrap_or_else(|| json!([]));
+            items.extend(
+                serde_json::from_value::<Vec<T>>(page)
+                    .map_err(|e| AppError::api(format!("Invalid {} result: {}", method, e)))?,
+            );
+            cursor = result.get("nextCursor").filter(|cursor| !cursor.is_null()).cloned();
+            if cursor.is_none() {
+                break;
+            }
+        }
+        Ok(items)
+    }
+
+    async fn send(&self, message: &RpcMessage) -> AppResult<()> {
+        write_message(&self.writer, message).await
+    }
+
+    fn pending_requests(&self) -> AppResult<MutexGuard<'_, PendingMap>> {
+        self.pending.lock().map_err(|_| AppError::internal("MCP client state is poisoned"))
+    }
+}
+
+impl Drop for McpClient {
+    fn drop(&mut self) {
+        // The reader owns the process, which is killed when it is dropped
+        self.reader.abort();
+    }
+}
+
+/// Writes one message as a line of JSON
+async fn write_message(writer: &Writer, message: &RpcMessage) -> AppResult<()> {
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    let mut writer = writer.lock().await;
+    writer.write_all(&line).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Reads the server's messages until it closes its output
+///
+/// Responses complete their pending request; requests from the server are
+/// answered, pings with an empty result and anything else as unknown.
+async fn read_messages(
+    server_id: String,
+    mut child: Child,
+    stdout: ChildStdout,
+    writer: Writer,
+    pending: Pending,
+    closed: watch::Sender<Option<String>>,
+) {
+    let mut lines = BufReader::new(stdout).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let message: RpcMessage = match serde_json::from_str(&line) {
+            Ok(message) => message,
+            Err(e) => {
+                warn!("[mcp {}] Ignoring invalid message: {}", server_id, e);
+                continue;
+            }
+        };
+
+        match (message.id, message.method) {
+            (Some(id), None) => {
+                let sender = id
+                    .as_u64()
+                    .and_then(|id| pending.lock().ok()?.remove(&id));
+                if let Some(sender) = sender {
+                    let result = match message.error {
+                        Some(error) => Err(error),
+                        None => Ok(message.result.unwrap_or(Value::Null)),
+                    };
+                    let _ = sender.send(result);
+                }
+            }
+            (Some(id), Some(method)) => {
+                let response = match method.as_str() {
+                    "ping" => RpcMessage::response(id, json!({})),
+                    _ => RpcMessage::error(id, METHOD_NOT_FOUND, format!("Unknown method: {}", method)),
+                };
+                if let Err(e) = write_message(&writer, &response).await {
+                    warn!("[mcp {}] Failed to answer {}: {}", server_id, method, e);
+                }
+            }
+            (None, Some(method)) => info!("[mcp {}] Notification: {}", server_id, method),
+            (None, None) => {}
+        }
+    }
+
+    let reason = match child.wait().await {
+        Ok(status) if status.success() => format!("{} exited", server_id),
+        Ok(status) => format!("{} exited with {}", server_id, status),
+        Err(e) => format!("{} stopped responding: {}", server_id, e),
+    };
+    let _ = closed.send(Some(reason));
+    if let Ok(mut pending) = pending.lock() {
+        // Dropping the senders fails the waiting requests
+        pending.clear();
+    }
+}
@@ -0,0 +1,460 @@
+//! Model Context Protocol
+//!
+//! This module connects Synapse to MCP servers. Servers configured in the
+//! settings are started over stdio and supervised: their tools, resources
+//! and prompts are listed after the handshake, their tools are registered
+//! with the chat tool registry, and servers that exit are restarted with a
+//! growing delay. The state of every server can be queried for display.
+//! Synapse can also act as an MCP server itself, see `server`.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use serde::Serialize;
+use serde_json::Value;
+use tokio::task::JoinHandle;
+
+use crate::settings::McpServerConfig;
+use crate::utils::{AppError, AppResult};
+use super::ai::{Approval, Tool, ToolRegistry};
+
+pub mod client;
+pub mod protocol;
+pub mod server;
+
+pub use client::McpClient;
+pub use protocol::{Implementation, McpPrompt, McpResource, McpTool};
+pub use server::{McpServer, ServerTransport};
+
+/// Delay before the first restart of a server; it doubles with every failure
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+/// Longest delay between restarts
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(60);
+
+/// Consecutive failures after which a server is given up
+const MAX_FAILURES: u32 = 5;
+
+/// How long a server has to run for earlier failures to be forgotten
+const STABLE_AFTER: Duration = Duration::from_secs(60);
+
+/// Longest name of a registered tool, as accepted by providers
+const MAX_TOOL_NAME: usize = 64;
+
+/// Lifecycle state of a server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum McpServerState {
+    /// The server is starting for the first time
+    Starting,
+    /// The server is connected and its tools are available
+    Running,
+    /// The server exited and is about to be started again
+    Restarting,
+    /// The server failed too often and was given up
+    Failed,
+    /// The server is disabled in the settings
+    Disabled,
+}
+
+/// Status of a configured server
+#[derive(Debug, Clone, Serialize)]
+pub struct McpServerStatus {
+    /// Id of the server in the settings
+    pub id: String,
+    /// Name shown to the user
+    pub name: String,
+    /// Lifecycle state
+    pub state: McpServerState,
+    /// Why the server last stopped or failed to start
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Number of times the server was restarted
+    pub restarts: u32,
+    /// The server program, once connected
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub server_info: Option<Implementation>,
+    /// Tools the server offers
+    pub tools: Vec<McpTool>,
+    /// Resources the server offers
+    pub resources: Vec<McpResource>,
+    /// Prompts the server offers
+    pub prompts: Vec<McpPrompt>,
+}
+
+impl McpServerStatus {
+    fn new(config: &McpServerConfig) -> Self {
+        Self {
+            id: config.id.clone(),
+            name: config.name.clone().unwrap_or_else(|| config.id.clone()),
+            state: if config.enabled {
+                McpServerState::Starting
+            } else {
+                McpServerState::Disabled
+            },
+            error: None,
+            restarts: 0,
+            server_info: None,
+            tools: Vec::new(),
+            resources: Vec::new(),
+            prompts: Vec::new(),
+        }
+    }
+}
+
+/// A configured server and the task supervising it
+#[derive(Debug)]
+struct ServerEntry {
+    /// Configuration the server was started with
+    config: McpServerConfig,
+    /// Current status, updated by the supervisor
+    status: Arc<RwLock<McpServerStatus>>,
+    /// Supervisor task, absent for disabled servers
+    task: Option<JoinHandle<()>>,
+}
+
+impl ServerEntry {
+    /// Stops the server and removes its tools
+    fn stop(&self, tools: &ToolRegistry) {
+        if let Some(task) = &self.task {
+            task.abort();
+        }
+        if let Ok(status) = self.status.read() {
+            unregister_tools(tools, &self.config.id, &status.tools);
+        }
+    }
+}
+
+/// Starts, supervises and stops the configured MCP servers
+#[derive(Debug)]
+pub struct McpManager {
+    /// Registry the servers' tools are added to
+    tools: ToolRegistry,
+    /// Configured servers by id
+    servers: Mutex<BTreeMap<String, ServerEntry>>,
+}
+
+impl McpManager {
+    /// Creates a manager adding tools to `tools`
+    pub fn new(tools: ToolRegistry) -> Self {
+        Self {
+            tools,
+            servers: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Applies the configured servers
+    ///
+    /// New servers are started and removed ones stopped. Servers whose
+    /// configuration changed are restarted; the others keep running.
+    pub fn sync(&self, configs: &[McpServerConfig]) {
+        let Ok(mut servers) = self.servers.lock() else {
+            return;
+        };
+        servers.retain(|_, entry| {
+            let keep = configs.contains(&entry.config);
+            if !keep {
+                entry.stop(&self.tools);
+            }
+            keep
+        });
+        for config in configs {
+            if !servers.contains_key(&config.id) {
+                servers.insert(config.id.clone(), self.start(config.clone()));
+            }
+        }
+    }
+
+    /// Restarts a server, for example after it was given up
+    ///
+    /// # Errors
+    /// Returns an error if no server with this id is configured
+    pub fn restart(&self, server_id: &str) -> AppResult<()> {
+        let mut servers = self.servers
+            .lock()
+            .map_err(|_| AppError::internal("MCP state is poisoned"))?;
+        let entry = servers
+            .remove(server_id)
+            .ok_or_else(|| AppError::not_found("MCP server not found"))?;
+        entry.stop(&self.tools);
+        servers.insert(server_id.to_string(), self.start(entry.config));
+        Ok(())
+    }
+
+    /// Status of every configured server
+    pub fn statuses(&self) -> Vec<McpServerStatus> {
+        self.servers
+            .lock()
+            .map(|servers| {
+                servers
+                    .values()
+                    .filter_map(|entry| entry.status.read().ok().map(|status| status.clone()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn start(&self, config: McpServerConfig) -> ServerEntry {
+        let status = Arc::new(RwLock::new(McpServerStatus::new(&config)));
+        let task = config
+            .enabled
+            .then(|| tokio::spawn(supervise(config.clone(), status.clone(), self.tools.clone())));
+        ServerEntry { config, status, task }
+    }
+}
+
+impl Drop for McpManager {
+    fn drop(&mut self) {
+        if let Ok(servers) = self.servers.lock() {
+            for entry in servers.values() {
+                entry.stop(&self.tools);
+            }
+        }
+    }
+}
+
+/// Keeps a server running until the task is aborted or the server is given up
+async fn supervise(config: McpServerConfig, status: Arc<RwLock<McpServerStatus>>, tools: ToolRegistry) {
+    let update = |change: &dyn Fn(&mut McpServerStatus)| {
+        if let Ok(mut status) = status.write() {
+            change(&mut status);
+        }
+    };
+
+    let mut failures = 0;
+    loop {
+        let started = Instant::now();
+        let error = match connect(&config).await {
+            Ok((client, listing)) => {
+                let client = Arc::new(client);
+                for tool in &listing.tools {
+                    tools.register(Arc::new(RemoteTool::new(&config.id, tool.clone(), client.clone())));
+                }
+                update(&|status| {
+                    status.state = McpServerState::Running;
+                    status.error = None;
+                    status.server_info = Some(client.server_info().server_info.clone());
+                    status.tools = listing.tools.clone();
+                    status.resources = listing.resources.clone();
+                    status.prompts = listing.prompts.clone();
+                });
+
+                let reason = client.closed().await;
+                unregister_tools(&tools, &config.id, &listing.tools);
+                reason
+            }
+            Err(e) => e.to_string(),
+        };
+
+        if started.elapsed() >= STABLE_AFTER {
+            failures = 0;
+        }
+        failures += 1;
+        log::warn!("MCP server {} stopped: {}", config.id, error);
+
+        let given_up = failures >= MAX_FAILURES;
+        update(&|status| {
+            status.state = if given_up { McpServerState::Failed } else { McpServerState::Restarting };
+            status.error = Some(error.clone());
+            status.tools.clear();
+        });
+        if given_up {
+            return;
+        }
+
+        let delay = RESTART_DELAY.saturating_mul(1 << (failures - 1)).min(MAX_RESTART_DELAY);
+        tokio::time::sleep(delay).await;
+        update(&|status| status.restarts += 1);
+    }
+}
+
+/// What a server offers
+#[derive(Debug, Default)]
+struct Listing {
+    tools: Vec<McpTool>,
+    resources: Vec<McpResource>,
+    prompts: Vec<McpPrompt>,
+}
+
+/// Starts a server and lists what it offers, according to its capabilities
+async fn connect(config: &McpServerConfig) -> AppResult<(McpClient, Listing)> {
+    let client = McpClient::connect(config).await?;
+    let mut listing = Listing::default();
+    if client.supports("tools") {
+        listing.tools = client.list_tools().await?;
+    }
+    if client.supports("resources") {
+        listing.resources = client.list_resources().await?;
+    }
+    if client.supports("prompts") {
+        listing.prompts = client.list_prompts().await?;
+    }
+    Ok((client, listing))
+}
+
+/// Name a server's tool is registered under
+///
+/// Tools are prefixed with their server's id so tools of different servers
+/// cannot clash, and reduced to the characters providers accept.
+pub fn tool_name(server_id: &str, tool: &str) -> String {
+    format!("{}__{}", server_id, tool)
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' { c } else { '_' })
+        .take(MAX_TOOL_NAME)
+        .collect()
+}
+
+fn unregister_tools(tools: &ToolRegistry, server_id: &str, server_tools: &[McpTool]) {
+    for tool in server_tools {
+        tools.unregister(&tool_name(server_id, &tool.name));
+    }
+}
+
+/// A tool of an MCP server, as offered to models
+#[derive(Debug)]
+pub struct RemoteTool {
+    /// Id of the server offering the tool
+    server_id: String,
+    /// Name the tool is registered under
+    name: String,
+    /// Description, mentioning the server
+    description: String,
+    /// The tool as the server describes it
+    tool: McpTool,
+    /// Connection to the server
+    client: Arc<McpClient>,
+}
+
+impl RemoteTool {
+    /// Wraps a server's tool
+    pub fn new(server_id: &str, tool: McpTool, client: Arc<McpClient>) -> Self {
+        let description = match &tool.description {
+            Some(description) => format!("{} (from the {} MCP server)", description, server_id),
+            None => format!("{} from the {} MCP server", tool.name, server_id),
+        };
+        Self {
+            server_id: server_id.to_string(),
+            name: tool_name(server_id, &tool.name),
+            description,
+            tool,
+            client,
+        }
+    }
+}
+
+#[async_trait]
+impl Tool for RemoteTool {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn parameters(&self) -> Value {
+        self.tool.input_schema.clone()
+    }
+
+    async fn execute(&self, arguments: Value) -> AppResult<String> {
+        let result = self.client.call_tool(&self.tool.name, arguments).await?;
+        if result.is_error {
+            return Err(AppError::api(result.to_text()));
+        }
+        Ok(result.to_text())
+    }
+
+    /// Asks before calling tools that do not declare themselves read-only
+    fn approval(&self, arguments: &Value) -> Option<Approval> {
+        if self.tool.is_read_only() {
+            return None;
+        }
+        Some(Approval {
+            key: self.name.clone(),
+            description: format!(
+                "Call {} of the {} MCP server with {}",
+                self.tool.name, self.server_id, arguments
+            ),
+        })
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    /// A stand-in MCP server answering with canned responses
+    const STAND_IN_SERVER: &str = r#"
+reply() { printf '{"jsonrpc":"2.0","id":%s,"result":%s}\n' "$id" "$1"; }
+while IFS= read -r line; do
+  id=$(printf '%s\n' "$line" | sed -n 's/.*"id":\([0-9][0-9]*\).*/\1/p')
+  case "$line" in
+    *'"method":"initialize"'*)
+      echo "starting" >&2
+      reply '{"protocolVersion":"2024-11-05","capabilities":{"tools":{},"resources":{}},"serverInfo":{"name":"stand-in","version":"1.0.0"}}' ;;
+    *'"method":"tools/list"'*)
+      reply '{"tools":[{"name":"echo","description":"Echoes text","inputSchema":{"type":"object","properties":{"text":{"type":"string"}}},"annotations":{"readOnlyHint":true}},{"name":"crash","inputSchema":{"type":"object"}}]}' ;;
+    *'"method":"resources/list"'*)
+      reply '{"resources":[{"uri":"file:///notes.txt","name":"notes.txt","mimeType":"text/plain"}]}' ;;
+    *'"name":"crash"'*)
+      exit 1 ;;
+    *'"method":"tools/call"'*)
+      text=$(printf '%s\n' "$line" | sed -n 's/.*"text":"\([^"]*\)".*/\1/p')
+      reply "{\"content\":[{\"type\":\"text\",\"text\":\"$text\"}]}" ;;
+  esac
+done
+"#;
+
+    async fn wait_for(manager: &McpManager, check: impl Fn(&McpServerStatus) -> bool) -> McpServerStatus {
+        for _ in 0..100 {
+            if let Some(status) = manager.statuses().into_iter().find(|status| check(status)) {
+                return status;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("server did not reach the expected state: {:?}", manager.statuses());
+    }
+
+    #[tokio::test]
+    async fn test_servers_are_connected_and_restarted() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let script = temp_dir.path().join("server.sh");
+        std::fs::write(&script, STAND_IN_SERVER).unwrap();
+
+        let registry = ToolRegistry::new();
+        let manager = McpManager::new(registry.clone());
+        let config = McpServerConfig {
+            id: "stand-in".to_string(),
+            name: None,
+            command: "sh".to_string(),
+            args: vec![script.display().to_string()],
+            env: Default::default(),
+            enabled: true,
+        };
+        manager.sync(&[config]);
+
+        let status = wait_for(&manager, |status| status.state == McpServerState::Running).await;
+        assert_eq!(status.server_info.unwrap().name, "stand-in");
+        assert_eq!(status.tools.len(), 2);
+        assert_eq!(status.resources[0].uri, "file:///notes.txt");
+        assert!(status.prompts.is_empty());
+
+        let echo = registry.get("stand-in__echo").unwrap();
+        assert!(echo.approval(&serde_json::json!({})).is_none());
+        assert_eq!(echo.execute(serde_json::json!({ "text": "hello" })).await.unwrap(), "hello");
+
+        // A crashed server is started again and its tools come back
+        let crash = registry.get("stand-in__crash").unwrap();
+        assert!(crash.approval(&serde_json::json!({})).is_some());
+        assert!(crash.execute(serde_json::json!({})).await.is_err());
+        let status = wait_for(&manager, |status| status.restarts == 1 && status.state == McpServerState::Running).await;
+        assert!(status.error.is_none());
+        assert!(registry.get("stand-in__echo").is_some());
+
+        manager.sync(&[]);
+        assert!(manager.statuses().is_empty());
+        assert!(registry.definitions().is_empty());
+    }
+}
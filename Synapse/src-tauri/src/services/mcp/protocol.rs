@@ -0,0 +1,281 @@
+//! MCP wire format
+//!
+//! This module defines the JSON-RPC 2.0 messages the Model Context Protocol
+//! is built on, and the MCP types exchanged over them. Over stdio, every
+//! message is one line of JSON.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Protocol revision Synapse speaks
+pub const PROTOCOL_VERSION: &str = "2024-11-05";
+
+/// JSON-RPC error code for messages that are not valid JSON-RPC
+pub const PARSE_ERROR: i64 = -32700;
+
+/// JSON-RPC error code for unknown methods
+pub const METHOD_NOT_FOUND: i64 = -32601;
+
+/// JSON-RPC error code for invalid parameters
+pub const INVALID_PARAMS: i64 = -32602;
+
+/// A JSON-RPC request, notification or response
+///
+/// Requests carry an id and a method, notifications only a method, and
+/// responses an id with either a result or an error.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RpcMessage {
+    /// Always "2.0"
+    pub jsonrpc: String,
+    /// Id pairing a request with its response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<Value>,
+    /// Method of a request or notification
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    /// Parameters of a request or notification
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params: Option<Value>,
+    /// Result of a successful request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    /// Error of a failed request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<RpcError>,
+}
+
+impl RpcMessage {
+    /// Creates a request
+    pub fn request(id: Value, method: &str, params: Value) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            id: Some(id),
+            method: Some(method.to_string()),
+            params: Some(params),
+            ..Self::default()
+        }
+    }
+
+    /// Creates a notification, which gets no response
+    pub fn notification(method: &str, params: Option<Value>) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            method: Some(method.to_string()),
+            params,
+            ..Self::default()
+        }
+    }
+
+    /// Creates a successful response
+    pub fn response(id: Value, result: Value) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            id: Some(id),
+            result: Some(result),
+            ..Self::default()
+        }
+    }
+
+    /// Creates an error response
+    pub fn error(id: Value, code: i64, message: impl Into<String>) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            id: Some(id),
+            error: Some(RpcError {
+                code,
+                message: message.into(),
+                data: None,
+            }),
+            ..Self::default()
+        }
+    }
+}
+
+/// Error of a failed JSON-RPC request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RpcError {
+    /// Error code, negative for protocol errors
+    pub code: i64,
+    /// Description of the error
+    pub message: String,
+    /// Further details
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+/// Name and version of a client or server
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Implementation {
+    /// Name of the program
+    pub name: String,
+    /// Version of the program
+    pub version: String,
+}
+
+impl Implementation {
+    /// Describes Synapse itself
+    pub fn synapse() -> Self {
+        Self {
+            name: "synapse".to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+}
+
+/// Result of the `initialize` request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InitializeResult {
+    /// Protocol revision the server chose
+    pub protocol_version: String,
+    /// Features the server supports, keyed by feature
+    #[serde(default)]
+    pub capabilities: serde_json::Map<String, Value>,
+    /// The server program
+    pub server_info: Implementation,
+    /// Hints on how to use the server
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instructions: Option<String>,
+}
+
+/// A tool offered by a server
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct McpTool {
+    /// Name the server knows the tool by
+    pub name: String,
+    /// What the tool does
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// JSON schema of the arguments
+    #[serde(default = "empty_schema")]
+    pub input_schema: Value,
+    /// Hints about the tool's behavior
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<ToolAnnotations>,
+}
+
+impl McpTool {
+    /// Whether the server declares that the tool changes nothing
+    pub fn is_read_only(&self) -> bool {
+        self.annotations
+            .as_ref()
+            .and_then(|annotations| annotations.read_only_hint)
+            .unwrap_or(false)
+    }
+}
+
+/// Hints about a tool's behavior
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolAnnotations {
+    /// Whether the tool only reads
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read_only_hint: Option<bool>,
+}
+
+/// A resource offered by a server
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct McpResource {
+    /// URI identifying the resource
+    pub uri: String,
+    /// Name of the resource
+    pub name: String,
+    /// What the resource contains
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// MIME type of the contents
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+}
+
+/// A prompt template offered by a server
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct McpPrompt {
+    /// Name of the prompt
+    pub name: String,
+    /// What the prompt is for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Arguments filled into the template
+    #[serde(default)]
+    pub arguments: Vec<PromptArgument>,
+}
+
+/// An argument of a prompt template
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PromptArgument {
+    /// Name of the argument
+    pub name: String,
+    /// What the argument means
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Whether the argument must be given
+    #[serde(default)]
+    pub required: bool,
+}
+
+/// Result of the `tools/call` request
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallToolResult {
+    /// Output of the tool
+    #[serde(default)]
+    pub content: Vec<ToolContent>,
+    /// Whether the tool failed
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub is_error: bool,
+}
+
+impl CallToolResult {
+    /// Creates a result consisting of text
+    pub fn text(text: impl Into<String>, is_error: bool) -> Self {
+        Self {
+            content: vec![ToolContent::Text { text: text.into() }],
+            is_error,
+        }
+    }
+
+    /// The output as text, with placeholders for content that is not text
+    pub fn to_text(&self) -> String {
+        let parts: Vec<String> = self.content
+            .iter()
+            .map(|content| match content {
+                ToolContent::Text { text } => text.clone(),
+                ToolContent::Image { mime_type, .. } => format!("[Image ({})]", mime_type),
+                ToolContent::Resource { resource } => resource
+                    .get("text")
+                    .and_then(Value::as_str)
+                    .map(str::to_string)
+                    .unwrap_or_else(|| format!("[Resource {}]", resource.get("uri").unwrap_or(&Value::Null))),
+                ToolContent::Other => "[Unsupported content]".to_string(),
+            })
+            .collect();
+        parts.join("\n")
+    }
+}
+
+/// One piece of a tool's output
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ToolContent {
+    /// Plain text
+    Text { text: String },
+    /// An image, base64 encoded
+    Image {
+        data: String,
+        #[serde(rename = "mimeType")]
+        mime_type: String,
+    },
+    /// Contents of a resource
+    Resource { resource: Value },
+    /// Content of a kind Synapse does not know
+    #[serde(other)]
+    Other,
+}
+
+/// Schema of a tool without arguments
+fn empty_schema() -> Value {
+    serde_json::json!({ "type": "object" })
+}
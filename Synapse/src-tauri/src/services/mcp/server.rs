@@ -0,0 +1,518 @@
+//! Synapse as an MCP server
+//!
+//! This module lets other agents use Synapse over the Model Context
+//! Protocol. It offers tools to ask one of the configured models, search
+//! the chat history, list saved sessions and read a session, backed by the
+//! `ChatManager` and the provider registry. The server speaks JSON-RPC over
+//! any byte stream: standard input and output when Synapse is started with
+//! `--mcp`, or, on Unix, connections to a socket file with `--mcp-socket`.
+//! The socket is only accessible to the user, as clients can read the chat
+//! history and spend the user's API keys.
+
+#[cfg(unix)]
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use log::{info, warn};
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+
+use crate::services::ai::{Message, ProviderRegistry, Role};
+use crate::services::chat::{ChatManager, ExportFormat, SearchQuery};
+use crate::settings::SettingsManager;
+use crate::utils::{AppError, AppResult};
+use super::protocol::{
+    CallToolResult, Implementation, McpTool, RpcMessage, INVALID_PARAMS, METHOD_NOT_FOUND, PARSE_ERROR,
+    PROTOCOL_VERSION,
+};
+
+/// Sessions listed when no limit is given
+const DEFAULT_SESSION_LIMIT: usize = 50;
+
+/// Search results returned when no limit is given
+const DEFAULT_RESULT_LIMIT: usize = 10;
+
+const INSTRUCTIONS: &str = "Synapse is a desktop AI assistant. Use ask_model to get an answer \
+from one of the user's configured models, and search_chat_history, list_sessions and \
+read_session to look up the user's earlier conversations.";
+
+/// How the server is reached
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServerTransport {
+    /// Standard input and output of the process
+    Stdio,
+    /// A Unix domain socket at a path
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl ServerTransport {
+    /// Reads the server mode from command-line arguments
+    ///
+    /// `--mcp` serves over stdio and `--mcp-socket <path>` over a Unix
+    /// domain socket at the path.
+    ///
+    /// # Returns
+    /// `None` if Synapse should start normally
+    ///
+    /// # Errors
+    /// Returns an error if the socket path is missing or sockets are not
+    /// supported on this platform
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> AppResult<Option<Self>> {
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--mcp" => return Ok(Some(Self::Stdio)),
+                "--mcp-socket" => {
+                    let address = args
+                        .next()
+                        .ok_or_else(|| AppError::invalid_input("--mcp-socket needs a path"))?;
+                    return Self::socket(&address).map(Some);
+                }
+                _ => {}
+            }
+        }
+        Ok(None)
+    }
+
+    fn socket(path: &str) -> AppResult<Self> {
+        #[cfg(unix)]
+        {
+            Ok(Self::Unix(PathBuf::from(path)))
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = path;
+            Err(AppError::invalid_input("MCP sockets are only supported on Unix, use --mcp instead"))
+        }
+    }
+}
+
+/// Serves Synapse's sessions and providers to MCP clients
+#[derive(Debug)]
+pub struct McpServer {
+    /// Sessions and chat history
+    chat_manager: Arc<ChatManager>,
+    /// Provider configuration and API keys
+    settings_manager: Arc<SettingsManager>,
+}
+
+impl McpServer {
+    /// Creates a server
+    pub fn new(chat_manager: Arc<ChatManager>, settings_manager: Arc<SettingsManager>) -> Self {
+        Self { chat_manager, settings_manager }
+    }
+
+    /// Serves clients until the transport closes
+    ///
+    /// Over stdio this is one client; sockets accept clients until the
+    /// process ends, serving each on its own task.
+    ///
+    /// # Errors
+    /// Returns an error if the transport cannot be opened or read, or
+    /// something other than a socket exists at the socket path
+    pub async fn listen(self: Arc<Self>, transport: ServerTransport) -> AppResult<()> {
+        match transport {
+            ServerTransport::Stdio => self.serve(tokio::io::stdin(), tokio::io::stdout()).await,
+            #[cfg(unix)]
+            ServerTransport::Unix(path) => {
+                // A socket file left behind by an earlier run blocks binding,
+                // but anything else at the path is the user's
+                match tokio::fs::symlink_metadata(&path).await {
+                    Ok(metadata) if metadata.file_type().is_socket() => tokio::fs::remove_file(&path).await?,
+                    Ok(_) => {
+                        return Err(AppError::invalid_input(format!(
+                            "{} exists and is not a socket",
+                            path.display()
+                        )));
+                    }
+                    Err(_) => {}
+                }
+                let listener = bind_private(&path)?;
+                info!("MCP server listening on {}", path.display());
+                loop {
+                    let (stream, _) = listener.accept().await?;
+                    let server = self.clone();
+                    tokio::spawn(async move {
+                        let (reader, writer) = stream.into_split();
+                        if let Err(e) = server.serve(reader, writer).await {
+                            warn!("MCP connection failed: {}", e);
+                        }
+                    });
+                }
+            }
+        }
+    }
+
+    /// Answers the messages of one client, one line of JSON each
+    ///
+    /// # Errors
+    /// Returns an error if the stream cannot be read or written
+    pub async fn serve<R, W>(&self, reader: R, mut writer: W) -> AppResult<()>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let mut lines = BufReader::new(reader).lines();
+        while let Some(line) = lines.next_line().await? {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let response = match serde_json::from_str::<RpcMessage>(&line) {
+                Ok(message) => self.handle(message).await,
+                Err(e) => Some(RpcMessage::error(Value::Null, PARSE_ERROR, format!("Invalid message: {}", e))),
+            };
+            if let Some(response) = response {
+                let mut line = serde_json::to_vec(&response)?;
+                line.push(b'\n');
+                writer.write_all(&line).await?;
+                writer.flush().await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Answers one message
+    ///
+    /// # Returns
+    /// The response to a request, or `None` for notifications and responses
+    pub async fn handle(&self, message: RpcMessage) -> Option<RpcMessage> {
+        let (Some(id), Some(method)) = (message.id, message.method) else {
+            return None;
+        };
+        let params = message.params.unwrap_or(Value::Null);
+
+        let result = match method.as_str() {
+            "initialize" => json!({
+                "protocolVersion": PROTOCOL_VERSION,
+                "capabilities": { "tools": {} },
+                "serverInfo": Implementation::synapse(),
+                "instructions": INSTRUCTIONS,
+            }),
+            "ping" => json!({}),
+            "tools/list" => json!({ "tools": tools() }),
+            "tools/call" => {
+                let Some(name) = params.get("name").and_then(Value::as_str) else {
+                    return Some(RpcMessage::error(id, INVALID_PARAMS, "Missing tool name"));
+                };
+                let arguments = params.get("arguments").cloned().unwrap_or_else(|| json!({}));
+                let result = match self.call_tool(name, &arguments).await {
+                    Ok(text) => CallToolResult::text(text, false),
+                    Err(e) => CallToolResult::text(e.to_string(), true),
+                };
+                serde_json::to_value(result).unwrap_or_default()
+            }
+            _ => return Some(RpcMessage::error(id, METHOD_NOT_FOUND, format!("Unknown method: {}", method))),
+        };
+        Some(RpcMessage::response(id, result))
+    }
+
+    async fn call_tool(&self, name: &str, arguments: &Value) -> AppResult<String> {
+        match name {
+            "ask_model" => self.ask_model(arguments).await,
+            "search_chat_history" => self.search_chat_history(arguments).await,
+            "list_sessions" => self.list_sessions(arguments).await,
+            "read_session" => {
+                let session_id = string_argument(arguments, "session_id")?
+                    .ok_or_else(|| AppError::invalid_input("Missing argument: session_id"))?;
+                self.chat_manager.export_session(&session_id, ExportFormat::Markdown).await
+            }
+            _ => Err(AppError::not_found(format!("Unknown tool: {}", name))),
+        }
+    }
+
+    /// Sends a one-off prompt to a configured provider
+    async fn ask_model(&self, arguments: &Value) -> AppResult<String> {
+        let prompt = string_argument(arguments, "prompt")?
+            .ok_or_else(|| AppError::invalid_input("Missing argument: prompt"))?;
+        let settings = self.settings_manager
+            .get_settings()
+            .await
+            .map_err(|e| AppError::internal(e.to_string()))?;
+        let provider_id = string_argument(arguments, "provider")?
+            .or_else(|| settings.ai_providers.active_provider.clone())
+            .ok_or_else(|| AppError::invalid_input("No AI provider selected"))?;
+
+        let api_key = self.settings_manager
+            .find_api_key(&provider_id)
+            .await
+            .map_err(|e| AppError::internal(e.to_string()))?;
+        let (provider, mut params) = ProviderRegistry::global()
+            .create_configured(&provider_id, api_key, &settings.ai_providers)
+            .await?;
+        if let Some(model) = string_argument(arguments, "model")? {
+            params.model = model;
+        }
+        params.system_prompt = string_argument(arguments, "system_prompt")?;
+
+        let completion = provider
+            .create_chat_completion(vec![Message::new(Role::User, prompt)], params)
+            .await?;
+        Ok(completion.message.content.text())
+    }
+
+    async fn search_chat_history(&self, arguments: &Value) -> AppResult<String> {
+        let text = string_argument(arguments, "query")?
+            .ok_or_else(|| AppError::invalid_input("Missing argument: query"))?;
+        let query = SearchQuery {
+            text: text.clone(),
+            limit: Some(limit_argument(arguments, DEFAULT_RESULT_LIMIT)),
+            ..SearchQuery::default()
+        };
+
+        let results = self.chat_manager.search(&query).await?;
+        if results.is_empty() {
+            return Ok(format!("No messages match \"{}\"", text));
+        }
+        let lines: Vec<String> = results
+            .iter()
+            .map(|result| {
+                let snippet: String = result.snippet.iter().map(|segment| segment.text.as_str()).collect();
+                let role = result.role.map(|role| role.to_string()).unwrap_or_else(|| "title".to_string());
+                format!("[{}] {} ({}): {}", result.session_id, result.session_title, role, snippet)
+            })
+            .collect();
+        Ok(lines.join("\n"))
+    }
+
+    async fn list_sessions(&self, arguments: &Value) -> AppResult<String> {
+        let sessions = self.chat_manager.list_sessions().await?;
+        if sessions.is_empty() {
+            return Ok("There are no saved sessions".to_string());
+        }
+        let lines: Vec<String> = sessions
+            .iter()
+            .take(limit_argument(arguments, DEFAULT_SESSION_LIMIT))
+            .map(|session| {
+                format!(
+                    "[{}] {} ({} messages, updated {})",
+                    session.id,
+                    session.title,
+                    session.message_count,
+                    session.updated_at.format("%Y-%m-%d %H:%M")
+                )
+            })
+            .collect();
+        Ok(lines.join("\n"))
+    }
+}
+
+/// Reads an optional string argument
+///
+/// # Errors
+/// Returns an error if the argument is present but not a string
+fn string_argument(arguments: &Value, name: &str) -> AppResult<Option<String>> {
+    match arguments.get(name) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(value)) if value.trim().is_empty() => Ok(None),
+        Some(Value::String(value)) => Ok(Some(value.clone())),
+        Some(_) => Err(AppError::invalid_input(format!("{} must be a string", name))),
+    }
+}
+
+/// Reads the optional `limit` argument
+fn limit_argument(arguments: &Value, default: usize) -> usize {
+    arguments
+        .get("limit")
+        .and_then(Value::as_u64)
+        .and_then(|limit| usize::try_from(limit).ok())
+        .filter(|limit| *limit > 0)
+        .unwrap_or(default)
+}
+
+/// The tools the server offers
+pub fn tools() -> Vec<McpTool> {
+    let tool = |name: &str, description: &str, input_schema: Value| McpTool {
+        name: name.to_string(),
+        description: Some(description.to_string()),
+        input_schema,
+        annotations: None,
+    };
+    vec![
+        tool(
+            "ask_model",
+            "Asks one of the AI models configured in Synapse a question and returns its answer.",
+            json!({
+                "type": "object",
+                "properties": {
+                    "prompt": { "type": "string", "description": "The question or task" },
+                    "provider": { "type": "string", "description": "Id of a configured provider; defaults to the active one" },
+                    "model": { "type": "string", "description": "Model to use; defaults to the provider's configured model" },
+                    "system_prompt": { "type": "string", "description": "Instructions for the model" }
+                },
+                "required": ["prompt"]
+            }),
+        ),
+        tool(
+            "search_chat_history",
+            "Searches the messages of all saved Synapse sessions and returns matching excerpts.",
+            json!({
+                "type": "object",
+                "properties": {
+                    "query": { "type": "string", "description": "Words to search for" },
+                    "limit": { "type": "integer", "description": "Maximum number of results" }
+                },
+                "required": ["query"]
+            }),
+        ),
+        tool(
+            "list_sessions",
+            "Lists saved Synapse sessions, most recently updated first.",
+            json!({
+                "type": "object",
+                "properties": {
+                    "limit": { "type": "integer", "description": "Maximum number of sessions" }
+                }
+            }),
+        ),
+        tool(
+            "read_session",
+            "Returns the conversation of a saved Synapse session as Markdown.",
+            json!({
+                "type": "object",
+                "properties": {
+                    "session_id": { "type": "string", "description": "Id of the session" }
+                },
+                "required": ["session_id"]
+            }),
+        ),
+    ]
+}
+
+/// Creates a Unix socket only the user can connect to
+///
+/// The socket is bound inside a fresh directory only the user can enter,
+/// restricted to mode 0600 and only then moved to `path`, so no other user
+/// can connect in between.
+#[cfg(unix)]
+fn bind_private(path: &std::path::Path) -> AppResult<tokio::net::UnixListener> {
+    use std::os::unix::fs::DirBuilderExt;
+
+    let parent = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(std::path::Path::new("."));
+    let staging = parent.join(format!(".synapse-{}", uuid::Uuid::new_v4()));
+    std::fs::DirBuilder::new().mode(0o700).create(&staging)?;
+
+    let staged = staging.join("socket");
+    let bound = (|| {
+        let listener = tokio::net::UnixListener::bind(&staged)?;
+        std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(0o600))?;
+        std::fs::rename(&staged, path)?;
+        Ok(listener)
+    })();
+    let _ = std::fs::remove_file(&staged);
+    let _ = std::fs::remove_dir(&staging);
+    bound
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::chat::ChatStore;
+
+    #[test]
+    fn test_transport_from_args() {
+        let args = |args: &[&str]| ServerTransport::from_args(args.iter().map(|arg| arg.to_string()));
+        assert_eq!(args(&[]).unwrap(), None);
+        assert_eq!(args(&["--mcp"]).unwrap(), Some(ServerTransport::Stdio));
+        #[cfg(unix)]
+        assert_eq!(
+            args(&["--mcp-socket", "/tmp/synapse.sock"]).unwrap(),
+            Some(ServerTransport::Unix(PathBuf::from("/tmp/synapse.sock")))
+        );
+        assert!(args(&["--mcp-socket"]).is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_socket_is_private_and_replaces_only_sockets() {
+        let dir = tempfile::tempdir().unwrap();
+        let chat_manager = Arc::new(ChatManager::new(ChatStore::open_in_memory().unwrap()));
+        let settings_manager = Arc::new(SettingsManager::new().await.unwrap());
+        let server = Arc::new(McpServer::new(chat_manager, settings_manager));
+
+        // A mistyped path must not delete the user's file
+        let notes = dir.path().join("notes.md");
+        std::fs::write(&notes, "keep me").unwrap();
+        assert!(server.clone().listen(ServerTransport::Unix(notes.clone())).await.is_err());
+        assert_eq!(std::fs::read_to_string(&notes).unwrap(), "keep me");
+
+        // A stale socket is replaced by one only the user can connect to
+        let socket = dir.path().join("synapse.sock");
+        drop(std::os::unix::net::UnixListener::bind(&socket).unwrap());
+        let listening = tokio::spawn(server.listen(ServerTransport::Unix(socket.clone())));
+        let mut connected = false;
+        for _ in 0..50 {
+            if tokio::net::UnixStream::connect(&socket).await.is_ok() {
+                connected = true;
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        listening.abort();
+        assert!(connected);
+        let mode = std::fs::metadata(&socket).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        let mut entries: Vec<_> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        entries.sort();
+        assert_eq!(entries, ["notes.md", "synapse.sock"]);
+    }
+
+    #[tokio::test]
+    async fn test_server_answers_requests() {
+        let chat_manager = Arc::new(ChatManager::new(ChatStore::open_in_memory().unwrap()));
+        let session = chat_manager.create_session("Borrowing".to_string()).await.unwrap();
+        chat_manager
+            .add_message(&session.id, Message::new(Role::User, "How do lifetimes work?"))
+            .await
+            .unwrap();
+        let settings_manager = Arc::new(SettingsManager::new().await.unwrap());
+        let server = McpServer::new(chat_manager, settings_manager);
+
+        let requests = [
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} }),
+            json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }),
+            json!({ "jsonrpc": "2.0", "id": 2, "method": "tools/list" }),
+            json!({ "jsonrpc": "2.0", "id": 3, "method": "tools/call",
+                    "params": { "name": "search_chat_history", "arguments": { "query": "lifetimes" } } }),
+            json!({ "jsonrpc": "2.0", "id": 4, "method": "tools/call",
+                    "params": { "name": "list_sessions", "arguments": {} } }),
+            json!({ "jsonrpc": "2.0", "id": 5, "method": "tools/call",
+                    "params": { "name": "read_session", "arguments": {} } }),
+            json!({ "jsonrpc": "2.0", "id": 6, "method": "resources/list" }),
+        ];
+        let input: String = requests.iter().map(|request| format!("{}\n", request)).collect();
+        let mut output = Vec::new();
+        server.serve(input.as_bytes(), &mut output).await.unwrap();
+
+        let responses: Vec<RpcMessage> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        // The notification gets no response
+        assert_eq!(responses.len(), 6);
+
+        let result = |index: usize| responses[index].result.clone().unwrap();
+        assert_eq!(result(0)["serverInfo"]["name"], "synapse");
+        assert_eq!(result(1)["tools"].as_array().unwrap().len(), 4);
+
+        let found: CallToolResult = serde_json::from_value(result(2)).unwrap();
+        assert!(!found.is_error);
+        assert!(found.to_text().contains("How do lifetimes work?"));
+
+        let listed: CallToolResult = serde_json::from_value(result(3)).unwrap();
+        assert!(listed.to_text().contains(&format!("[{}] Borrowing (1 messages", session.id)));
+
+        let missing: CallToolResult = serde_json::from_value(result(4)).unwrap();
+        assert!(missing.is_error);
+        assert_eq!(responses[5].error.as_ref().unwrap().code, METHOD_NOT_FOUND);
+    }
+}
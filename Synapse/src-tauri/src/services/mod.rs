@@ -3,8 +3,16 @@
 //! This module contains core application services:
 //! - AI providers and chat completion
 //! - Chat session management
+//! - Indexing local documents for chats to draw on
+//! - Built-in tools models can call
+//! - Model Context Protocol servers, and serving Synapse over MCP
 
 pub mod ai;
 pub mod chat;
+pub mod documents;
+pub mod mcp;
+pub mod tools;
 
-pub use chat::ChatManager; 
\ No newline at end of file
+pub use chat::ChatManager;
+pub use documents::{DocumentIndex, DocumentWatcher};
+pub use mcp::{McpManager, McpServer}; 
\ No newline at end of file
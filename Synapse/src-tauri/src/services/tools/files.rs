@@ -0,0 +1,391 @@
+//! File tools
+//!
+//! This module contains tools that let a model read files, list directories
+//! and search file names and contents. Paths inside the folders the user
+//! approved are accessed freely; anything else needs the user's approval for
+//! the directory involved. Relative paths are taken relative to the first
+//! approved folder. None of these tools change files.
+
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use tokio::io::AsyncReadExt;
+
+use crate::services::ai::{Approval, Tool, ToolPermissions};
+use crate::utils::{AppError, AppResult};
+
+/// Bytes of a file returned by `read_file`
+const MAX_READ_BYTES: u64 = 256 * 1024;
+
+/// Entries returned by `list_directory`
+const MAX_ENTRIES: usize = 500;
+
+/// Matches returned by `search_files`
+const MAX_MATCHES: usize = 100;
+
+/// Files looked at by one search
+const MAX_SEARCHED_FILES: usize = 10_000;
+
+/// Files larger than this are matched by name only
+const MAX_SEARCH_FILE_SIZE: u64 = 1024 * 1024;
+
+/// Characters of a matching line included in search results
+const MAX_LINE_CHARS: usize = 200;
+
+/// Directories searches do not descend into
+const SKIPPED_DIRS: &[&str] = &[".git", "node_modules", "target"];
+
+/// Reads a required string argument
+fn string_argument<'a>(arguments: &'a Value, name: &str) -> AppResult<&'a str> {
+    arguments
+        .get(name)
+        .and_then(Value::as_str)
+        .filter(|value| !value.trim().is_empty())
+        .ok_or_else(|| AppError::invalid_input(format!("Missing argument: {}", name)))
+}
+
+/// Resolves the `path` argument to an existing, canonical path
+///
+/// # Errors
+/// Returns an error if the argument is missing, a relative path is given
+/// without an approved folder, or the path does not exist
+fn resolve_path(permissions: &ToolPermissions, arguments: &Value) -> AppResult<PathBuf> {
+    let path = Path::new(string_argument(arguments, "path")?);
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        permissions
+            .roots()
+            .first()
+            .map(|root| root.join(path))
+            .ok_or_else(|| AppError::invalid_input("Relative paths need an approved folder"))?
+    };
+    path.canonicalize().map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => AppError::not_found(format!("{} does not exist", path.display())),
+        _ => e.into(),
+    })
+}
+
+/// The approval needed for a path outside the approved folders
+///
+/// Approval is asked per directory, so always allowing an action covers
+/// the other files of that directory as well. Paths that cannot be
+/// resolved need no approval, since the call fails anyway.
+fn outside_roots(permissions: &ToolPermissions, tool: &str, arguments: &Value, action: &str) -> Option<Approval> {
+    let path = resolve_path(permissions, arguments).ok()?;
+    if permissions.is_within_roots(&path) {
+        return None;
+    }
+    let directory = if path.is_dir() {
+        path.as_path()
+    } else {
+        path.parent().unwrap_or(&path)
+    };
+    Some(Approval {
+        key: format!("{}:{}", tool, directory.display()),
+        description: format!("{} {}, which is outside the approved folders", action, path.display()),
+    })
+}
+
+/// JSON schema of a tool taking a path, plus any further properties
+fn path_parameters(path: &str, mut properties: serde_json::Map<String, Value>) -> Value {
+    properties.insert("path".to_string(), json!({ "type": "string", "description": path }));
+    let required: Vec<&String> = properties.keys().collect();
+    json!({ "type": "object", "properties": properties, "required": required })
+}
+
+/// Reads a text file
+#[derive(Debug, Clone)]
+pub struct ReadFileTool {
+    /// Approved folders and decisions
+    permissions: ToolPermissions,
+}
+
+impl ReadFileTool {
+    /// Creates the tool
+    pub fn new(permissions: ToolPermissions) -> Self {
+        Self { permissions }
+    }
+}
+
+#[async_trait]
+impl Tool for ReadFileTool {
+    fn name(&self) -> &str {
+        "read_file"
+    }
+
+    fn description(&self) -> &str {
+        "Reads a text file from the user's computer. Relative paths are resolved against \
+         the user's first approved folder. Large files are truncated."
+    }
+
+    fn parameters(&self) -> Value {
+        path_parameters("Path of the file to read", serde_json::Map::new())
+    }
+
+    async fn execute(&self, arguments: Value) -> AppResult<String> {
+        let path = resolve_path(&self.permissions, &arguments)?;
+        let size = tokio::fs::metadata(&path).await?.len();
+        if !path.is_file() {
+            return Err(AppError::invalid_input(format!("{} is not a file", path.display())));
+        }
+
+        let mut data = Vec::new();
+        tokio::fs::File::open(&path)
+            .await?
+            .take(MAX_READ_BYTES)
+            .read_to_end(&mut data)
+            .await?;
+        if data.contains(&0) {
+            return Err(AppError::invalid_input(format!("{} is not a text file", path.display())));
+        }
+
+        let mut text = String::from_utf8_lossy(&data).into_owned();
+        if size > MAX_READ_BYTES {
+            text.push_str(&format!("\n[Truncated: showing {} of {} bytes]", MAX_READ_BYTES, size));
+        }
+        Ok(text)
+    }
+
+    fn approval(&self, arguments: &Value) -> Option<Approval> {
+        outside_roots(&self.permissions, self.name(), arguments, "Read")
+    }
+}
+
+/// Lists the entries of a directory
+#[derive(Debug, Clone)]
+pub struct ListDirectoryTool {
+    /// Approved folders and decisions
+    permissions: ToolPermissions,
+}
+
+impl ListDirectoryTool {
+    /// Creates the tool
+    pub fn new(permissions: ToolPermissions) -> Self {
+        Self { permissions }
+    }
+}
+
+#[async_trait]
+impl Tool for ListDirectoryTool {
+    fn name(&self) -> &str {
+        "list_directory"
+    }
+
+    fn description(&self) -> &str {
+        "Lists the files and subdirectories of a directory on the user's computer. \
+         Directories end with a slash. Use \".\" for the user's first approved folder."
+    }
+
+    fn parameters(&self) -> Value {
+        path_parameters("Path of the directory to list", serde_json::Map::new())
+    }
+
+    async fn execute(&self, arguments: Value) -> AppResult<String> {
+        let path = resolve_path(&self.permissions, &arguments)?;
+        if !path.is_dir() {
+            return Err(AppError::invalid_input(format!("{} is not a directory", path.display())));
+        }
+
+        let mut entries = tokio::fs::read_dir(&path).await?;
+        let mut lines = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let metadata = entry.metadata().await?;
+            lines.push(if metadata.is_dir() {
+                format!("{}/", name)
+            } else {
+                format!("{} ({} bytes)", name, metadata.len())
+            });
+        }
+        if lines.is_empty() {
+            return Ok(format!("{} is empty", path.display()));
+        }
+
+        lines.sort();
+        let total = lines.len();
+        lines.truncate(MAX_ENTRIES);
+        if total > MAX_ENTRIES {
+            lines.push(format!("[{} more entries not shown]", total - MAX_ENTRIES));
+        }
+        Ok(lines.join("\n"))
+    }
+
+    fn approval(&self, arguments: &Value) -> Option<Approval> {
+        outside_roots(&self.permissions, self.name(), arguments, "List")
+    }
+}
+
+/// Searches file names and contents below a directory
+#[derive(Debug, Clone)]
+pub struct SearchFilesTool {
+    /// Approved folders and decisions
+    permissions: ToolPermissions,
+}
+
+impl SearchFilesTool {
+    /// Creates the tool
+    pub fn new(permissions: ToolPermissions) -> Self {
+        Self { permissions }
+    }
+}
+
+#[async_trait]
+impl Tool for SearchFilesTool {
+    fn name(&self) -> &str {
+        "search_files"
+    }
+
+    fn description(&self) -> &str {
+        "Searches the names and text contents of all files below a directory on the user's \
+         computer, ignoring case. Returns matching paths and lines as path:line: text. \
+         Use \".\" for the user's first approved folder."
+    }
+
+    fn parameters(&self) -> Value {
+        let mut properties = serde_json::Map::new();
+        properties.insert(
+            "query".to_string(),
+            json!({ "type": "string", "description": "Text to look for" }),
+        );
+        path_parameters("Directory to search", properties)
+    }
+
+    async fn execute(&self, arguments: Value) -> AppResult<String> {
+        let root = resolve_path(&self.permissions, &arguments)?;
+        if !root.is_dir() {
+            return Err(AppError::invalid_input(format!("{} is not a directory", root.display())));
+        }
+        let query = string_argument(&arguments, "query")?.to_lowercase();
+
+        tokio::task::spawn_blocking(move || search(&root, &query))
+            .await
+            .map_err(|e| AppError::internal(format!("Search failed: {}", e)))
+    }
+
+    fn approval(&self, arguments: &Value) -> Option<Approval> {
+        outside_roots(&self.permissions, self.name(), arguments, "Search")
+    }
+}
+
+/// Walks `root` breadth first, collecting paths and lines containing `query`
+///
+/// Symbolic links are not followed, so the search stays below `root`.
+fn search(root: &Path, query: &str) -> String {
+    let mut matches = Vec::new();
+    let mut searched = 0;
+    let mut directories = VecDeque::from([root.to_path_buf()]);
+
+    'walk: while let Some(directory) = directories.pop_front() {
+        let Ok(entries) = std::fs::read_dir(&directory) else {
+            continue;
+        };
+        let mut entries: Vec<_> = entries.filter_map(Result::ok).collect();
+        entries.sort_by_key(|entry| entry.file_name());
+
+        for entry in entries {
+            if matches.len() >= MAX_MATCHES || searched >= MAX_SEARCHED_FILES {
+                break 'walk;
+            }
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let relative = path.strip_prefix(root).unwrap_or(&path).display().to_string();
+
+            if file_type.is_dir() {
+                if name.to_lowercase().contains(query) {
+                    matches.push(format!("{}/", relative));
+                }
+                if !SKIPPED_DIRS.contains(&name.as_str()) {
+                    directories.push_back(path);
+                }
+            } else if file_type.is_file() {
+                searched += 1;
+                if name.to_lowercase().contains(query) {
+                    matches.push(relative.clone());
+                }
+                search_file(&path, &relative, query, &mut matches);
+            }
+        }
+    }
+
+    if matches.is_empty() {
+        return format!("No files or lines contain \"{}\"", query);
+    }
+    let mut output = matches.join("\n");
+    if matches.len() >= MAX_MATCHES {
+        output.push_str(&format!("\n[Stopped after {} matches]", MAX_MATCHES));
+    } else if searched >= MAX_SEARCHED_FILES {
+        output.push_str(&format!("\n[Stopped after {} files]", MAX_SEARCHED_FILES));
+    }
+    output
+}
+
+/// Adds the lines of a text file containing `query` to `matches`
+fn search_file(path: &Path, relative: &str, query: &str, matches: &mut Vec<String>) {
+    let too_large = std::fs::metadata(path).map_or(true, |metadata| metadata.len() > MAX_SEARCH_FILE_SIZE);
+    if too_large {
+        return;
+    }
+    let Ok(data) = std::fs::read(path) else {
+        return;
+    };
+    if data.contains(&0) {
+        return;
+    }
+    let Ok(text) = std::str::from_utf8(&data) else {
+        return;
+    };
+
+    for (index, line) in text.lines().enumerate() {
+        if matches.len() >= MAX_MATCHES {
+            return;
+        }
+        if line.to_lowercase().contains(query) {
+            let line: String = line.trim().chars().take(MAX_LINE_CHARS).collect();
+            matches.push(format!("{}:{}: {}", relative, index + 1, line));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_file_tools_stay_within_approved_folders() {
+        let approved = tempfile::tempdir().unwrap();
+        let elsewhere = tempfile::tempdir().unwrap();
+        std::fs::create_dir(approved.path().join("src")).unwrap();
+        std::fs::write(approved.path().join("src/main.rs"), "fn main() {\n    println!(\"Hello\");\n}\n").unwrap();
+        std::fs::write(elsewhere.path().join("secret.txt"), "hunter2").unwrap();
+
+        let permissions = ToolPermissions::new();
+        permissions.set_roots(vec![approved.path().to_path_buf()]);
+        let read = ReadFileTool::new(permissions.clone());
+        let list = ListDirectoryTool::new(permissions.clone());
+        let search = SearchFilesTool::new(permissions.clone());
+
+        let arguments = json!({ "path": "src/main.rs" });
+        assert!(read.approval(&arguments).is_none());
+        assert!(read.execute(arguments).await.unwrap().contains("println!"));
+        assert_eq!(list.execute(json!({ "path": "." })).await.unwrap(), "src/");
+
+        let found = search.execute(json!({ "path": ".", "query": "HELLO" })).await.unwrap();
+        assert_eq!(found, format!("{}:2: println!(\"Hello\");", Path::new("src").join("main.rs").display()));
+
+        // Escaping the folder, directly or through "..", needs approval
+        let secret = elsewhere.path().join("secret.txt");
+        let approval = read.approval(&json!({ "path": secret })).unwrap();
+        assert!(approval.key.starts_with("read_file:"));
+        let escape = format!("../{}", elsewhere.path().file_name().unwrap().to_string_lossy());
+        assert!(list.approval(&json!({ "path": escape })).is_some());
+
+        assert!(read.execute(json!({ "path": "missing.txt" })).await.is_err());
+        assert!(read.execute(json!({})).await.is_err());
+    }
+}
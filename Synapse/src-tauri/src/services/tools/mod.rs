@@ -0,0 +1,30 @@
+//! Built-in tools
+//!
+//! This module contains the tools Synapse offers models out of the box.
+//! They share the registry's permissions, so what they may do without
+//! asking follows the folders and decisions the user approved. The shell
+//! command tool lives in `services::ai::shell`.
+
+use std::sync::Arc;
+
+use crate::services::ai::{ShellTool, ToolRegistry};
+use crate::settings::ToolSettings;
+
+pub mod files;
+
+pub use files::{ListDirectoryTool, ReadFileTool, SearchFilesTool};
+
+/// Registers the built-in tools with a registry, configured by `settings`
+///
+/// Calling it again applies changed settings, replacing the registered
+/// tools and the approved folders and decisions.
+pub fn register_builtin(registry: &ToolRegistry, settings: &ToolSettings) {
+    let permissions = registry.permissions();
+    permissions.set_roots(settings.allowed_roots.clone());
+    permissions.set_always_allowed(settings.always_allow.iter().cloned());
+
+    registry.register(Arc::new(ReadFileTool::new(permissions.clone())));
+    registry.register(Arc::new(ListDirectoryTool::new(permissions.clone())));
+    registry.register(Arc::new(SearchFilesTool::new(permissions.clone())));
+    registry.register(Arc::new(ShellTool::new(permissions.clone(), settings.shell.clone())));
+}
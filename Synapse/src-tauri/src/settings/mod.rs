@@ -1,6 +1,6 @@
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use keyring::Entry;
 
 mod error;
@@ -39,6 +39,11 @@ impl SettingsManager {
         })
     }
 
+    /// Directory holding the settings file and other application data
+    pub fn config_dir(&self) -> &Path {
+        self.file_path.parent().unwrap_or_else(|| Path::new("."))
+    }
+
     pub async fn save(&self) -> Result<(), SettingsError> {
         let settings = self.settings.read().await;
         let content = serde_json::to_string_pretty(&*settings)?;
@@ -73,6 +78,17 @@ impl SettingsManager {
         Ok(keyring.get_password()?)
     }
 
+    /// Looks up the API key of a provider, or `None` if none is stored
+    ///
+    /// Unlike a missing key, a keyring that cannot be read is an error.
+    pub async fn find_api_key(&self, provider: &str) -> Result<Option<String>, SettingsError> {
+        match self.get_api_key(provider).await {
+            Ok(key) => Ok(Some(key)),
+            Err(SettingsError::Keyring(keyring::Error::NoEntry)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
     pub async fn delete_api_key(&self, provider: &str) -> Result<(), SettingsError> {
         let keyring = Entry::new("synapse", provider)?;
         keyring.delete_password()?;
@@ -105,4 +121,40 @@ mod tests {
         let updated_settings = manager.get_settings().await.unwrap();
         assert_eq!(updated_settings.preferences.window_width, 1000);
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn test_legacy_provider_settings_migrate() {
+        let stored = serde_json::json!({
+            "openai": { "model": "gpt-4o", "temperature": 0.3, "max_tokens": 512 },
+            "ollama": {
+                "base_url": "http://gpu-box:11434",
+                "model": "llama3.2",
+                "temperature": 0.7,
+                "max_tokens": 1024
+            },
+            "openai_compatible": [{
+                "id": "vllm",
+                "name": "vLLM",
+                "base_url": "http://localhost:8000/v1",
+                "model": "mistral-7b",
+                "temperature": 0.5,
+                "max_tokens": 256
+            }]
+        });
+
+        let settings: AIProviderSettings = serde_json::from_value(stored).unwrap();
+        assert!(settings.validate().is_ok());
+
+        let openai = settings.provider("openai").unwrap();
+        assert_eq!(openai.kind, "openai");
+        assert_eq!(openai.model, "gpt-4o");
+
+        let ollama = settings.provider("ollama").unwrap();
+        assert_eq!(ollama.option_str("base_url"), Some("http://gpu-box:11434"));
+
+        let vllm = settings.provider("vllm").unwrap();
+        assert_eq!(vllm.kind, "openai-compatible");
+        assert_eq!(vllm.name.as_deref(), Some("vLLM"));
+        assert_eq!(vllm.option_str("base_url"), Some("http://localhost:8000/v1"));
+    }
+}
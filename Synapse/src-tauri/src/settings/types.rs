@@ -1,10 +1,22 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+use crate::services::ai::ProviderRegistry;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Settings {
     pub preferences: AppPreferences,
     pub ai_providers: AIProviderSettings,
+    /// What tools may do without asking
+    #[serde(default)]
+    pub tools: ToolSettings,
+    /// MCP servers whose tools models can call
+    #[serde(default)]
+    pub mcp_servers: Vec<McpServerConfig>,
+    /// Folders indexed for chats to draw on
+    #[serde(default)]
+    pub documents: DocumentSettings,
 }
 
 impl Default for Settings {
@@ -12,6 +24,9 @@ impl Default for Settings {
         Self {
             preferences: AppPreferences::default(),
             ai_providers: AIProviderSettings::default(),
+            tools: ToolSettings::default(),
+            mcp_servers: Vec::new(),
+            documents: DocumentSettings::default(),
         }
     }
 }
@@ -37,33 +52,274 @@ impl Default for AppPreferences {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(from = "StoredAIProviderSettings")]
 pub struct AIProviderSettings {
-    pub openai: Option<OpenAIConfig>,
-    pub anthropic: Option<AnthropicConfig>,
+    /// Id of the provider used for new conversations
+    pub active_provider: Option<String>,
+    /// Configured providers, each resolved through the provider registry
+    pub providers: Vec<ProviderConfig>,
+    /// Cheaper model of the active provider used to title sessions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title_model: Option<String>,
 }
 
-impl Default for AIProviderSettings {
-    fn default() -> Self {
-        Self {
-            openai: None,
-            anthropic: None,
-        }
+impl AIProviderSettings {
+    /// Finds a configured provider by its id
+    pub fn provider(&self, id: &str) -> Option<&ProviderConfig> {
+        self.providers.iter().find(|config| config.id == id)
     }
 }
 
+/// Configuration of one provider instance
+///
+/// The `id` selects the provider and is the key under which its API key is
+/// stored. Built-in providers use their registry kind as id, while kinds
+/// that allow multiple instances (such as OpenAI-compatible endpoints) use
+/// a user-defined id.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct OpenAIConfig {
+pub struct ProviderConfig {
+    pub id: String,
+    pub kind: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
     pub model: String,
     pub temperature: f32,
     pub max_tokens: u32,
+    /// Provider-specific options, described by the registry's config schema
+    #[serde(default)]
+    pub options: serde_json::Map<String, serde_json::Value>,
 }
 
+impl ProviderConfig {
+    /// Creates a configuration with default parameters
+    pub fn new<I: Into<String>, K: Into<String>>(id: I, kind: K) -> Self {
+        Self {
+            id: id.into(),
+            kind: kind.into(),
+            name: None,
+            model: String::new(),
+            temperature: 0.7,
+            max_tokens: 2048,
+            options: serde_json::Map::new(),
+        }
+    }
+
+    /// Reads a string option
+    pub fn option_str(&self, key: &str) -> Option<&str> {
+        self.options.get(key).and_then(|value| value.as_str())
+    }
+
+    /// Reads a string list option, returning an empty list if unset
+    pub fn option_list(&self, key: &str) -> Vec<String> {
+        self.options
+            .get(key)
+            .and_then(|value| value.as_array())
+            .map(|items| items.iter().filter_map(|item| item.as_str().map(str::to_string)).collect())
+            .unwrap_or_default()
+    }
+
+    /// Reads a string map option, returning an empty map if unset
+    pub fn option_map(&self, key: &str) -> HashMap<String, String> {
+        self.options
+            .get(key)
+            .and_then(|value| value.as_object())
+            .map(|entries| {
+                entries
+                    .iter()
+                    .filter_map(|(name, value)| Some((name.clone(), value.as_str()?.to_string())))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Provider settings as stored on disk, including the per-provider fields
+/// written by earlier versions, which are folded into `providers` on load
+#[derive(Deserialize)]
+struct StoredAIProviderSettings {
+    #[serde(default)]
+    active_provider: Option<String>,
+    #[serde(default)]
+    providers: Vec<ProviderConfig>,
+    #[serde(default)]
+    title_model: Option<String>,
+    #[serde(default)]
+    openai: Option<LegacyProviderConfig>,
+    #[serde(default)]
+    anthropic: Option<LegacyProviderConfig>,
+    #[serde(default)]
+    ollama: Option<LegacyProviderConfig>,
+    #[serde(default)]
+    openai_compatible: Vec<LegacyCompatibleConfig>,
+}
+
+#[derive(Deserialize)]
+struct LegacyProviderConfig {
+    model: String,
+    temperature: f32,
+    max_tokens: u32,
+    #[serde(default)]
+    base_url: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct LegacyCompatibleConfig {
+    id: String,
+    name: String,
+    base_url: String,
+    #[serde(default)]
+    headers: HashMap<String, String>,
+    #[serde(default)]
+    models: Vec<String>,
+    model: String,
+    temperature: f32,
+    max_tokens: u32,
+}
+
+impl From<StoredAIProviderSettings> for AIProviderSettings {
+    fn from(stored: StoredAIProviderSettings) -> Self {
+        let mut providers = stored.providers;
+        let mut migrate = |kind: &str, legacy: LegacyProviderConfig| {
+            if providers.iter().any(|config| config.id == kind) {
+                return;
+            }
+            let mut config = ProviderConfig::new(kind, kind);
+            config.model = legacy.model;
+            config.temperature = legacy.temperature;
+            config.max_tokens = legacy.max_tokens;
+            if let Some(base_url) = legacy.base_url {
+                config.options.insert("base_url".to_string(), base_url.into());
+            }
+            providers.push(config);
+        };
+
+        if let Some(legacy) = stored.openai {
+            migrate("openai", legacy);
+        }
+        if let Some(legacy) = stored.anthropic {
+            migrate("anthropic", legacy);
+        }
+        if let Some(legacy) = stored.ollama {
+            migrate("ollama", legacy);
+        }
+
+        for legacy in stored.openai_compatible {
+            if providers.iter().any(|config| config.id == legacy.id) {
+                continue;
+            }
+            let mut config = ProviderConfig::new(legacy.id, "openai-compatible");
+            config.name = Some(legacy.name);
+            config.model = legacy.model;
+            config.temperature = legacy.temperature;
+            config.max_tokens = legacy.max_tokens;
+            config.options.insert("base_url".to_string(), legacy.base_url.into());
+            config.options.insert("headers".to_string(), serde_json::json!(legacy.headers));
+            config.options.insert("models".to_string(), serde_json::json!(legacy.models));
+            providers.push(config);
+        }
+
+        Self {
+            active_provider: stored.active_provider,
+            providers,
+            title_model: stored.title_model,
+        }
+    }
+}
+
+/// Permissions of the tools models can call
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ToolSettings {
+    /// Folders file tools may read without asking
+    #[serde(default)]
+    pub allowed_roots: Vec<PathBuf>,
+    /// Keys of tool actions the user chose to always allow
+    #[serde(default)]
+    pub always_allow: Vec<String>,
+    /// Configuration of the shell command tool
+    #[serde(default)]
+    pub shell: ShellSettings,
+}
+
+/// Configuration of the shell command tool
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct AnthropicConfig {
-    pub model: String,
-    pub temperature: f32,
-    pub max_tokens: u32,
+#[serde(default)]
+pub struct ShellSettings {
+    /// Directory commands run in, defaulting to the first approved folder
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub working_directory: Option<PathBuf>,
+    /// Seconds a command may run before it is stopped
+    pub timeout_secs: u64,
+    /// Bytes kept of each of a command's output streams
+    pub max_output_bytes: usize,
+    /// Commands that run without asking, matched with any further arguments
+    pub allowed_commands: Vec<String>,
+}
+
+impl Default for ShellSettings {
+    fn default() -> Self {
+        Self {
+            working_directory: None,
+            timeout_secs: 30,
+            max_output_bytes: 64 * 1024,
+            allowed_commands: Vec::new(),
+        }
+    }
+}
+
+/// A Model Context Protocol server started over stdio
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct McpServerConfig {
+    /// Identifies the server and prefixes the names of its tools
+    pub id: String,
+    /// Name shown to the user
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Program to run
+    pub command: String,
+    /// Arguments passed to the program
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Environment variables set for the program
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// Whether the server is started
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+/// Folders indexed for chats to draw on, and how passages are found
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DocumentSettings {
+    /// Whether chats are given passages from the indexed folders
+    pub enabled: bool,
+    /// Folders whose documents are indexed
+    pub folders: Vec<PathBuf>,
+    /// Provider embedding the documents, defaulting to the active provider
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provider_id: Option<String>,
+    /// Embedding model, defaulting to the provider's
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub embedding_model: Option<String>,
+    /// Passages added to a prompt
+    pub top_k: usize,
+}
+
+impl Default for DocumentSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            folders: Vec::new(),
+            provider_id: None,
+            embedding_model: None,
+            top_k: 4,
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -113,6 +369,21 @@ impl Validate for Settings {
     fn validate(&self) -> Result<(), String> {
         self.preferences.validate()?;
         self.ai_providers.validate()?;
+        self.tools.validate()?;
+        self.documents.validate()?;
+        if let Some(provider_id) = &self.documents.provider_id {
+            ProviderRegistry::global()
+                .resolve(provider_id, &self.ai_providers)
+                .map_err(|e| e.to_string())?;
+        }
+
+        let mut ids = HashSet::new();
+        for server in &self.mcp_servers {
+            server.validate()?;
+            if !ids.insert(server.id.as_str()) {
+                return Err(format!("Duplicate MCP server id: {}", server.id));
+            }
+        }
         Ok(())
     }
 }
@@ -132,15 +403,103 @@ impl Validate for AppPreferences {
 
 impl Validate for AIProviderSettings {
     fn validate(&self) -> Result<(), String> {
-        if let Some(openai) = &self.openai {
-            if openai.temperature < 0.0 || openai.temperature > 1.0 {
-                return Err("OpenAI temperature must be between 0 and 1".to_string());
+        let registry = ProviderRegistry::global();
+        let mut ids = HashSet::new();
+
+        for config in &self.providers {
+            if config.id.trim().is_empty() {
+                return Err("Provider id must not be empty".to_string());
+            }
+            if !ids.insert(config.id.as_str()) {
+                return Err(format!("Duplicate provider id: {}", config.id));
+            }
+
+            let descriptor = registry
+                .get(&config.kind)
+                .ok_or_else(|| format!("Unknown provider kind: {}", config.kind))?;
+            let label = config.name.as_deref().unwrap_or(&descriptor.display_name);
+
+            if descriptor.multiple_instances {
+                if registry.get(&config.id).is_some() {
+                    return Err(format!("Provider id is reserved: {}", config.id));
+                }
+                if !matches!(config.name.as_deref(), Some(name) if !name.trim().is_empty()) {
+                    return Err(format!("{} needs a name", descriptor.display_name));
+                }
+            } else if config.id != config.kind {
+                return Err(format!("{} must use the id {}", descriptor.display_name, config.kind));
+            }
+
+            if config.temperature < 0.0 || config.temperature > 1.0 {
+                return Err(format!("{} temperature must be between 0 and 1", label));
+            }
+            descriptor
+                .validate_options(config)
+                .map_err(|e| format!("{}: {}", label, e))?;
+        }
+
+        if let Some(active) = &self.active_provider {
+            registry.resolve(active, self).map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+impl Validate for ToolSettings {
+    fn validate(&self) -> Result<(), String> {
+        for root in &self.allowed_roots {
+            if !root.is_absolute() {
+                return Err(format!("Approved folders must be absolute paths: {}", root.display()));
             }
         }
-        if let Some(anthropic) = &self.anthropic {
-            if anthropic.temperature < 0.0 || anthropic.temperature > 1.0 {
-                return Err("Anthropic temperature must be between 0 and 1".to_string());
+        self.shell.validate()?;
+        Ok(())
+    }
+}
+
+impl Validate for ShellSettings {
+    fn validate(&self) -> Result<(), String> {
+        if let Some(directory) = &self.working_directory {
+            if !directory.is_absolute() {
+                return Err(format!("Working directory must be an absolute path: {}", directory.display()));
+            }
+        }
+        if !(1..=600).contains(&self.timeout_secs) {
+            return Err("Command timeout must be between 1 and 600 seconds".to_string());
+        }
+        if !(1024..=1024 * 1024).contains(&self.max_output_bytes) {
+            return Err("Command output limit must be between 1 KB and 1 MB".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl Validate for DocumentSettings {
+    fn validate(&self) -> Result<(), String> {
+        for folder in &self.folders {
+            if !folder.is_absolute() {
+                return Err(format!("Indexed folders must be absolute paths: {}", folder.display()));
             }
+            if let Some(outer) = self.folders.iter().find(|other| *other != folder && folder.starts_with(other)) {
+                return Err(format!(
+                    "{} is already indexed as part of {}", folder.display(), outer.display()
+                ));
+            }
+        }
+        if !(1..=20).contains(&self.top_k) {
+            return Err("Passages per prompt must be between 1 and 20".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl Validate for McpServerConfig {
+    fn validate(&self) -> Result<(), String> {
+        if self.id.is_empty() || !self.id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err(format!("MCP server ids may only contain letters, digits, - and _: {}", self.id));
+        }
+        if self.command.trim().is_empty() {
+            return Err(format!("MCP server {} needs a command", self.id));
         }
         Ok(())
     }
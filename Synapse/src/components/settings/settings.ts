@@ -1,5 +1,5 @@
 import { invoke } from '@tauri-apps/api/tauri';
-import type { Settings, Theme, StartupBehavior } from '../../types/settings';
+import type { Settings, Theme, StartupBehavior, ProviderConfig } from '../../types/settings';
 
 class SettingsManager {
   private settings: Settings | null = null;
@@ -14,6 +14,7 @@ class SettingsManager {
     anthropicModel: document.getElementById('anthropic-model') as HTMLSelectElement,
     theme: document.getElementById('theme') as HTMLSelectElement,
     startMinimized: document.getElementById('start-minimized') as HTMLInputElement,
+    allowedRoots: document.getElementById('allowed-roots') as HTMLTextAreaElement,
     saveButton: document.getElementById('save-settings') as HTMLButtonElement,
     saveStatus: document.getElementById('save-status') as HTMLSpanElement,
   };
@@ -39,13 +40,15 @@ class SettingsManager {
     if (!this.settings) return;
 
     // Update OpenAI settings
-    if (this.settings.ai_providers.openai) {
-      this.elements.openaiModel.value = this.settings.ai_providers.openai.model;
+    const openai = this.findProvider('openai');
+    if (openai) {
+      this.elements.openaiModel.value = openai.model;
     }
 
     // Update Anthropic settings
-    if (this.settings.ai_providers.anthropic) {
-      this.elements.anthropicModel.value = this.settings.ai_providers.anthropic.model;
+    const anthropic = this.findProvider('anthropic');
+    if (anthropic) {
+      this.elements.anthropicModel.value = anthropic.model;
     }
 
     // Update appearance settings
@@ -54,6 +57,34 @@ class SettingsManager {
     // Update behavior settings
     this.elements.startMinimized.checked = 
       this.settings.preferences.startup_behavior === 'minimized';
+
+    // Update tool settings
+    this.elements.allowedRoots.value = this.settings.tools.allowed_roots.join('\n');
+  }
+
+  private findProvider(id: string): ProviderConfig | undefined {
+    return this.settings?.ai_providers.providers.find((provider) => provider.id === id);
+  }
+
+  private updateProviderModel(id: string, model: string) {
+    if (!this.settings) return;
+
+    const existing = this.findProvider(id);
+    const updated: ProviderConfig = existing
+      ? { ...existing, model }
+      : { id, kind: id, model, temperature: 0.7, max_tokens: 2048 };
+
+    this.markUnsaved();
+    this.queueAutoSave({
+      ...this.settings,
+      ai_providers: {
+        ...this.settings.ai_providers,
+        providers: [
+          ...this.settings.ai_providers.providers.filter((provider) => provider.id !== id),
+          updated
+        ]
+      }
+    });
   }
 
   private setupEventListeners() {
@@ -77,37 +108,13 @@ class SettingsManager {
 
     // Model selection handling
     this.elements.openaiModel.addEventListener('change', (e) => {
-      if (!this.settings?.ai_providers.openai) return;
-      
       const target = e.target as HTMLSelectElement;
-      this.markUnsaved();
-      this.queueAutoSave({
-        ...this.settings,
-        ai_providers: {
-          ...this.settings.ai_providers,
-          openai: {
-            ...this.settings.ai_providers.openai,
-            model: target.value
-          }
-        }
-      });
+      this.updateProviderModel('openai', target.value);
     });
 
     this.elements.anthropicModel.addEventListener('change', (e) => {
-      if (!this.settings?.ai_providers.anthropic) return;
-      
       const target = e.target as HTMLSelectElement;
-      this.markUnsaved();
-      this.queueAutoSave({
-        ...this.settings,
-        ai_providers: {
-          ...this.settings.ai_providers,
-          anthropic: {
-            ...this.settings.ai_providers.anthropic,
-            model: target.value
-          }
-        }
-      });
+      this.updateProviderModel('anthropic', target.value);
     });
 
     // Theme handling
@@ -142,6 +149,25 @@ class SettingsManager {
       });
     });
 
+    // Allowed folders handling
+    this.elements.allowedRoots.addEventListener('change', (e) => {
+      if (!this.settings) return;
+
+      const target = e.target as HTMLTextAreaElement;
+      const roots = target.value
+        .split('\n')
+        .map((root) => root.trim())
+        .filter((root) => root.length > 0);
+      this.markUnsaved();
+      this.queueAutoSave({
+        ...this.settings,
+        tools: {
+          ...this.settings.tools,
+          allowed_roots: roots
+        }
+      });
+    });
+
     // Handle unsaved changes when closing
     window.addEventListener('beforeunload', (e) => {
       if (this.hasUnsavedChanges) {
@@ -16,6 +16,38 @@ interface ChatMessage {
   type: 'sent' | 'received'
 }
 
+/** Chat message as returned by the backend */
+interface BackendMessage {
+  role: string
+  content: string
+  timestamp: number
+}
+
+/** Chat session as returned by the backend */
+interface ChatSession {
+  id: string
+  title: string
+}
+
+/** Tool call waiting for the user's approval, as published by the backend */
+interface ApprovalRequest {
+  id: string
+  tool: string
+  description: string
+  arguments: unknown
+  key: string
+}
+
+/** The user's answer to an approval request */
+type ApprovalDecision = 'deny' | 'allow' | 'always_allow'
+
+/** Labels of the approval buttons and of the prompt once answered */
+const APPROVAL_CHOICES: { decision: ApprovalDecision, label: string, outcome: string }[] = [
+  { decision: 'allow', label: 'Allow', outcome: 'Allowed' },
+  { decision: 'always_allow', label: 'Always allow', outcome: 'Always allowed' },
+  { decision: 'deny', label: 'Deny', outcome: 'Denied' },
+]
+
 /** State interface for window position and animation */
 interface WindowState {
   /** Whether the window is currently visible */
@@ -30,6 +62,19 @@ interface WindowState {
   animationFrame: number | null
 }
 
+/**
+ * Extracts a readable message from a command error
+ *
+ * Command errors are serialized as `{ Variant: message }` objects.
+ */
+function formatCommandError(err: unknown): string {
+  if (err && typeof err === 'object') {
+    const [message] = Object.values(err as Record<string, unknown>)
+    if (typeof message === 'string') return message
+  }
+  return String(err)
+}
+
 /**
  * Creates a chat message element
  */
@@ -54,6 +99,45 @@ function createMessageElement(message: ChatMessage): HTMLDivElement {
   return messageEl
 }
 
+/**
+ * Creates a prompt asking the user to approve a tool call
+ *
+ * The buttons are replaced by the outcome once `respond` succeeds.
+ */
+function createApprovalElement(
+  request: ApprovalRequest,
+  respond: (decision: ApprovalDecision) => Promise<void>
+): HTMLDivElement {
+  const promptEl = document.createElement('div')
+  promptEl.className = 'chat-message received approval-prompt'
+
+  const contentEl = document.createElement('div')
+  contentEl.className = 'message-content'
+  contentEl.textContent = `${request.tool}: ${request.description}`
+
+  const actionsEl = document.createElement('div')
+  actionsEl.className = 'approval-actions'
+  for (const choice of APPROVAL_CHOICES) {
+    const button = document.createElement('button')
+    button.className = `approval-button ${choice.decision}`
+    button.textContent = choice.label
+    button.addEventListener('click', async () => {
+      actionsEl.querySelectorAll('button').forEach(el => { el.disabled = true })
+      try {
+        await respond(choice.decision)
+        actionsEl.textContent = choice.outcome
+      } catch (err) {
+        actionsEl.textContent = `Error: ${formatCommandError(err)}`
+      }
+    })
+    actionsEl.appendChild(button)
+  }
+
+  promptEl.appendChild(contentEl)
+  promptEl.appendChild(actionsEl)
+  return promptEl
+}
+
 /**
  * WindowManager class handles all window-related operations including
  * animations, positioning, and focus management.
@@ -70,6 +154,8 @@ class WindowManager {
 
   /** Chat messages array */
   private messages: ChatMessage[] = []
+  /** ID of the backend chat session, created with the first message */
+  private sessionId: string | null = null
   
   /** Duration of show/hide animations in milliseconds */
   private readonly ANIMATION_DURATION = 300
@@ -175,51 +261,78 @@ class WindowManager {
 
     const content = this.chatInput.value.trim()
     if (content) {
-      // Create new message
-      const message: ChatMessage = {
+      this.appendMessage({
         id: crypto.randomUUID(),
         content,
         timestamp: new Date(),
         type: 'sent'
-      }
-      
-      // Add message to state
-      this.messages.push(message)
-      
-      // Create and append message element
-      const messageEl = createMessageElement(message)
-      this.chatHistory.appendChild(messageEl)
+      })
       
-      // Clear input and scroll to bottom
+      // Clear input
       this.chatInput.value = ''
-      this.scrollToBottom()
       
-      // TODO: Handle message processing and response
-      this.simulateResponse()
+      void this.requestResponse(content)
     }
   }
 
   /**
-   * Temporary function to simulate a response
-   * This will be replaced with actual message processing
+   * Sends a message to the active AI provider and displays the reply
    */
-  private simulateResponse() {
-    setTimeout(() => {
-      const response: ChatMessage = {
+  private async requestResponse(content: string) {
+    try {
+      if (!this.sessionId) {
+        const session = await invoke<ChatSession>('create_session', { title: null })
+        this.sessionId = session.id
+      }
+
+      const reply = await invoke<BackendMessage>('send_message', {
+        sessionId: this.sessionId,
+        content
+      })
+
+      this.appendMessage({
         id: crypto.randomUUID(),
-        content: 'This is a simulated response.',
+        content: reply.content,
+        timestamp: new Date(reply.timestamp),
+        type: 'received'
+      })
+    } catch (err) {
+      console.error('Failed to send message:', err)
+      this.appendMessage({
+        id: crypto.randomUUID(),
+        content: `Error: ${formatCommandError(err)}`,
         timestamp: new Date(),
         type: 'received'
-      }
-      
-      this.messages.push(response)
-      
-      if (this.chatHistory) {
-        const messageEl = createMessageElement(response)
-        this.chatHistory.appendChild(messageEl)
-        this.scrollToBottom()
-      }
-    }, 1000)
+      })
+    }
+  }
+
+  /**
+   * Adds a message to the state and the chat history
+   */
+  private appendMessage(message: ChatMessage) {
+    this.messages.push(message)
+
+    if (this.chatHistory) {
+      const messageEl = createMessageElement(message)
+      this.chatHistory.appendChild(messageEl)
+      this.scrollToBottom()
+    }
+  }
+
+  /**
+   * Asks the user whether a tool call may go ahead
+   *
+   * The call waits in the backend until it is answered.
+   */
+  private showApprovalPrompt(request: ApprovalRequest) {
+    if (!this.chatHistory) return
+
+    const promptEl = createApprovalElement(request, async (decision) => {
+      await invoke('respond_to_tool_approval', { requestId: request.id, decision })
+    })
+    this.chatHistory.appendChild(promptEl)
+    this.scrollToBottom()
   }
 
   /**
@@ -298,6 +411,11 @@ class WindowManager {
       await this.mainWindow.listen('window_hidden', () => {
         void this.handleWindowHidden()
       })
+
+      // Tool calls waiting for the user's approval
+      await this.mainWindow.listen<ApprovalRequest>('tool_approval', (event) => {
+        this.showApprovalPrompt(event.payload)
+      })
     } catch (err) {
       console.error('Failed to setup event listeners:', err)
     }
@@ -1,6 +1,9 @@
 export interface Settings {
     preferences: AppPreferences;
     ai_providers: AIProviderSettings;
+    tools: ToolSettings;
+    mcp_servers: McpServerConfig[];
+    documents: DocumentSettings;
 }
 
 export interface AppPreferences {
@@ -12,20 +15,84 @@ export interface AppPreferences {
 }
 
 export interface AIProviderSettings {
-    openai?: OpenAIConfig;
-    anthropic?: AnthropicConfig;
+    active_provider?: string;
+    providers: ProviderConfig[];
+    /** Cheaper model of the active provider used to title sessions */
+    title_model?: string;
 }
 
-export interface OpenAIConfig {
-    model: string;
-    temperature: number;
-    max_tokens: number;
+/** Permissions of the tools models can call */
+export interface ToolSettings {
+    /** Absolute paths of folders file tools may read without asking */
+    allowed_roots: string[];
+    /** Keys of tool actions the user chose to always allow */
+    always_allow: string[];
+    shell: ShellSettings;
+}
+
+/** Configuration of the shell command tool */
+export interface ShellSettings {
+    /** Directory commands run in, defaulting to the first approved folder */
+    working_directory?: string;
+    timeout_secs: number;
+    /** Bytes kept of each of a command's output streams */
+    max_output_bytes: number;
+    /** Commands that run without asking, matched with any further arguments */
+    allowed_commands: string[];
+}
+
+/** A Model Context Protocol server started over stdio */
+export interface McpServerConfig {
+    /** Identifies the server and prefixes the names of its tools */
+    id: string;
+    name?: string;
+    command: string;
+    args: string[];
+    env: Record<string, string>;
+    enabled: boolean;
+}
+
+/** Folders indexed for chats to draw on */
+export interface DocumentSettings {
+    /** Whether chats are given passages from the indexed folders */
+    enabled: boolean;
+    /** Absolute paths of the indexed folders */
+    folders: string[];
+    /** Provider embedding the documents, defaulting to the active provider */
+    provider_id?: string;
+    /** Embedding model, defaulting to the provider's */
+    embedding_model?: string;
+    /** Passages added to a prompt */
+    top_k: number;
 }
 
-export interface AnthropicConfig {
+/** Configuration of one provider instance, resolved through the provider registry */
+export interface ProviderConfig {
+    id: string;
+    kind: string;
+    name?: string;
     model: string;
     temperature: number;
     max_tokens: number;
+    options?: Record<string, unknown>;
+}
+
+/** A provider kind as reported by the `list_providers` command */
+export interface ProviderDescriptor {
+    id: string;
+    display_name: string;
+    api_key: 'required' | 'optional' | 'unsupported';
+    multiple_instances: boolean;
+    default_model?: string;
+    default_embedding_model?: string;
+    config_schema: ConfigField[];
+}
+
+export interface ConfigField {
+    key: string;
+    label: string;
+    kind: 'string' | 'url' | 'string_list' | 'string_map';
+    required: boolean;
 }
 
 export type Theme = 'light' | 'dark' | 'system';
@@ -54,5 +121,22 @@ export const DEFAULT_SETTINGS: Settings = {
             }
         }
     },
-    ai_providers: {}
+    ai_providers: {
+        providers: []
+    },
+    tools: {
+        allowed_roots: [],
+        always_allow: [],
+        shell: {
+            timeout_secs: 30,
+            max_output_bytes: 65536,
+            allowed_commands: []
+        }
+    },
+    mcp_servers: [],
+    documents: {
+        enabled: true,
+        folders: [],
+        top_k: 4
+    }
 }; 
\ No newline at end of file